@@ -0,0 +1,525 @@
+use crate::{
+    error::{self, ResponseExt},
+    model, sse, ApiResponse, Datasource, EventStream, ResponseMeta,
+};
+use async_trait::async_trait;
+use futures::{stream, StreamExt, TryStreamExt};
+use serde::Serialize;
+use std::{collections::HashMap, sync, time};
+
+const DEFAULT_API_VERSION: &str = "2023-07-01-preview";
+
+pub struct AzureOpenAIApi {
+    http_client: sync::Arc<reqwest::Client>,
+    api_key: String,
+    endpoint: String,
+    api_version: String,
+    deployments: HashMap<String, String>,
+    headers: reqwest::header::HeaderMap,
+    timeout: Option<time::Duration>,
+}
+
+impl AzureOpenAIApi {
+    pub fn builder() -> AzureOpenAIApiBuilder {
+        AzureOpenAIApiBuilder::default()
+    }
+
+    fn request(&self, method: reqwest::Method, path: &str) -> reqwest::RequestBuilder {
+        self.http_client
+            .request(method, format!("{}/openai{}", &self.endpoint, path))
+            .query(&[("api-version", &self.api_version)])
+            .headers(self.headers.clone())
+            .header("api-key", &self.api_key)
+    }
+
+    fn deployment(&self, model: &impl Serialize) -> Result<&str, error::Error> {
+        let model = serde_json::to_value(model)?;
+        let model = model.as_str().unwrap_or_default();
+
+        self.deployments
+            .get(model)
+            .map(String::as_str)
+            .ok_or_else(|| error::Error::UnsupportedModel(format!("no deployment for {}", model)))
+    }
+}
+
+// Azure opens a stream with a chunk carrying only prompt filter results, with an
+// empty `object` and no choices, so those are dropped before decoding.
+fn events<T>(
+    response: reqwest::Response,
+    idle: Option<time::Duration>,
+) -> impl futures::Stream<Item = Result<T, error::Error>>
+where
+    T: serde::de::DeserializeOwned,
+{
+    sse::events(response, idle)
+        .try_filter(|event: &serde_json::Value| futures::future::ready(!is_prompt_filter(event)))
+        .and_then(|event| futures::future::ready(serde_json::from_value(event).map_err(Into::into)))
+}
+
+fn is_prompt_filter(event: &serde_json::Value) -> bool {
+    event["choices"]
+        .as_array()
+        .is_some_and(|choices| choices.is_empty())
+}
+
+#[derive(Default)]
+pub struct AzureOpenAIApiBuilder {
+    http_client: Option<sync::Arc<reqwest::Client>>,
+    api_key: Option<String>,
+    endpoint: Option<String>,
+    api_version: Option<String>,
+    deployments: HashMap<String, String>,
+    headers: Vec<(String, String)>,
+    user_agent: Option<String>,
+    connect_timeout: Option<time::Duration>,
+    timeout: Option<time::Duration>,
+}
+
+impl AzureOpenAIApiBuilder {
+    pub fn http_client(mut self, http_client: sync::Arc<reqwest::Client>) -> Self {
+        self.http_client = Some(http_client);
+        self
+    }
+
+    pub fn api_key(mut self, api_key: String) -> Self {
+        self.api_key = Some(api_key);
+        self
+    }
+
+    // The resource endpoint, e.g. https://{resource}.openai.azure.com
+    pub fn endpoint(mut self, endpoint: Option<String>) -> Self {
+        self.endpoint = endpoint;
+        self
+    }
+
+    pub fn api_version(mut self, api_version: Option<String>) -> Self {
+        self.api_version = api_version;
+        self
+    }
+
+    // Routes requests for `model` (its API id, e.g. "gpt-4") to the named deployment.
+    pub fn deployment(mut self, model: String, deployment: String) -> Self {
+        self.deployments.insert(model, deployment);
+        self
+    }
+
+    pub fn header(mut self, name: String, value: String) -> Self {
+        self.headers.push((name, value));
+        self
+    }
+
+    pub fn user_agent(mut self, user_agent: Option<String>) -> Self {
+        self.user_agent = user_agent;
+        self
+    }
+
+    pub fn connect_timeout(mut self, connect_timeout: Option<time::Duration>) -> Self {
+        self.connect_timeout = connect_timeout;
+        self
+    }
+
+    pub fn timeout(mut self, timeout: Option<time::Duration>) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    pub fn build(self) -> Result<AzureOpenAIApi, error::Error> {
+        let api_key = self
+            .api_key
+            .ok_or_else(|| error::Error::InvalidConfiguration(String::from("missing API key")))?;
+
+        let endpoint = self.endpoint.ok_or_else(|| {
+            error::Error::InvalidConfiguration(String::from("missing Azure endpoint"))
+        })?;
+
+        let http_client = match self.http_client {
+            Some(http_client) => http_client,
+            None => crate::build_http_client(self.user_agent, self.connect_timeout)?,
+        };
+
+        Ok(AzureOpenAIApi {
+            http_client,
+            api_key,
+            endpoint: crate::parse_base_url(&endpoint)?,
+            api_version: self
+                .api_version
+                .unwrap_or_else(|| String::from(DEFAULT_API_VERSION)),
+            deployments: self.deployments,
+            headers: crate::parse_headers(self.headers)?,
+            timeout: self.timeout,
+        })
+    }
+}
+
+#[async_trait]
+impl Datasource for AzureOpenAIApi {
+    async fn list_models(&self) -> Result<model::list_models::Response, error::Error> {
+        Err(error::Error::UnsupportedOperation(String::from(
+            "listing models on Azure OpenAI",
+        )))
+    }
+
+    async fn create_completion(
+        &self,
+        request: &model::create_completion::Request,
+    ) -> Result<model::create_completion::Response, error::Error> {
+        Ok(self.create_completion_with_meta(request).await?.body)
+    }
+
+    async fn create_completion_with_meta(
+        &self,
+        request: &model::create_completion::Request,
+    ) -> Result<ApiResponse<model::create_completion::Response>, error::Error> {
+        let path = format!(
+            "/deployments/{}/completions",
+            self.deployment(&request.model)?
+        );
+        let body = serde_json::to_string(&request)?;
+
+        let started = time::Instant::now();
+        let request = self
+            .request(reqwest::Method::POST, &path)
+            .header("Content-Type", "application/json")
+            .body(body);
+        let response = crate::send(request, self.timeout, false).await?;
+
+        ApiResponse::decode(response, started).await
+    }
+
+    async fn create_completion_stream(
+        &self,
+        request: &model::create_completion::Request,
+    ) -> Result<EventStream<model::create_completion::Choice>, error::Error> {
+        Ok(self.create_completion_stream_with_meta(request).await?.body)
+    }
+
+    async fn create_completion_stream_with_meta(
+        &self,
+        request: &model::create_completion::Request,
+    ) -> Result<ApiResponse<EventStream<model::create_completion::Choice>>, error::Error> {
+        let path = format!(
+            "/deployments/{}/completions",
+            self.deployment(&request.model)?
+        );
+        let mut body = serde_json::to_value(request)?;
+        body["stream"] = serde_json::Value::Bool(true);
+        let body = serde_json::to_string(&body)?;
+
+        let started = time::Instant::now();
+        let request = self
+            .request(reqwest::Method::POST, &path)
+            .header("Content-Type", "application/json")
+            .header("Accept", "text/event-stream")
+            .body(body);
+        let response = crate::send(request, self.timeout, true).await?;
+
+        let response = response.check().await?;
+        let meta = ResponseMeta::new(response.headers(), started.elapsed());
+
+        Ok(ApiResponse::new(
+            events(response, self.timeout)
+                .map_ok(|chunk: model::create_completion::Chunk| {
+                    stream::iter(chunk.choices.into_iter().map(Ok))
+                })
+                .try_flatten()
+                .boxed(),
+            meta,
+        ))
+    }
+
+    async fn create_chat(
+        &self,
+        request: &model::create_chat::Request,
+    ) -> Result<model::create_chat::Response, error::Error> {
+        Ok(self.create_chat_with_meta(request).await?.body)
+    }
+
+    async fn create_chat_with_meta(
+        &self,
+        request: &model::create_chat::Request,
+    ) -> Result<ApiResponse<model::create_chat::Response>, error::Error> {
+        let path = format!(
+            "/deployments/{}/chat/completions",
+            self.deployment(&request.model)?
+        );
+        let body = serde_json::to_string(&request)?;
+
+        let started = time::Instant::now();
+        let request = self
+            .request(reqwest::Method::POST, &path)
+            .header("Content-Type", "application/json")
+            .body(body);
+        let response = crate::send(request, self.timeout, false).await?;
+
+        ApiResponse::decode(response, started).await
+    }
+
+    async fn create_chat_stream(
+        &self,
+        request: &model::create_chat::Request,
+    ) -> Result<EventStream<model::create_chat::Chunk>, error::Error> {
+        Ok(self.create_chat_stream_with_meta(request).await?.body)
+    }
+
+    async fn create_chat_stream_with_meta(
+        &self,
+        request: &model::create_chat::Request,
+    ) -> Result<ApiResponse<EventStream<model::create_chat::Chunk>>, error::Error> {
+        let path = format!(
+            "/deployments/{}/chat/completions",
+            self.deployment(&request.model)?
+        );
+        let mut body = serde_json::to_value(request)?;
+        body["stream"] = serde_json::Value::Bool(true);
+        let body = serde_json::to_string(&body)?;
+
+        let started = time::Instant::now();
+        let request = self
+            .request(reqwest::Method::POST, &path)
+            .header("Content-Type", "application/json")
+            .header("Accept", "text/event-stream")
+            .body(body);
+        let response = crate::send(request, self.timeout, true).await?;
+
+        let response = response.check().await?;
+        let meta = ResponseMeta::new(response.headers(), started.elapsed());
+
+        Ok(ApiResponse::new(
+            events(response, self.timeout).boxed(),
+            meta,
+        ))
+    }
+
+    async fn create_image(
+        &self,
+        _request: &model::create_image::Request,
+    ) -> Result<model::create_image::Response, error::Error> {
+        Err(error::Error::UnsupportedOperation(String::from(
+            "image generation on Azure OpenAI",
+        )))
+    }
+
+    async fn create_edit(
+        &self,
+        _request: &model::create_edit::Request,
+    ) -> Result<model::create_edit::Response, error::Error> {
+        Err(error::Error::UnsupportedOperation(String::from(
+            "edits on Azure OpenAI",
+        )))
+    }
+
+    async fn list_files(&self) -> Result<model::list_files::Response, error::Error> {
+        Ok(self.list_files_with_meta().await?.body)
+    }
+
+    async fn list_files_with_meta(
+        &self,
+    ) -> Result<ApiResponse<model::list_files::Response>, error::Error> {
+        let started = time::Instant::now();
+        let request = self
+            .request(reqwest::Method::GET, "/files")
+            .header("Content-Type", "application/json");
+        let response = crate::send(request, self.timeout, false).await?;
+
+        ApiResponse::decode(response, started).await
+    }
+
+    async fn create_embedding(
+        &self,
+        request: &model::create_embedding::Request,
+    ) -> Result<model::create_embedding::Response, error::Error> {
+        Ok(self.create_embedding_with_meta(request).await?.body)
+    }
+
+    async fn create_embedding_with_meta(
+        &self,
+        request: &model::create_embedding::Request,
+    ) -> Result<ApiResponse<model::create_embedding::Response>, error::Error> {
+        let path = format!(
+            "/deployments/{}/embeddings",
+            self.deployment(&request.model)?
+        );
+        let body = serde_json::to_string(&request)?;
+
+        let started = time::Instant::now();
+        let request = self
+            .request(reqwest::Method::POST, &path)
+            .header("Content-Type", "application/json")
+            .body(body);
+        let response = crate::send(request, self.timeout, false).await?;
+
+        ApiResponse::decode(response, started).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use tokio::{
+        io::{AsyncReadExt, AsyncWriteExt},
+        net::TcpListener,
+    };
+
+    // Answers one request with `body` and hands back the request line and headers.
+    async fn serve(body: serde_json::Value) -> (String, tokio::task::JoinHandle<String>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+
+        let head = tokio::spawn(async move {
+            let (mut socket, _) = listener.accept().await.unwrap();
+            let mut request = vec![];
+
+            while !request.ends_with(b"\r\n\r\n") {
+                request.push(socket.read_u8().await.unwrap());
+            }
+
+            let body = body.to_string();
+            socket
+                .write_all(
+                    format!(
+                        "HTTP/1.1 200 OK\r\ncontent-type: application/json\r\ncontent-length: {}\r\nconnection: close\r\n\r\n{}",
+                        body.len(),
+                        body
+                    )
+                    .as_bytes(),
+                )
+                .await
+                .unwrap();
+
+            String::from_utf8(request).unwrap()
+        });
+
+        (format!("http://{}", address), head)
+    }
+
+    fn datasource(endpoint: String, api_version: Option<String>) -> AzureOpenAIApi {
+        AzureOpenAIApi::builder()
+            .http_client(sync::Arc::new(
+                reqwest::Client::builder().no_proxy().build().unwrap(),
+            ))
+            .api_key(String::from("secret"))
+            .endpoint(Some(endpoint))
+            .api_version(api_version)
+            .deployment(String::from("gpt-3.5-turbo"), String::from("chat"))
+            .deployment(String::from("text-davinci-003"), String::from("text"))
+            .build()
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn chat_goes_to_its_deployment() {
+        let (endpoint, head) = serve(json!({
+            "id": "c",
+            "object": "chat.completion",
+            "created": 1,
+            "model": "gpt-35-turbo",
+            "choices": [{
+                "index": 0,
+                "finish_reason": "content_filter",
+                "message": {"role": "assistant"},
+                "content_filter_results": {"hate": {"filtered": true, "severity": "high"}},
+            }],
+            "usage": {"prompt_tokens": 9, "completion_tokens": 0, "total_tokens": 9},
+        }))
+        .await;
+        let request = model::create_chat::Request::new(
+            model::create_chat::Model::Gpt3dot5Turbo,
+            vec![model::create_chat::Message {
+                role: model::create_chat::Role::User,
+                content: Some(String::from("Hi")),
+                name: None,
+                function_call: None,
+            }],
+        );
+
+        let response = datasource(endpoint, Some(String::from("2024-02-01")))
+            .create_chat(&request)
+            .await
+            .unwrap();
+        let head = head.await.unwrap();
+
+        assert!(head.starts_with(
+            "POST /openai/deployments/chat/chat/completions?api-version=2024-02-01 HTTP/1.1\r\n"
+        ));
+        assert!(head.contains("\r\napi-key: secret\r\n"));
+        assert!(!head.to_lowercase().contains("authorization"));
+        assert!(matches!(
+            response.choices[0].finish_reason,
+            Some(model::create_chat::FinishReason::ContentFilter)
+        ));
+        assert_eq!(response.choices[0].message.content, None);
+    }
+
+    #[tokio::test]
+    async fn completion_defaults_the_api_version() {
+        let (endpoint, head) = serve(json!({
+            "id": "c",
+            "object": "text_completion",
+            "created": 1,
+            "model": "text-davinci-003",
+            "choices": [{"text": "Hi", "index": 0, "logprobs": null, "finish_reason": "tool_calls"}],
+            "usage": {"prompt_tokens": 1, "completion_tokens": 1, "total_tokens": 2},
+        }))
+        .await;
+        let request = model::create_completion::Request::new(
+            model::create_completion::Model::TextDavinci003,
+            String::from("Hello"),
+        );
+
+        let response = datasource(endpoint, None)
+            .create_completion(&request)
+            .await
+            .unwrap();
+        let head = head.await.unwrap();
+
+        assert!(head.starts_with(&format!(
+            "POST /openai/deployments/text/completions?api-version={} HTTP/1.1\r\n",
+            DEFAULT_API_VERSION
+        )));
+        assert!(head.contains("\r\napi-key: secret\r\n"));
+        assert!(matches!(
+            response.choices[0].finish_reason,
+            Some(model::create_completion::FinishReason::Other)
+        ));
+    }
+
+    #[tokio::test]
+    async fn model_without_deployment_is_rejected() {
+        let request = model::create_chat::Request::new(model::create_chat::Model::Gpt4, vec![]);
+
+        let error = datasource(String::from("http://127.0.0.1:9"), None)
+            .create_chat(&request)
+            .await
+            .unwrap_err();
+
+        assert!(matches!(error, error::Error::UnsupportedModel(_)));
+    }
+
+    #[test]
+    fn prompt_filter_chunk_is_skipped() {
+        let filter = json!({
+            "id": "",
+            "object": "",
+            "created": 0,
+            "model": "",
+            "prompt_filter_results": [{"prompt_index": 0}],
+            "choices": [],
+        });
+        let chunk = json!({
+            "id": "c",
+            "object": "chat.completion.chunk",
+            "created": 1,
+            "model": "gpt-35-turbo",
+            "choices": [{"index": 0, "delta": {"content": "Hi"}, "finish_reason": null}],
+        });
+
+        assert!(is_prompt_filter(&filter));
+        assert!(!is_prompt_filter(&chunk));
+
+        let chunk: model::create_chat::Chunk = serde_json::from_value(chunk).unwrap();
+
+        assert_eq!(chunk.model, "gpt-35-turbo");
+    }
+}
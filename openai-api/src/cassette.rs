@@ -0,0 +1,368 @@
+use crate::error;
+use serde::{Deserialize, Serialize};
+use std::{collections::BTreeMap, fs, path, str::FromStr, sync};
+
+// Headers that describe the transfer rather than the payload. Bodies are stored decoded,
+// so replaying them would make the response undecodable.
+const TRANSFER_HEADERS: [&str; 3] = ["content-encoding", "content-length", "transfer-encoding"];
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Matching {
+    #[default]
+    Body,
+    Model,
+}
+
+impl FromStr for Matching {
+    type Err = error::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "body" => Ok(Self::Body),
+            "model" => Ok(Self::Model),
+            _ => Err(error::Error::InvalidConfiguration(format!(
+                "unsupported cassette matching: {}",
+                s
+            ))),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub(crate) struct Interaction {
+    request: RecordedRequest,
+    response: RecordedResponse,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+struct RecordedRequest {
+    method: String,
+    path: String,
+    body: Option<serde_json::Value>,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+struct RecordedResponse {
+    status: u16,
+    headers: BTreeMap<String, String>,
+    body: String,
+}
+
+impl RecordedRequest {
+    fn new(method: &reqwest::Method, path: &str, body: Option<&str>) -> Self {
+        Self {
+            method: method.to_string(),
+            path: path.to_string(),
+            body: body.and_then(|body| serde_json::from_str(body).ok()),
+        }
+    }
+
+    fn matches(&self, other: &Self, matching: Matching) -> bool {
+        if self.method != other.method || self.path != other.path {
+            return false;
+        }
+
+        match matching {
+            Matching::Body => self.body == other.body,
+            Matching::Model => ["model", "stream"]
+                .into_iter()
+                .all(|field| self.field(field) == other.field(field)),
+        }
+    }
+
+    fn field(&self, name: &str) -> Option<&serde_json::Value> {
+        self.body.as_ref().and_then(|body| body.get(name))
+    }
+}
+
+impl RecordedResponse {
+    fn into_response(self) -> Result<reqwest::Response, error::Error> {
+        let mut builder = http::Response::builder().status(self.status);
+
+        for (name, value) in self.headers {
+            builder = builder.header(name, value);
+        }
+
+        let response = builder
+            .body(self.body)
+            .map_err(|error| error::Error::Cassette(error.to_string()))?;
+
+        Ok(reqwest::Response::from(response))
+    }
+}
+
+pub(crate) enum Cassette {
+    Record {
+        directory: path::PathBuf,
+        recorded: sync::Mutex<usize>,
+    },
+    Replay {
+        matching: Matching,
+        interactions: sync::Mutex<Vec<(Interaction, bool)>>,
+    },
+}
+
+impl Cassette {
+    pub(crate) fn record(directory: path::PathBuf) -> Result<Self, error::Error> {
+        fs::create_dir_all(&directory).map_err(|error| cassette_error(&directory, error))?;
+        let recorded = entries(&directory)?.len();
+
+        Ok(Self::Record {
+            directory,
+            recorded: sync::Mutex::new(recorded),
+        })
+    }
+
+    pub(crate) fn replay(
+        directory: path::PathBuf,
+        matching: Matching,
+    ) -> Result<Self, error::Error> {
+        let interactions = entries(&directory)?
+            .into_iter()
+            .map(|entry| {
+                let content =
+                    fs::read_to_string(&entry).map_err(|error| cassette_error(&entry, error))?;
+                let interaction: Interaction = serde_json::from_str(&content)?;
+
+                Ok((interaction, false))
+            })
+            .collect::<Result<_, error::Error>>()?;
+
+        Ok(Self::Replay {
+            matching,
+            interactions: sync::Mutex::new(interactions),
+        })
+    }
+
+    // Serves the first unused matching interaction so that repeated identical requests
+    // replay in recorded order, then keeps serving the last one once they run out.
+    pub(crate) fn replayed(
+        &self,
+        method: &reqwest::Method,
+        path: &str,
+        body: Option<&str>,
+    ) -> Option<Result<reqwest::Response, error::Error>> {
+        let Self::Replay {
+            matching,
+            interactions,
+        } = self
+        else {
+            return None;
+        };
+
+        let request = RecordedRequest::new(method, path, body);
+        let mut interactions = interactions
+            .lock()
+            .unwrap_or_else(|error| error.into_inner());
+        let mut last = None;
+
+        for (interaction, used) in interactions
+            .iter_mut()
+            .filter(|(interaction, _)| interaction.request.matches(&request, *matching))
+        {
+            if !*used {
+                *used = true;
+                return Some(interaction.response.clone().into_response());
+            }
+
+            last = Some(interaction.response.clone());
+        }
+
+        Some(match last {
+            Some(response) => response.into_response(),
+            None => Err(error::Error::Cassette(format!(
+                "no recorded interaction for {} {}",
+                method, path
+            ))),
+        })
+    }
+
+    // Reads the whole body before handing it back, so recorded streams arrive at once.
+    pub(crate) async fn recorded(
+        &self,
+        method: &reqwest::Method,
+        path: &str,
+        body: Option<&str>,
+        response: reqwest::Response,
+    ) -> Result<reqwest::Response, error::Error> {
+        let Self::Record {
+            directory,
+            recorded,
+        } = self
+        else {
+            return Ok(response);
+        };
+
+        let status = response.status().as_u16();
+        let headers = response
+            .headers()
+            .iter()
+            .filter(|(name, _)| !TRANSFER_HEADERS.contains(&name.as_str()))
+            .filter_map(|(name, value)| {
+                value
+                    .to_str()
+                    .ok()
+                    .map(|value| (name.to_string(), value.to_string()))
+            })
+            .collect();
+        let bytes = response.bytes().await?;
+
+        let interaction = Interaction {
+            request: RecordedRequest::new(method, path, body),
+            response: RecordedResponse {
+                status,
+                headers,
+                body: String::from_utf8_lossy(&bytes).into_owned(),
+            },
+        };
+
+        let entry = {
+            let mut recorded = recorded.lock().unwrap_or_else(|error| error.into_inner());
+            *recorded += 1;
+
+            directory.join(format!(
+                "{:04}-{}.json",
+                recorded,
+                path.trim_start_matches('/').replace('/', "-")
+            ))
+        };
+
+        fs::write(&entry, serde_json::to_string_pretty(&interaction)?)
+            .map_err(|error| cassette_error(&entry, error))?;
+
+        interaction.response.into_response()
+    }
+}
+
+fn entries(directory: &path::Path) -> Result<Vec<path::PathBuf>, error::Error> {
+    let mut entries = fs::read_dir(directory)
+        .map_err(|error| cassette_error(directory, error))?
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|entry| {
+            entry
+                .extension()
+                .is_some_and(|extension| extension == "json")
+        })
+        .collect::<Vec<_>>();
+
+    entries.sort();
+
+    Ok(entries)
+}
+
+fn cassette_error(path: &path::Path, error: std::io::Error) -> error::Error {
+    error::Error::Cassette(format!("{}: {}", path.display(), error))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn request(path: &str, body: serde_json::Value) -> RecordedRequest {
+        RecordedRequest::new(&reqwest::Method::POST, path, Some(&body.to_string()))
+    }
+
+    fn interaction(body: serde_json::Value, response: &str) -> (Interaction, bool) {
+        let interaction = Interaction {
+            request: request("/chat/completions", body),
+            response: RecordedResponse {
+                status: 200,
+                headers: BTreeMap::new(),
+                body: response.to_string(),
+            },
+        };
+
+        (interaction, false)
+    }
+
+    async fn replay(cassette: &Cassette, body: serde_json::Value) -> Result<String, error::Error> {
+        let response = cassette
+            .replayed(
+                &reqwest::Method::POST,
+                "/chat/completions",
+                Some(&body.to_string()),
+            )
+            .unwrap()?;
+
+        Ok(response.text().await?)
+    }
+
+    #[test]
+    fn body_matching_compares_whole_bodies() {
+        let recorded = request("/completions", json!({"model": "m", "prompt": "a"}));
+        let same = request("/completions", json!({"prompt": "a", "model": "m"}));
+        let other = request("/completions", json!({"model": "m", "prompt": "b"}));
+
+        assert!(recorded.matches(&same, Matching::Body));
+        assert!(!recorded.matches(&other, Matching::Body));
+    }
+
+    #[test]
+    fn model_matching_ignores_other_fields() {
+        let recorded = request("/completions", json!({"model": "m", "prompt": "a"}));
+        let other = request("/completions", json!({"model": "m", "prompt": "b"}));
+        let streamed = request("/completions", json!({"model": "m", "stream": true}));
+        let model = request("/completions", json!({"model": "n", "prompt": "a"}));
+
+        assert!(recorded.matches(&other, Matching::Model));
+        assert!(!recorded.matches(&streamed, Matching::Model));
+        assert!(!recorded.matches(&model, Matching::Model));
+    }
+
+    #[test]
+    fn method_and_path_must_match() {
+        let recorded = request("/completions", json!({"model": "m"}));
+        let path = request("/chat/completions", json!({"model": "m"}));
+        let method = RecordedRequest::new(&reqwest::Method::GET, "/completions", None);
+
+        assert!(!recorded.matches(&path, Matching::Model));
+        assert!(!recorded.matches(&method, Matching::Model));
+    }
+
+    #[tokio::test]
+    async fn identical_requests_replay_in_recorded_order() {
+        let body = json!({"model": "m"});
+        let cassette = Cassette::Replay {
+            matching: Matching::Body,
+            interactions: sync::Mutex::new(vec![
+                interaction(body.clone(), "first"),
+                interaction(json!({"model": "n"}), "other"),
+                interaction(body.clone(), "second"),
+            ]),
+        };
+
+        assert_eq!(replay(&cassette, body.clone()).await.unwrap(), "first");
+        assert_eq!(replay(&cassette, body.clone()).await.unwrap(), "second");
+        assert_eq!(replay(&cassette, body.clone()).await.unwrap(), "second");
+        assert_eq!(
+            replay(&cassette, json!({"model": "n"})).await.unwrap(),
+            "other"
+        );
+    }
+
+    #[tokio::test]
+    async fn unmatched_request_is_an_error() {
+        let cassette = Cassette::Replay {
+            matching: Matching::Body,
+            interactions: sync::Mutex::new(vec![interaction(json!({"model": "m"}), "first")]),
+        };
+
+        assert!(matches!(
+            replay(&cassette, json!({"model": "x"})).await,
+            Err(error::Error::Cassette(_))
+        ));
+    }
+
+    #[test]
+    fn recording_does_not_replay() {
+        let cassette = Cassette::Record {
+            directory: path::PathBuf::new(),
+            recorded: sync::Mutex::new(0),
+        };
+
+        assert!(cassette
+            .replayed(&reqwest::Method::GET, "/models", None)
+            .is_none());
+    }
+}
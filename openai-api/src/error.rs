@@ -1,3 +1,6 @@
+use async_trait::async_trait;
+use serde::Deserialize;
+use std::{fmt, time};
 use thiserror::Error;
 
 #[derive(Debug, Error)]
@@ -5,12 +8,30 @@ pub enum Error {
     #[error("Invalid response: {0}")]
     InvalidResponse(String),
 
-    #[error("Invalid HTTP response: {0}")]
-    InvalidHttpResponse(String),
+    #[error("Authentication failed: {0}")]
+    Authentication(Box<HttpError>),
+
+    #[error("Rate limited: {0}")]
+    RateLimit(Box<HttpError>),
+
+    #[error("Quota exceeded: {0}")]
+    QuotaExceeded(Box<HttpError>),
+
+    #[error("Context length exceeded: {0}")]
+    ContextLengthExceeded(Box<HttpError>),
+
+    #[error("Invalid request: {0}")]
+    InvalidRequest(Box<HttpError>),
+
+    #[error("Server error: {0}")]
+    Server(Box<HttpError>),
 
     #[error("Unsupported model: {0}")]
     UnsupportedModel(String),
 
+    #[error("Unsupported operation: {0}")]
+    UnsupportedOperation(String),
+
     #[error("Unsupported role: {0}")]
     UnsupportedRole(String),
 
@@ -20,15 +41,259 @@ pub enum Error {
     #[error("Unsupported response format: {0}")]
     UnsupportedResponseFormat(String),
 
+    #[error("Unsupported encoding: {0}")]
+    UnsupportedEncoding(String),
+
+    #[error("Unknown function: {0}")]
+    UnknownFunction(String),
+
+    #[error("Invalid schema: {0}")]
+    InvalidSchema(String),
+
+    #[error("Invalid argument: {0}")]
+    InvalidArgument(String),
+
+    #[error("Invalid configuration: {0}")]
+    InvalidConfiguration(String),
+
+    #[error("Tokenizer: {0}")]
+    Tokenizer(String),
+
+    #[error("Cassette: {0}")]
+    Cassette(String),
+
     #[error("Json Serialization: {0}")]
     JsonSerialization(String),
 
+    #[error("Timeout: {0}")]
+    Timeout(String),
+
+    #[error("Connection: {0}")]
+    Connection(String),
+
     #[error("Reqwest: {0}")]
     Reqwest(String),
 }
 
+#[derive(Clone, Debug)]
+pub struct HttpError {
+    pub status: u16,
+    pub api_error: Option<ApiError>,
+    pub request_id: Option<String>,
+    pub retry_after: Option<time::Duration>,
+    pub body: String,
+}
+
+impl fmt::Display for HttpError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.api_error {
+            Some(api_error) => write!(f, "HTTP {}: {}", self.status, api_error.message)?,
+            None if self.body.is_empty() => write!(f, "HTTP {}", self.status)?,
+            None => write!(f, "HTTP {}: {}", self.status, self.body)?,
+        }
+
+        match &self.request_id {
+            Some(request_id) => write!(f, " (request id {})", request_id),
+            None => Ok(()),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct ApiError {
+    pub message: String,
+
+    #[serde(rename = "type")]
+    pub r#type: Option<String>,
+
+    pub param: Option<String>,
+    pub code: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct ErrorBody {
+    error: ApiError,
+}
+
+impl Error {
+    pub fn is_retryable(&self) -> bool {
+        match self {
+            Error::RateLimit(_) | Error::Server(_) => true,
+            Error::InvalidRequest(error) => matches!(error.status, 408 | 409),
+            Error::Timeout(_) | Error::Connection(_) => true,
+            _ => false,
+        }
+    }
+
+    // A POST that timed out may still have been processed, so only failures to
+    // connect, which never reached the server, are retried for it.
+    pub fn is_retryable_post(&self) -> bool {
+        match self {
+            Error::Timeout(_) => false,
+            _ => self.is_retryable(),
+        }
+    }
+
+    pub fn http_error(&self) -> Option<&HttpError> {
+        match self {
+            Error::Authentication(error)
+            | Error::RateLimit(error)
+            | Error::QuotaExceeded(error)
+            | Error::ContextLengthExceeded(error)
+            | Error::InvalidRequest(error)
+            | Error::Server(error) => Some(error),
+            _ => None,
+        }
+    }
+
+    pub fn status(&self) -> Option<u16> {
+        self.http_error().map(|error| error.status)
+    }
+
+    pub fn api_error(&self) -> Option<&ApiError> {
+        self.http_error().and_then(|error| error.api_error.as_ref())
+    }
+
+    pub fn request_id(&self) -> Option<&str> {
+        self.http_error()
+            .and_then(|error| error.request_id.as_deref())
+    }
+
+    pub fn retry_after(&self) -> Option<time::Duration> {
+        self.http_error().and_then(|error| error.retry_after)
+    }
+
+    async fn from_response(response: reqwest::Response) -> Self {
+        let status = response.status().as_u16();
+        let retry_after = retry_after(response.headers());
+        let request_id = response
+            .headers()
+            .get("x-request-id")
+            .and_then(|value| value.to_str().ok())
+            .map(String::from);
+        let body = response.text().await.unwrap_or_default();
+        let api_error = serde_json::from_str::<ErrorBody>(&body)
+            .ok()
+            .map(|body| body.error);
+
+        let code = api_error
+            .as_ref()
+            .and_then(|api_error| api_error.code.clone());
+        let error = Box::new(HttpError {
+            status,
+            api_error,
+            request_id,
+            retry_after,
+            body,
+        });
+
+        match (status, code.as_deref()) {
+            (_, Some("context_length_exceeded")) => Error::ContextLengthExceeded(error),
+            (429, Some("insufficient_quota")) => Error::QuotaExceeded(error),
+            (401 | 403, _) => Error::Authentication(error),
+            (429, _) => Error::RateLimit(error),
+            (500..=599, _) => Error::Server(error),
+            _ => Error::InvalidRequest(error),
+        }
+    }
+}
+
+// Prefers the server's explicit hints, falling back to the longest of the rate-limit reset windows.
+fn retry_after(headers: &reqwest::header::HeaderMap) -> Option<time::Duration> {
+    let header = |name: &str| headers.get(name).and_then(|value| value.to_str().ok());
+
+    if let Some(millis) = header("retry-after-ms").and_then(|value| value.parse::<f64>().ok()) {
+        return seconds(millis / 1000.0);
+    }
+
+    if let Some(value) = header("retry-after") {
+        if let Ok(value) = value.parse::<f64>() {
+            return seconds(value);
+        }
+
+        if let Ok(date) = chrono::DateTime::parse_from_rfc2822(value) {
+            return (date.with_timezone(&chrono::Utc) - chrono::Utc::now())
+                .to_std()
+                .ok();
+        }
+    }
+
+    ["x-ratelimit-reset-requests", "x-ratelimit-reset-tokens"]
+        .into_iter()
+        .filter_map(|name| header(name).and_then(parse_reset))
+        .max()
+}
+
+// Parses reset windows such as "20ms", "1s", "6m0s" or "1h2m3.5s".
+pub(crate) fn parse_reset(value: &str) -> Option<time::Duration> {
+    let mut total = 0.0;
+    let mut number = String::new();
+    let mut chars = value.trim().chars().peekable();
+
+    while let Some(c) = chars.next() {
+        if c.is_ascii_digit() || c == '.' {
+            number.push(c);
+            continue;
+        }
+
+        let unit = match (c, chars.peek()) {
+            ('m', Some('s')) => {
+                chars.next();
+                0.001
+            }
+            ('h', _) => 3600.0,
+            ('m', _) => 60.0,
+            ('s', _) => 1.0,
+            _ => return None,
+        };
+
+        total += number.parse::<f64>().ok()? * unit;
+        number.clear();
+    }
+
+    match number.is_empty() {
+        true => seconds(total),
+        false => None,
+    }
+}
+
+// Headers are untrusted input: NaN, infinite and negative values are dropped rather than
+// panicking in Duration, and anything past a day is capped at a day.
+pub(crate) fn seconds(value: f64) -> Option<time::Duration> {
+    const LONGEST: f64 = 86_400.0;
+
+    match value.is_finite() && value >= 0.0 {
+        true => time::Duration::try_from_secs_f64(value.min(LONGEST)).ok(),
+        false => None,
+    }
+}
+
+#[async_trait]
+pub(crate) trait ResponseExt {
+    async fn check(self) -> Result<reqwest::Response, Error>;
+}
+
+#[async_trait]
+impl ResponseExt for reqwest::Response {
+    async fn check(self) -> Result<reqwest::Response, Error> {
+        match self.status().is_success() {
+            true => Ok(self),
+            false => Err(Error::from_response(self).await),
+        }
+    }
+}
+
 impl std::convert::From<reqwest::Error> for Error {
     fn from(err: reqwest::Error) -> Self {
+        // Checked first, so that connect timeouts count as failures to connect.
+        if err.is_connect() {
+            return Error::Connection(err.to_string());
+        }
+
+        if err.is_timeout() {
+            return Error::Timeout(err.to_string());
+        }
+
         Error::Reqwest(err.to_string())
     }
 }
@@ -38,3 +303,87 @@ impl std::convert::From<serde_json::Error> for Error {
         Error::JsonSerialization(err.to_string())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use reqwest::header::{HeaderMap, HeaderValue};
+
+    fn headers(pairs: &[(&'static str, &str)]) -> HeaderMap {
+        pairs
+            .iter()
+            .map(|(name, value)| (*name, HeaderValue::from_str(value).unwrap()))
+            .map(|(name, value)| (reqwest::header::HeaderName::from_static(name), value))
+            .collect()
+    }
+
+    #[test]
+    fn parse_reset_units() {
+        let parse = |value| parse_reset(value).map(|duration| duration.as_secs_f64());
+
+        assert_eq!(parse("20ms"), Some(0.02));
+        assert_eq!(parse("1s"), Some(1.0));
+        assert_eq!(parse("6m0s"), Some(360.0));
+        assert_eq!(parse("1h2m3.5s"), Some(3723.5));
+        assert_eq!(parse(" 2s "), Some(2.0));
+    }
+
+    #[test]
+    fn parse_reset_rejects_garbage() {
+        assert_eq!(parse_reset("12"), None);
+        assert_eq!(parse_reset("1d"), None);
+        assert_eq!(parse_reset("1.2.3s"), None);
+    }
+
+    #[test]
+    fn retry_after_prefers_milliseconds() {
+        let headers = headers(&[("retry-after-ms", "250"), ("retry-after", "9")]);
+
+        assert_eq!(
+            retry_after(&headers),
+            Some(time::Duration::from_millis(250))
+        );
+    }
+
+    #[test]
+    fn retry_after_seconds_and_dates() {
+        let seconds = headers(&[("retry-after", "2")]);
+
+        assert_eq!(retry_after(&seconds), Some(time::Duration::from_secs(2)));
+
+        let past = headers(&[("retry-after", "Wed, 21 Oct 2015 07:28:00 GMT")]);
+
+        assert_eq!(retry_after(&past), None);
+
+        let future = (chrono::Utc::now() + chrono::Duration::seconds(30)).to_rfc2822();
+        let future = retry_after(&headers(&[("retry-after", &future)])).unwrap();
+
+        assert!(future > time::Duration::from_secs(20));
+        assert!(future <= time::Duration::from_secs(30));
+    }
+
+    #[test]
+    fn retry_after_falls_back_to_longest_reset() {
+        let headers = headers(&[
+            ("x-ratelimit-reset-requests", "120ms"),
+            ("x-ratelimit-reset-tokens", "6m0s"),
+        ]);
+
+        assert_eq!(retry_after(&headers), Some(time::Duration::from_secs(360)));
+        assert_eq!(retry_after(&HeaderMap::new()), None);
+    }
+
+    #[test]
+    fn retry_after_survives_hostile_values() {
+        let day = Some(time::Duration::from_secs(86_400));
+
+        assert_eq!(retry_after(&headers(&[("retry-after", "1e30")])), day);
+        assert_eq!(retry_after(&headers(&[("retry-after-ms", "1e300")])), day);
+        assert_eq!(retry_after(&headers(&[("retry-after", "inf")])), None);
+        assert_eq!(retry_after(&headers(&[("retry-after", "NaN")])), None);
+        assert_eq!(retry_after(&headers(&[("retry-after", "-5")])), None);
+        assert_eq!(retry_after(&headers(&[("retry-after-ms", "-1")])), None);
+        assert_eq!(parse_reset(&format!("{}s", "9".repeat(400))), None);
+        assert_eq!(parse_reset(&format!("{}h", "9".repeat(30))), day);
+    }
+}
@@ -1,8 +1,30 @@
 use async_trait::async_trait;
-use std::sync;
+use error::ResponseExt;
+use futures::{stream, StreamExt, TryStreamExt};
+use std::{path, pin, sync, time};
 
-mod error;
+mod azure;
+mod cassette;
+pub mod error;
+mod limiter;
+mod meta;
 pub mod model;
+mod registry;
+mod retry;
+mod sse;
+#[cfg(feature = "testing")]
+pub mod testing;
+#[cfg(feature = "tokenizer")]
+pub mod tokenizer;
+
+pub use azure::{AzureOpenAIApi, AzureOpenAIApiBuilder};
+pub use cassette::Matching;
+pub use limiter::{RateLimited, RateLimits};
+pub use meta::{ApiResponse, RateLimit, ResponseMeta};
+pub use registry::FunctionRegistry;
+pub use retry::{Retry, RetryPolicy};
+
+pub type EventStream<T> = pin::Pin<Box<dyn futures::Stream<Item = Result<T, error::Error>> + Send>>;
 
 #[async_trait]
 pub trait Datasource {
@@ -11,10 +33,18 @@ pub trait Datasource {
         &self,
         request: &model::create_completion::Request,
     ) -> Result<model::create_completion::Response, error::Error>;
+    async fn create_completion_stream(
+        &self,
+        request: &model::create_completion::Request,
+    ) -> Result<EventStream<model::create_completion::Choice>, error::Error>;
     async fn create_chat(
         &self,
         request: &model::create_chat::Request,
     ) -> Result<model::create_chat::Response, error::Error>;
+    async fn create_chat_stream(
+        &self,
+        request: &model::create_chat::Request,
+    ) -> Result<EventStream<model::create_chat::Chunk>, error::Error>;
     async fn create_image(
         &self,
         request: &model::create_image::Request,
@@ -28,12 +58,107 @@ pub trait Datasource {
         &self,
         request: &model::create_embedding::Request,
     ) -> Result<model::create_embedding::Response, error::Error>;
+
+    async fn list_models_with_meta(
+        &self,
+    ) -> Result<ApiResponse<model::list_models::Response>, error::Error> {
+        let started = time::Instant::now();
+        let body = self.list_models().await?;
+
+        Ok(ApiResponse::new(body, ResponseMeta::elapsed(started)))
+    }
+
+    async fn create_completion_with_meta(
+        &self,
+        request: &model::create_completion::Request,
+    ) -> Result<ApiResponse<model::create_completion::Response>, error::Error> {
+        let started = time::Instant::now();
+        let body = self.create_completion(request).await?;
+
+        Ok(ApiResponse::new(body, ResponseMeta::elapsed(started)))
+    }
+
+    async fn create_completion_stream_with_meta(
+        &self,
+        request: &model::create_completion::Request,
+    ) -> Result<ApiResponse<EventStream<model::create_completion::Choice>>, error::Error> {
+        let started = time::Instant::now();
+        let body = self.create_completion_stream(request).await?;
+
+        Ok(ApiResponse::new(body, ResponseMeta::elapsed(started)))
+    }
+
+    async fn create_chat_with_meta(
+        &self,
+        request: &model::create_chat::Request,
+    ) -> Result<ApiResponse<model::create_chat::Response>, error::Error> {
+        let started = time::Instant::now();
+        let body = self.create_chat(request).await?;
+
+        Ok(ApiResponse::new(body, ResponseMeta::elapsed(started)))
+    }
+
+    async fn create_chat_stream_with_meta(
+        &self,
+        request: &model::create_chat::Request,
+    ) -> Result<ApiResponse<EventStream<model::create_chat::Chunk>>, error::Error> {
+        let started = time::Instant::now();
+        let body = self.create_chat_stream(request).await?;
+
+        Ok(ApiResponse::new(body, ResponseMeta::elapsed(started)))
+    }
+
+    async fn create_image_with_meta(
+        &self,
+        request: &model::create_image::Request,
+    ) -> Result<ApiResponse<model::create_image::Response>, error::Error> {
+        let started = time::Instant::now();
+        let body = self.create_image(request).await?;
+
+        Ok(ApiResponse::new(body, ResponseMeta::elapsed(started)))
+    }
+
+    async fn create_edit_with_meta(
+        &self,
+        request: &model::create_edit::Request,
+    ) -> Result<ApiResponse<model::create_edit::Response>, error::Error> {
+        let started = time::Instant::now();
+        let body = self.create_edit(request).await?;
+
+        Ok(ApiResponse::new(body, ResponseMeta::elapsed(started)))
+    }
+
+    async fn list_files_with_meta(
+        &self,
+    ) -> Result<ApiResponse<model::list_files::Response>, error::Error> {
+        let started = time::Instant::now();
+        let body = self.list_files().await?;
+
+        Ok(ApiResponse::new(body, ResponseMeta::elapsed(started)))
+    }
+
+    async fn create_embedding_with_meta(
+        &self,
+        request: &model::create_embedding::Request,
+    ) -> Result<ApiResponse<model::create_embedding::Response>, error::Error> {
+        let started = time::Instant::now();
+        let body = self.create_embedding(request).await?;
+
+        Ok(ApiResponse::new(body, ResponseMeta::elapsed(started)))
+    }
 }
 
+// Like OPENAI_BASE_URL elsewhere, the base URL includes the API version, so compatible
+// servers such as http://localhost:8000/v1 work as given.
+const DEFAULT_BASE_URL: &str = "https://api.openai.com/v1";
+
 pub struct OpenAIApi {
     http_client: sync::Arc<reqwest::Client>,
     api_key: String,
     base_url: String,
+    headers: reqwest::header::HeaderMap,
+    cassette: Option<cassette::Cassette>,
+    timeout: Option<time::Duration>,
 }
 
 impl OpenAIApi {
@@ -41,168 +166,549 @@ impl OpenAIApi {
         Self {
             http_client,
             api_key,
-            base_url: String::from("https://api.openai.com"),
+            base_url: String::from(DEFAULT_BASE_URL),
+            headers: reqwest::header::HeaderMap::new(),
+            cassette: None,
+            timeout: None,
+        }
+    }
+
+    pub fn builder() -> OpenAIApiBuilder {
+        OpenAIApiBuilder::default()
+    }
+
+    fn request(&self, method: reqwest::Method, path: &str) -> reqwest::RequestBuilder {
+        self.http_client
+            .request(method, format!("{}{}", &self.base_url, path))
+            .headers(self.headers.clone())
+            .bearer_auth(&self.api_key)
+    }
+
+    async fn send(
+        &self,
+        method: reqwest::Method,
+        path: &str,
+        body: Option<String>,
+        stream: bool,
+    ) -> Result<reqwest::Response, error::Error> {
+        if let Some(replayed) = self
+            .cassette
+            .as_ref()
+            .and_then(|cassette| cassette.replayed(&method, path, body.as_deref()))
+        {
+            return replayed;
+        }
+
+        let mut request = self.request(method.clone(), path);
+
+        if let Some(body) = &body {
+            request = request
+                .header("Content-Type", "application/json")
+                .body(body.clone());
+        }
+
+        if stream {
+            request = request.header("Accept", "text/event-stream");
+        }
+
+        let response = send(request, self.timeout, stream).await?;
+
+        match &self.cassette {
+            Some(cassette) => {
+                cassette
+                    .recorded(&method, path, body.as_deref(), response)
+                    .await
+            }
+            None => Ok(response),
         }
     }
 }
 
+#[derive(Default)]
+pub struct OpenAIApiBuilder {
+    http_client: Option<sync::Arc<reqwest::Client>>,
+    api_key: Option<String>,
+    base_url: Option<String>,
+    organization: Option<String>,
+    project: Option<String>,
+    headers: Vec<(String, String)>,
+    user_agent: Option<String>,
+    connect_timeout: Option<time::Duration>,
+    timeout: Option<time::Duration>,
+    record: Option<path::PathBuf>,
+    replay: Option<path::PathBuf>,
+    matching: cassette::Matching,
+}
+
+impl OpenAIApiBuilder {
+    // A pre-built client is used as is, so the user agent and connect timeout below are ignored.
+    pub fn http_client(mut self, http_client: sync::Arc<reqwest::Client>) -> Self {
+        self.http_client = Some(http_client);
+        self
+    }
+
+    pub fn api_key(mut self, api_key: String) -> Self {
+        self.api_key = Some(api_key);
+        self
+    }
+
+    pub fn base_url(mut self, base_url: Option<String>) -> Self {
+        self.base_url = base_url;
+        self
+    }
+
+    pub fn organization(mut self, organization: Option<String>) -> Self {
+        self.organization = organization;
+        self
+    }
+
+    pub fn project(mut self, project: Option<String>) -> Self {
+        self.project = project;
+        self
+    }
+
+    pub fn header(mut self, name: String, value: String) -> Self {
+        self.headers.push((name, value));
+        self
+    }
+
+    pub fn user_agent(mut self, user_agent: Option<String>) -> Self {
+        self.user_agent = user_agent;
+        self
+    }
+
+    pub fn connect_timeout(mut self, connect_timeout: Option<time::Duration>) -> Self {
+        self.connect_timeout = connect_timeout;
+        self
+    }
+
+    // Covers the whole of a request and its response body. Streamed responses may run
+    // longer, so for those it bounds the wait for the response and then each gap between
+    // chunks.
+    pub fn timeout(mut self, timeout: Option<time::Duration>) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    // Writes every request and response pair to the directory as it happens.
+    pub fn record(mut self, record: Option<path::PathBuf>) -> Self {
+        self.record = record;
+        self
+    }
+
+    // Serves responses from a recorded directory and never touches the network.
+    pub fn replay(mut self, replay: Option<path::PathBuf>) -> Self {
+        self.replay = replay;
+        self
+    }
+
+    pub fn matching(mut self, matching: cassette::Matching) -> Self {
+        self.matching = matching;
+        self
+    }
+
+    pub fn build(self) -> Result<OpenAIApi, error::Error> {
+        let cassette = match (self.record, self.replay) {
+            (Some(_), Some(_)) => {
+                return Err(error::Error::InvalidConfiguration(String::from(
+                    "cannot record and replay at the same time",
+                )))
+            }
+            (Some(record), None) => Some(cassette::Cassette::record(record)?),
+            (None, Some(replay)) => Some(cassette::Cassette::replay(replay, self.matching)?),
+            (None, None) => None,
+        };
+
+        // Replaying needs no credentials, so a missing key only matters when going online.
+        let api_key = match (self.api_key, &cassette) {
+            (Some(api_key), _) => api_key,
+            (None, Some(cassette::Cassette::Replay { .. })) => String::new(),
+            (None, _) => {
+                return Err(error::Error::InvalidConfiguration(String::from(
+                    "missing API key",
+                )))
+            }
+        };
+
+        let base_url = parse_base_url(self.base_url.as_deref().unwrap_or(DEFAULT_BASE_URL))?;
+
+        let named = [
+            ("OpenAI-Organization", self.organization),
+            ("OpenAI-Project", self.project),
+        ];
+
+        let headers = parse_headers(
+            named
+                .into_iter()
+                .filter_map(|(name, value)| value.map(|value| (name.to_string(), value)))
+                .chain(self.headers),
+        )?;
+
+        let http_client = match self.http_client {
+            Some(http_client) => http_client,
+            None => build_http_client(self.user_agent, self.connect_timeout)?,
+        };
+
+        Ok(OpenAIApi {
+            http_client,
+            api_key,
+            base_url,
+            headers,
+            cassette,
+            timeout: self.timeout,
+        })
+    }
+}
+
+fn parse_base_url(base_url: &str) -> Result<String, error::Error> {
+    let base_url = base_url.trim_end_matches('/').to_string();
+
+    url::Url::parse(&base_url).map_err(|error| {
+        error::Error::InvalidConfiguration(format!("base URL {}: {}", base_url, error))
+    })?;
+
+    Ok(base_url)
+}
+
+fn parse_headers(
+    pairs: impl IntoIterator<Item = (String, String)>,
+) -> Result<reqwest::header::HeaderMap, error::Error> {
+    let mut headers = reqwest::header::HeaderMap::new();
+
+    for (name, value) in pairs {
+        let header_name =
+            reqwest::header::HeaderName::from_bytes(name.as_bytes()).map_err(|error| {
+                error::Error::InvalidConfiguration(format!("header {}: {}", name, error))
+            })?;
+        let header_value = reqwest::header::HeaderValue::from_str(&value).map_err(|error| {
+            error::Error::InvalidConfiguration(format!("header {}: {}", name, error))
+        })?;
+
+        headers.append(header_name, header_value);
+    }
+
+    Ok(headers)
+}
+
+// The request timeout is applied per request by `send`, as streams need it applied
+// differently.
+fn build_http_client(
+    user_agent: Option<String>,
+    connect_timeout: Option<time::Duration>,
+) -> Result<sync::Arc<reqwest::Client>, error::Error> {
+    let mut builder = reqwest::Client::builder();
+
+    if let Some(user_agent) = user_agent {
+        builder = builder.user_agent(user_agent);
+    }
+
+    if let Some(connect_timeout) = connect_timeout {
+        builder = builder.connect_timeout(connect_timeout);
+    }
+
+    Ok(sync::Arc::new(builder.build()?))
+}
+
+// reqwest's timeout would also cut a long stream off partway, so a streamed request only
+// has its wait for the response bounded here, and `sse` bounds each gap after that.
+async fn send(
+    request: reqwest::RequestBuilder,
+    timeout: Option<time::Duration>,
+    stream: bool,
+) -> Result<reqwest::Response, error::Error> {
+    match (timeout, stream) {
+        (Some(timeout), false) => Ok(request.timeout(timeout).send().await?),
+        (Some(timeout), true) => tokio::time::timeout(timeout, request.send())
+            .await
+            .map_err(|_| error::Error::Timeout(format!("no response within {:?}", timeout)))?
+            .map_err(Into::into),
+        (None, _) => Ok(request.send().await?),
+    }
+}
+
 #[async_trait]
 impl Datasource for OpenAIApi {
     async fn list_models(&self) -> Result<model::list_models::Response, error::Error> {
+        Ok(self.list_models_with_meta().await?.body)
+    }
+
+    async fn list_models_with_meta(
+        &self,
+    ) -> Result<ApiResponse<model::list_models::Response>, error::Error> {
+        let started = time::Instant::now();
         let response = self
-            .http_client
-            .get(format!("{}/v1/models", &self.base_url))
-            .bearer_auth(&self.api_key)
-            .send()
+            .send(reqwest::Method::GET, "/models", None, false)
             .await?;
 
-        match response.error_for_status() {
-            Ok(response) => {
-                let data: model::list_models::Response = response.json().await?;
-
-                Ok(data)
-            }
-            Err(error) => Err(error::Error::InvalidHttpResponse(error.to_string())),
-        }
+        ApiResponse::decode(response, started).await
     }
 
     async fn create_completion(
         &self,
         request: &model::create_completion::Request,
     ) -> Result<model::create_completion::Response, error::Error> {
+        Ok(self.create_completion_with_meta(request).await?.body)
+    }
+
+    async fn create_completion_with_meta(
+        &self,
+        request: &model::create_completion::Request,
+    ) -> Result<ApiResponse<model::create_completion::Response>, error::Error> {
         let body = serde_json::to_string(&request)?;
 
+        let started = time::Instant::now();
         let response = self
-            .http_client
-            .post(format!("{}/v1/completions", &self.base_url))
-            .header("Content-Type", "application/json")
-            .bearer_auth(&self.api_key)
-            .body(body)
-            .send()
+            .send(reqwest::Method::POST, "/completions", Some(body), false)
             .await?;
 
-        match response.error_for_status() {
-            Ok(response) => {
-                let data: model::create_completion::Response = response.json().await?;
+        ApiResponse::decode(response, started).await
+    }
 
-                Ok(data)
-            }
-            Err(error) => Err(error::Error::InvalidHttpResponse(error.to_string())),
-        }
+    async fn create_completion_stream(
+        &self,
+        request: &model::create_completion::Request,
+    ) -> Result<EventStream<model::create_completion::Choice>, error::Error> {
+        Ok(self.create_completion_stream_with_meta(request).await?.body)
+    }
+
+    async fn create_completion_stream_with_meta(
+        &self,
+        request: &model::create_completion::Request,
+    ) -> Result<ApiResponse<EventStream<model::create_completion::Choice>>, error::Error> {
+        let mut body = serde_json::to_value(request)?;
+        body["stream"] = serde_json::Value::Bool(true);
+        let body = serde_json::to_string(&body)?;
+
+        let started = time::Instant::now();
+        let response = self
+            .send(reqwest::Method::POST, "/completions", Some(body), true)
+            .await?;
+
+        let response = response.check().await?;
+        let meta = ResponseMeta::new(response.headers(), started.elapsed());
+
+        Ok(ApiResponse::new(
+            sse::events(response, self.timeout)
+                .map_ok(|chunk: model::create_completion::Chunk| {
+                    stream::iter(chunk.choices.into_iter().map(Ok))
+                })
+                .try_flatten()
+                .boxed(),
+            meta,
+        ))
     }
 
     async fn create_chat(
         &self,
         request: &model::create_chat::Request,
     ) -> Result<model::create_chat::Response, error::Error> {
+        Ok(self.create_chat_with_meta(request).await?.body)
+    }
+
+    async fn create_chat_with_meta(
+        &self,
+        request: &model::create_chat::Request,
+    ) -> Result<ApiResponse<model::create_chat::Response>, error::Error> {
         let body = serde_json::to_string(&request)?;
 
+        let started = time::Instant::now();
         let response = self
-            .http_client
-            .post(format!("{}/v1/chat/completions", &self.base_url))
-            .header("Content-Type", "application/json")
-            .bearer_auth(&self.api_key)
-            .body(body)
-            .send()
+            .send(
+                reqwest::Method::POST,
+                "/chat/completions",
+                Some(body),
+                false,
+            )
             .await?;
 
-        match response.error_for_status() {
-            Ok(response) => {
-                let data: model::create_chat::Response = response.json().await?;
+        ApiResponse::decode(response, started).await
+    }
 
-                Ok(data)
-            }
-            Err(error) => Err(error::Error::InvalidHttpResponse(error.to_string())),
-        }
+    async fn create_chat_stream(
+        &self,
+        request: &model::create_chat::Request,
+    ) -> Result<EventStream<model::create_chat::Chunk>, error::Error> {
+        Ok(self.create_chat_stream_with_meta(request).await?.body)
+    }
+
+    async fn create_chat_stream_with_meta(
+        &self,
+        request: &model::create_chat::Request,
+    ) -> Result<ApiResponse<EventStream<model::create_chat::Chunk>>, error::Error> {
+        let mut body = serde_json::to_value(request)?;
+        body["stream"] = serde_json::Value::Bool(true);
+        let body = serde_json::to_string(&body)?;
+
+        let started = time::Instant::now();
+        let response = self
+            .send(reqwest::Method::POST, "/chat/completions", Some(body), true)
+            .await?;
+
+        let response = response.check().await?;
+        let meta = ResponseMeta::new(response.headers(), started.elapsed());
+
+        Ok(ApiResponse::new(
+            sse::events(response, self.timeout).boxed(),
+            meta,
+        ))
     }
 
     async fn create_image(
         &self,
         request: &model::create_image::Request,
     ) -> Result<model::create_image::Response, error::Error> {
+        Ok(self.create_image_with_meta(request).await?.body)
+    }
+
+    async fn create_image_with_meta(
+        &self,
+        request: &model::create_image::Request,
+    ) -> Result<ApiResponse<model::create_image::Response>, error::Error> {
         let body = serde_json::to_string(&request)?;
 
+        let started = time::Instant::now();
         let response = self
-            .http_client
-            .post(format!("{}/v1/images/generations", &self.base_url))
-            .header("Content-Type", "application/json")
-            .bearer_auth(&self.api_key)
-            .body(body)
-            .send()
+            .send(
+                reqwest::Method::POST,
+                "/images/generations",
+                Some(body),
+                false,
+            )
             .await?;
 
-        match response.error_for_status() {
-            Ok(response) => {
-                let data: model::create_image::Response = response.json().await?;
-
-                Ok(data)
-            }
-            Err(error) => Err(error::Error::InvalidHttpResponse(error.to_string())),
-        }
+        ApiResponse::decode(response, started).await
     }
 
     async fn create_edit(
         &self,
         request: &model::create_edit::Request,
     ) -> Result<model::create_edit::Response, error::Error> {
+        Ok(self.create_edit_with_meta(request).await?.body)
+    }
+
+    async fn create_edit_with_meta(
+        &self,
+        request: &model::create_edit::Request,
+    ) -> Result<ApiResponse<model::create_edit::Response>, error::Error> {
         let body = serde_json::to_string(&request)?;
 
+        let started = time::Instant::now();
         let response = self
-            .http_client
-            .post(format!("{}/v1/edits", &self.base_url))
-            .header("Content-Type", "application/json")
-            .bearer_auth(&self.api_key)
-            .body(body)
-            .send()
+            .send(reqwest::Method::POST, "/edits", Some(body), false)
             .await?;
 
-        match response.error_for_status() {
-            Ok(response) => {
-                let data: model::create_edit::Response = response.json().await?;
-
-                Ok(data)
-            }
-            Err(error) => Err(error::Error::InvalidHttpResponse(error.to_string())),
-        }
+        ApiResponse::decode(response, started).await
     }
 
     async fn list_files(&self) -> Result<model::list_files::Response, error::Error> {
+        Ok(self.list_files_with_meta().await?.body)
+    }
+
+    async fn list_files_with_meta(
+        &self,
+    ) -> Result<ApiResponse<model::list_files::Response>, error::Error> {
+        let started = time::Instant::now();
         let response = self
-            .http_client
-            .get(format!("{}/v1/files", &self.base_url))
-            .header("Content-Type", "application/json")
-            .bearer_auth(&self.api_key)
-            .send()
+            .send(reqwest::Method::GET, "/files", None, false)
             .await?;
 
-        match response.error_for_status() {
-            Ok(response) => {
-                let data: model::list_files::Response = response.json().await?;
-
-                Ok(data)
-            }
-            Err(error) => Err(error::Error::InvalidHttpResponse(error.to_string())),
-        }
+        ApiResponse::decode(response, started).await
     }
 
     async fn create_embedding(
         &self,
         request: &model::create_embedding::Request,
     ) -> Result<model::create_embedding::Response, error::Error> {
+        Ok(self.create_embedding_with_meta(request).await?.body)
+    }
+
+    async fn create_embedding_with_meta(
+        &self,
+        request: &model::create_embedding::Request,
+    ) -> Result<ApiResponse<model::create_embedding::Response>, error::Error> {
         let body = serde_json::to_string(&request)?;
 
+        let started = time::Instant::now();
         let response = self
-            .http_client
-            .post(format!("{}/v1/embeddings", &self.base_url))
-            .header("Content-Type", "application/json")
-            .bearer_auth(&self.api_key)
-            .body(body)
-            .send()
-            .await?
-            .error_for_status()?
-            .json()
+            .send(reqwest::Method::POST, "/embeddings", Some(body), false)
             .await?;
 
-        Ok(response)
+        ApiResponse::decode(response, started).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::{
+        io::{AsyncReadExt, AsyncWriteExt},
+        net::TcpListener,
+    };
+
+    // Streams for longer than the request timeout, never pausing for as long as it.
+    #[tokio::test]
+    async fn stream_outlives_the_request_timeout() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+
+        tokio::spawn(async move {
+            let (mut socket, _) = listener.accept().await.unwrap();
+            let mut request = vec![];
+
+            while !request.ends_with(b"\r\n\r\n") {
+                request.push(socket.read_u8().await.unwrap());
+            }
+
+            socket
+                .write_all(
+                    b"HTTP/1.1 200 OK\r\ncontent-type: text/event-stream\r\nconnection: close\r\n\r\n",
+                )
+                .await
+                .unwrap();
+
+            for index in 0..8 {
+                tokio::time::sleep(time::Duration::from_millis(50)).await;
+
+                let chunk = serde_json::json!({
+                    "id": "c",
+                    "object": "text_completion",
+                    "created": 1,
+                    "model": "text-davinci-003",
+                    "choices": [{"text": index.to_string(), "index": 0, "logprobs": null, "finish_reason": null}],
+                });
+
+                socket
+                    .write_all(format!("data: {}\n\n", chunk).as_bytes())
+                    .await
+                    .unwrap();
+                socket.flush().await.unwrap();
+            }
+
+            socket.write_all(b"data: [DONE]\n\n").await.unwrap();
+        });
+
+        let datasource = OpenAIApi::builder()
+            .http_client(sync::Arc::new(
+                reqwest::Client::builder().no_proxy().build().unwrap(),
+            ))
+            .api_key(String::from("key"))
+            .base_url(Some(format!("http://{}/v1", address)))
+            .timeout(Some(time::Duration::from_millis(200)))
+            .build()
+            .unwrap();
+        let request = model::create_completion::Request::new(
+            serde_json::from_value(serde_json::json!("text-davinci-003")).unwrap(),
+            String::from("count"),
+        );
+
+        let text: Vec<String> = datasource
+            .create_completion_stream(&request)
+            .await
+            .unwrap()
+            .map(|choice| choice.unwrap().text)
+            .collect()
+            .await;
+
+        assert_eq!(text.concat(), "01234567");
     }
 }
@@ -0,0 +1,593 @@
+use crate::{error, model, ApiResponse, Datasource, EventStream};
+use async_trait::async_trait;
+use futures::StreamExt;
+use serde::Serialize;
+use std::{future::Future, sync, time};
+
+#[derive(Clone, Debug, Default)]
+pub struct RateLimits {
+    pub requests_per_minute: Option<u32>,
+    pub tokens_per_minute: Option<u32>,
+}
+
+impl RateLimits {
+    pub fn requests_per_minute(mut self, requests_per_minute: Option<u32>) -> Self {
+        self.requests_per_minute = requests_per_minute;
+        self
+    }
+
+    pub fn tokens_per_minute(mut self, tokens_per_minute: Option<u32>) -> Self {
+        self.tokens_per_minute = tokens_per_minute;
+        self
+    }
+}
+
+// Refills continuously at `capacity` per minute. The level may go negative when a response
+// reports more tokens than were reserved, which delays later callers accordingly.
+struct Bucket {
+    capacity: f64,
+    level: f64,
+}
+
+impl Bucket {
+    fn new(capacity: u32) -> Self {
+        Self {
+            capacity: f64::from(capacity),
+            level: f64::from(capacity),
+        }
+    }
+
+    // A limit of zero would never refill, so it means no limit at all.
+    fn limit(capacity: Option<u32>) -> Option<Self> {
+        capacity.filter(|capacity| *capacity > 0).map(Self::new)
+    }
+
+    fn refill(&mut self, elapsed: time::Duration) {
+        self.level = (self.level + elapsed.as_secs_f64() * self.capacity / 60.0).min(self.capacity);
+    }
+
+    // A single request larger than the whole bucket only waits for a full bucket.
+    fn wait(&self, amount: f64) -> Option<time::Duration> {
+        let amount = amount.min(self.capacity);
+
+        match self.level >= amount {
+            true => None,
+            false => Some(time::Duration::from_secs_f64(
+                (amount - self.level) * 60.0 / self.capacity,
+            )),
+        }
+    }
+}
+
+struct Buckets {
+    requests: Option<Bucket>,
+    tokens: Option<Bucket>,
+    refilled: time::Instant,
+}
+
+impl Buckets {
+    // Returns reserved tokens that went unused, or charges for ones that were under-estimated.
+    fn settle(&mut self, estimated: u64, actual: u64) {
+        if let Some(bucket) = self.tokens.as_mut() {
+            bucket.level += estimated as f64 - actual as f64;
+        }
+    }
+}
+
+fn lock(buckets: &sync::Mutex<Buckets>) -> sync::MutexGuard<'_, Buckets> {
+    buckets.lock().unwrap_or_else(|error| error.into_inner())
+}
+
+// Streams report no usage, so a stream is charged when it ends or is dropped: the prompt as
+// estimated plus the characters it delivered, at the same four per token.
+struct Settlement {
+    buckets: sync::Arc<sync::Mutex<Buckets>>,
+    estimated: u64,
+    prompt: u64,
+    characters: usize,
+}
+
+impl Settlement {
+    fn delivered(&mut self, characters: usize) {
+        self.characters += characters;
+    }
+}
+
+impl Drop for Settlement {
+    fn drop(&mut self) {
+        let actual = self.prompt + self.characters.div_ceil(4) as u64;
+        lock(&self.buckets).settle(self.estimated, actual);
+    }
+}
+
+pub struct RateLimited<D> {
+    inner: D,
+    buckets: sync::Arc<sync::Mutex<Buckets>>,
+}
+
+impl<D> RateLimited<D> {
+    pub fn new(inner: D, limits: RateLimits) -> Self {
+        Self {
+            inner,
+            buckets: sync::Arc::new(sync::Mutex::new(Buckets {
+                requests: Bucket::limit(limits.requests_per_minute),
+                tokens: Bucket::limit(limits.tokens_per_minute),
+                refilled: time::Instant::now(),
+            })),
+        }
+    }
+
+    pub fn into_inner(self) -> D {
+        self.inner
+    }
+
+    fn buckets(&self) -> sync::MutexGuard<'_, Buckets> {
+        lock(&self.buckets)
+    }
+
+    async fn acquire(&self, tokens: u64) {
+        let tokens = tokens as f64;
+
+        loop {
+            let wait = {
+                let mut buckets = self.buckets();
+                let now = time::Instant::now();
+                let elapsed = now.duration_since(buckets.refilled);
+                buckets.refilled = now;
+
+                if let Some(bucket) = buckets.requests.as_mut() {
+                    bucket.refill(elapsed);
+                }
+
+                if let Some(bucket) = buckets.tokens.as_mut() {
+                    bucket.refill(elapsed);
+                }
+
+                let wait = [
+                    buckets
+                        .requests
+                        .as_ref()
+                        .and_then(|bucket| bucket.wait(1.0)),
+                    buckets
+                        .tokens
+                        .as_ref()
+                        .and_then(|bucket| bucket.wait(tokens)),
+                ]
+                .into_iter()
+                .flatten()
+                .max();
+
+                if wait.is_none() {
+                    if let Some(bucket) = buckets.requests.as_mut() {
+                        bucket.level -= 1.0;
+                    }
+
+                    if let Some(bucket) = buckets.tokens.as_mut() {
+                        bucket.level -= tokens;
+                    }
+                }
+
+                wait
+            };
+
+            match wait {
+                Some(wait) => tokio::time::sleep(wait).await,
+                None => return,
+            }
+        }
+    }
+
+    fn settle(&self, estimated: u64, actual: u64) {
+        self.buckets().settle(estimated, actual);
+    }
+
+    async fn limit<T, F, Fut>(
+        &self,
+        estimated: u64,
+        call: F,
+        usage: impl Fn(&T) -> Option<u64> + Send,
+    ) -> Result<T, error::Error>
+    where
+        F: FnOnce() -> Fut + Send,
+        Fut: Future<Output = Result<T, error::Error>> + Send,
+        T: Send,
+    {
+        self.acquire(estimated).await;
+
+        let result = call().await;
+
+        match &result {
+            Ok(body) => {
+                if let Some(actual) = usage(body) {
+                    self.settle(estimated, actual);
+                }
+            }
+            Err(_) => self.settle(estimated, 0),
+        }
+
+        result
+    }
+
+    // Reserves like `limit`, then leaves settling to the stream it returns.
+    async fn limit_stream<T, F, Fut>(
+        &self,
+        request: &(impl Serialize + Sync),
+        max_tokens: Option<usize>,
+        call: F,
+        characters: fn(&T) -> usize,
+    ) -> Result<ApiResponse<EventStream<T>>, error::Error>
+    where
+        F: FnOnce() -> Fut + Send,
+        Fut: Future<Output = Result<ApiResponse<EventStream<T>>, error::Error>> + Send,
+        T: Send + 'static,
+    {
+        let estimated = estimate(request, max_tokens);
+        let response = self.limit(estimated, call, |_| None).await?;
+
+        let mut settlement = Settlement {
+            buckets: self.buckets.clone(),
+            estimated,
+            prompt: estimate(request, None),
+            characters: 0,
+        };
+
+        Ok(ApiResponse::new(
+            response
+                .body
+                .map(move |item| {
+                    if let Ok(item) = &item {
+                        settlement.delivered(characters(item));
+                    }
+
+                    item
+                })
+                .boxed(),
+            response.meta,
+        ))
+    }
+}
+
+// About four characters per token for English text. The serialized body overcounts a little
+// because of JSON syntax, which errs on the side of waiting.
+fn estimate(request: &impl Serialize, max_tokens: Option<usize>) -> u64 {
+    let characters = serde_json::to_string(request)
+        .map(|body| body.chars().count())
+        .unwrap_or_default();
+
+    (characters.div_ceil(4) + max_tokens.unwrap_or_default()) as u64
+}
+
+fn completion_characters(choice: &model::create_completion::Choice) -> usize {
+    choice.text.chars().count()
+}
+
+fn chat_characters(chunk: &model::create_chat::Chunk) -> usize {
+    let characters = |text: &Option<String>| {
+        text.as_deref()
+            .map(|text| text.chars().count())
+            .unwrap_or_default()
+    };
+
+    chunk
+        .choices
+        .iter()
+        .map(|choice| {
+            characters(&choice.delta.content)
+                + choice
+                    .delta
+                    .function_call
+                    .as_ref()
+                    .map(|call| characters(&call.name) + characters(&call.arguments))
+                    .unwrap_or_default()
+        })
+        .sum()
+}
+
+#[async_trait]
+impl<D> Datasource for RateLimited<D>
+where
+    D: Datasource + Send + Sync,
+{
+    async fn list_models(&self) -> Result<model::list_models::Response, error::Error> {
+        self.limit(0, || self.inner.list_models(), |_| None).await
+    }
+
+    async fn create_completion(
+        &self,
+        request: &model::create_completion::Request,
+    ) -> Result<model::create_completion::Response, error::Error> {
+        self.limit(
+            estimate(request, request.max_tokens),
+            || self.inner.create_completion(request),
+            |response| Some(response.usage.total_tokens as u64),
+        )
+        .await
+    }
+
+    async fn create_completion_stream(
+        &self,
+        request: &model::create_completion::Request,
+    ) -> Result<EventStream<model::create_completion::Choice>, error::Error> {
+        Ok(self.create_completion_stream_with_meta(request).await?.body)
+    }
+
+    async fn create_chat(
+        &self,
+        request: &model::create_chat::Request,
+    ) -> Result<model::create_chat::Response, error::Error> {
+        self.limit(
+            estimate(request, request.max_tokens),
+            || self.inner.create_chat(request),
+            |response| Some(response.usage.total_tokens as u64),
+        )
+        .await
+    }
+
+    async fn create_chat_stream(
+        &self,
+        request: &model::create_chat::Request,
+    ) -> Result<EventStream<model::create_chat::Chunk>, error::Error> {
+        Ok(self.create_chat_stream_with_meta(request).await?.body)
+    }
+
+    async fn create_image(
+        &self,
+        request: &model::create_image::Request,
+    ) -> Result<model::create_image::Response, error::Error> {
+        self.limit(0, || self.inner.create_image(request), |_| None)
+            .await
+    }
+
+    async fn create_edit(
+        &self,
+        request: &model::create_edit::Request,
+    ) -> Result<model::create_edit::Response, error::Error> {
+        self.limit(
+            estimate(request, None),
+            || self.inner.create_edit(request),
+            |response| Some(response.usage.total_tokens as u64),
+        )
+        .await
+    }
+
+    async fn list_files(&self) -> Result<model::list_files::Response, error::Error> {
+        self.limit(0, || self.inner.list_files(), |_| None).await
+    }
+
+    async fn create_embedding(
+        &self,
+        request: &model::create_embedding::Request,
+    ) -> Result<model::create_embedding::Response, error::Error> {
+        self.limit(
+            estimate(request, None),
+            || self.inner.create_embedding(request),
+            |response| Some(response.usage.total_tokens as u64),
+        )
+        .await
+    }
+
+    async fn list_models_with_meta(
+        &self,
+    ) -> Result<ApiResponse<model::list_models::Response>, error::Error> {
+        self.limit(0, || self.inner.list_models_with_meta(), |_| None)
+            .await
+    }
+
+    async fn create_completion_with_meta(
+        &self,
+        request: &model::create_completion::Request,
+    ) -> Result<ApiResponse<model::create_completion::Response>, error::Error> {
+        self.limit(
+            estimate(request, request.max_tokens),
+            || self.inner.create_completion_with_meta(request),
+            |response| Some(response.body.usage.total_tokens as u64),
+        )
+        .await
+    }
+
+    async fn create_completion_stream_with_meta(
+        &self,
+        request: &model::create_completion::Request,
+    ) -> Result<ApiResponse<EventStream<model::create_completion::Choice>>, error::Error> {
+        self.limit_stream(
+            request,
+            request.max_tokens,
+            || self.inner.create_completion_stream_with_meta(request),
+            completion_characters,
+        )
+        .await
+    }
+
+    async fn create_chat_with_meta(
+        &self,
+        request: &model::create_chat::Request,
+    ) -> Result<ApiResponse<model::create_chat::Response>, error::Error> {
+        self.limit(
+            estimate(request, request.max_tokens),
+            || self.inner.create_chat_with_meta(request),
+            |response| Some(response.body.usage.total_tokens as u64),
+        )
+        .await
+    }
+
+    async fn create_chat_stream_with_meta(
+        &self,
+        request: &model::create_chat::Request,
+    ) -> Result<ApiResponse<EventStream<model::create_chat::Chunk>>, error::Error> {
+        self.limit_stream(
+            request,
+            request.max_tokens,
+            || self.inner.create_chat_stream_with_meta(request),
+            chat_characters,
+        )
+        .await
+    }
+
+    async fn create_image_with_meta(
+        &self,
+        request: &model::create_image::Request,
+    ) -> Result<ApiResponse<model::create_image::Response>, error::Error> {
+        self.limit(0, || self.inner.create_image_with_meta(request), |_| None)
+            .await
+    }
+
+    async fn create_edit_with_meta(
+        &self,
+        request: &model::create_edit::Request,
+    ) -> Result<ApiResponse<model::create_edit::Response>, error::Error> {
+        self.limit(
+            estimate(request, None),
+            || self.inner.create_edit_with_meta(request),
+            |response| Some(response.body.usage.total_tokens as u64),
+        )
+        .await
+    }
+
+    async fn list_files_with_meta(
+        &self,
+    ) -> Result<ApiResponse<model::list_files::Response>, error::Error> {
+        self.limit(0, || self.inner.list_files_with_meta(), |_| None)
+            .await
+    }
+
+    async fn create_embedding_with_meta(
+        &self,
+        request: &model::create_embedding::Request,
+    ) -> Result<ApiResponse<model::create_embedding::Response>, error::Error> {
+        self.limit(
+            estimate(request, None),
+            || self.inner.create_embedding_with_meta(request),
+            |response| Some(response.body.usage.total_tokens as u64),
+        )
+        .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn choice(text: &str) -> Result<model::create_completion::Choice, error::Error> {
+        Ok(model::create_completion::Choice {
+            text: String::from(text),
+            index: 0,
+            logprobs: None,
+            finish_reason: None,
+        })
+    }
+
+    fn level(limited: &RateLimited<()>) -> f64 {
+        limited.buckets().tokens.as_ref().unwrap().level
+    }
+
+    // Streams twelve characters, three tokens at four characters each, in three chunks.
+    async fn stream(
+        limited: &RateLimited<()>,
+        request: &model::create_completion::Request,
+    ) -> EventStream<model::create_completion::Choice> {
+        let chunks = vec![choice("abcd"), choice("efgh"), choice("ijkl")];
+
+        limited
+            .limit_stream(
+                request,
+                request.max_tokens,
+                || async {
+                    Ok(ApiResponse::new(
+                        futures::stream::iter(chunks).boxed(),
+                        crate::ResponseMeta::default(),
+                    ))
+                },
+                completion_characters,
+            )
+            .await
+            .unwrap()
+            .body
+    }
+
+    fn request() -> model::create_completion::Request {
+        model::create_completion::Request::new(
+            model::create_completion::Model::TextDavinci003,
+            String::from("Count"),
+        )
+        .max_tokens(500)
+    }
+
+    #[tokio::test]
+    async fn finished_stream_is_charged_for_what_it_delivered() {
+        let limited = RateLimited::new((), RateLimits::default().tokens_per_minute(Some(10_000)));
+        let request = request();
+        let prompt = estimate(&request, None) as f64;
+
+        let chunks: Vec<_> = stream(&limited, &request).await.collect().await;
+
+        assert_eq!(chunks.len(), 3);
+        assert_eq!(level(&limited), 10_000.0 - prompt - 3.0);
+    }
+
+    #[tokio::test]
+    async fn dropped_stream_is_charged_for_what_it_delivered_so_far() {
+        let limited = RateLimited::new((), RateLimits::default().tokens_per_minute(Some(10_000)));
+        let request = request();
+        let prompt = estimate(&request, None) as f64;
+
+        let mut chunks = stream(&limited, &request).await;
+        chunks.next().await.unwrap().unwrap();
+
+        assert_eq!(level(&limited), 10_000.0 - prompt - 500.0);
+
+        drop(chunks);
+
+        assert_eq!(level(&limited), 10_000.0 - prompt - 1.0);
+    }
+
+    #[test]
+    fn zero_means_no_limit() {
+        assert!(Bucket::limit(Some(0)).is_none());
+        assert!(Bucket::limit(None).is_none());
+        assert!(Bucket::limit(Some(60)).is_some());
+    }
+
+    #[test]
+    fn full_bucket_does_not_wait() {
+        let bucket = Bucket::new(60);
+
+        assert_eq!(bucket.wait(60.0), None);
+    }
+
+    #[test]
+    fn empty_bucket_waits_for_refill() {
+        let mut bucket = Bucket::new(60);
+        bucket.level = 0.0;
+
+        assert_eq!(bucket.wait(1.0), Some(time::Duration::from_secs(1)));
+        assert_eq!(bucket.wait(30.0), Some(time::Duration::from_secs(30)));
+    }
+
+    #[test]
+    fn oversized_request_waits_for_a_full_bucket_only() {
+        let mut bucket = Bucket::new(60);
+        bucket.level = 0.0;
+
+        assert_eq!(bucket.wait(1000.0), Some(time::Duration::from_secs(60)));
+    }
+
+    #[test]
+    fn negative_level_delays_longer() {
+        let mut bucket = Bucket::new(60);
+        bucket.level = -60.0;
+
+        assert_eq!(bucket.wait(60.0), Some(time::Duration::from_secs(120)));
+    }
+
+    #[test]
+    fn refill_is_capped_at_capacity() {
+        let mut bucket = Bucket::new(60);
+        bucket.level = 0.0;
+
+        bucket.refill(time::Duration::from_secs(10));
+        assert_eq!(bucket.level, 10.0);
+
+        bucket.refill(time::Duration::from_secs(3600));
+        assert_eq!(bucket.level, 60.0);
+    }
+}
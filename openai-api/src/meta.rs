@@ -0,0 +1,114 @@
+use crate::error::{self, ResponseExt};
+use serde::de::DeserializeOwned;
+use std::time;
+
+#[derive(Debug)]
+pub struct ApiResponse<T> {
+    pub body: T,
+    pub meta: ResponseMeta,
+}
+
+impl<T> ApiResponse<T> {
+    pub fn new(body: T, meta: ResponseMeta) -> Self {
+        Self { body, meta }
+    }
+
+    pub(crate) async fn decode(
+        response: reqwest::Response,
+        started: time::Instant,
+    ) -> Result<Self, error::Error>
+    where
+        T: DeserializeOwned,
+    {
+        let response = response.check().await?;
+        let mut meta = ResponseMeta::new(response.headers(), started.elapsed());
+        let body: T = response.json().await?;
+        meta.latency = started.elapsed();
+
+        Ok(Self { body, meta })
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct ResponseMeta {
+    pub request_id: Option<String>,
+    pub organization: Option<String>,
+    pub model: Option<String>,
+    pub processing: Option<time::Duration>,
+    pub latency: time::Duration,
+    pub rate_limit: RateLimit,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct RateLimit {
+    pub limit_requests: Option<u64>,
+    pub limit_tokens: Option<u64>,
+    pub remaining_requests: Option<u64>,
+    pub remaining_tokens: Option<u64>,
+    pub reset_requests: Option<time::Duration>,
+    pub reset_tokens: Option<time::Duration>,
+}
+
+impl ResponseMeta {
+    pub fn new(headers: &reqwest::header::HeaderMap, latency: time::Duration) -> Self {
+        let header = |name: &str| headers.get(name).and_then(|value| value.to_str().ok());
+        let number = |name: &str| header(name).and_then(|value| value.parse::<u64>().ok());
+
+        Self {
+            request_id: header("x-request-id").map(String::from),
+            organization: header("openai-organization").map(String::from),
+            model: header("openai-model").map(String::from),
+            processing: header("openai-processing-ms")
+                .and_then(|value| value.parse::<f64>().ok())
+                .and_then(|millis| error::seconds(millis / 1000.0)),
+            latency,
+            rate_limit: RateLimit {
+                limit_requests: number("x-ratelimit-limit-requests"),
+                limit_tokens: number("x-ratelimit-limit-tokens"),
+                remaining_requests: number("x-ratelimit-remaining-requests"),
+                remaining_tokens: number("x-ratelimit-remaining-tokens"),
+                reset_requests: header("x-ratelimit-reset-requests").and_then(error::parse_reset),
+                reset_tokens: header("x-ratelimit-reset-tokens").and_then(error::parse_reset),
+            },
+        }
+    }
+
+    // For datasources that have no HTTP response to inspect.
+    pub fn elapsed(started: time::Instant) -> Self {
+        Self {
+            latency: started.elapsed(),
+            ..Default::default()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use reqwest::header::{HeaderMap, HeaderValue};
+
+    fn processing(value: &str) -> Option<time::Duration> {
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            "openai-processing-ms",
+            HeaderValue::from_str(value).unwrap(),
+        );
+
+        ResponseMeta::new(&headers, time::Duration::ZERO).processing
+    }
+
+    #[test]
+    fn processing_time_parses_milliseconds() {
+        assert_eq!(processing("250"), Some(time::Duration::from_millis(250)));
+        assert_eq!(processing("0.5"), Some(time::Duration::from_micros(500)));
+    }
+
+    #[test]
+    fn processing_time_survives_hostile_values() {
+        assert_eq!(processing("1e30"), Some(time::Duration::from_secs(86_400)));
+        assert_eq!(processing("inf"), None);
+        assert_eq!(processing("NaN"), None);
+        assert_eq!(processing("-3"), None);
+        assert_eq!(processing("soon"), None);
+    }
+}
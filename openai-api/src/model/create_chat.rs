@@ -1,12 +1,13 @@
 use super::function::Function;
 use super::object::Object;
+use super::sampling;
 use crate::error;
-use serde::{Deserialize, Deserializer, Serialize};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
 use std::{collections::HashMap, str::FromStr};
 
-#[derive(Debug, Serialize)]
-pub struct Request<'a> {
-    pub model: &'a Model,
+#[derive(Clone, Debug, Serialize)]
+pub struct Request {
+    pub model: Model,
     pub messages: Vec<Message>,
 
     #[serde(skip_serializing_if = "Option::is_none")]
@@ -19,7 +20,7 @@ pub struct Request<'a> {
     pub temperature: Option<f32>,
 
     #[serde(skip_serializing_if = "Option::is_none")]
-    pub top_p: Option<usize>,
+    pub top_p: Option<f32>,
 
     #[serde(skip_serializing_if = "Option::is_none")]
     pub n: Option<usize>,
@@ -28,23 +29,23 @@ pub struct Request<'a> {
     pub stream: Option<bool>,
 
     #[serde(skip_serializing_if = "Option::is_none")]
-    pub stop: Option<String>,
+    pub stop: Option<Vec<String>>,
 
     #[serde(skip_serializing_if = "Option::is_none")]
-    pub presence_penalty: Option<isize>,
+    pub presence_penalty: Option<f32>,
 
     #[serde(skip_serializing_if = "Option::is_none")]
-    pub frequency_penalty: Option<isize>,
+    pub frequency_penalty: Option<f32>,
 
     #[serde(skip_serializing_if = "Option::is_none")]
-    pub logit_bias: Option<String>,
+    pub logit_bias: Option<HashMap<usize, f32>>,
 
     #[serde(skip_serializing_if = "Option::is_none")]
     pub user: Option<String>,
 }
 
-impl<'a> Request<'a> {
-    pub fn new(model: &'a Model, messages: Vec<Message>) -> Self {
+impl Request {
+    pub fn new(model: Model, messages: Vec<Message>) -> Self {
         Self {
             model,
             messages,
@@ -72,22 +73,55 @@ impl<'a> Request<'a> {
         self
     }
 
+    pub fn n(mut self, n: Option<usize>) -> Self {
+        self.n = n;
+        self
+    }
+
     pub fn temperature(mut self, temperature: Option<f32>) -> Self {
         if let Some(temperature) = temperature {
-            if temperature <= 2.0 || temperature >= 0.0 {
+            if (0.0..=2.0).contains(&temperature) {
                 self.temperature = Some(temperature);
             }
         }
 
         self
     }
+
+    pub fn top_p(mut self, top_p: Option<f32>) -> Result<Self, error::Error> {
+        self.top_p = sampling::top_p(top_p)?;
+        Ok(self)
+    }
+
+    pub fn stop(mut self, stop: Vec<String>) -> Result<Self, error::Error> {
+        self.stop = sampling::stop(stop)?;
+        Ok(self)
+    }
+
+    pub fn presence_penalty(mut self, presence_penalty: Option<f32>) -> Result<Self, error::Error> {
+        self.presence_penalty = sampling::presence_penalty(presence_penalty)?;
+        Ok(self)
+    }
+
+    pub fn frequency_penalty(
+        mut self,
+        frequency_penalty: Option<f32>,
+    ) -> Result<Self, error::Error> {
+        self.frequency_penalty = sampling::frequency_penalty(frequency_penalty)?;
+        Ok(self)
+    }
+
+    pub fn logit_bias(mut self, logit_bias: HashMap<usize, f32>) -> Result<Self, error::Error> {
+        self.logit_bias = sampling::logit_bias(logit_bias)?;
+        Ok(self)
+    }
 }
 
-#[derive(Clone, Debug, Deserialize, Serialize)]
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
 pub struct Message {
     pub role: Role,
 
-    #[serde(skip_serializing_if = "Option::is_none")]
+    // Sent as null rather than omitted, which the API requires for function call replies.
     pub content: Option<String>,
 
     #[serde(skip_serializing_if = "Option::is_none")]
@@ -97,12 +131,39 @@ pub struct Message {
     pub function_call: Option<FunctionCall>,
 }
 
-#[derive(Clone, Debug, Serialize)]
+#[derive(Clone, Debug, PartialEq)]
 pub struct FunctionCall {
     name: String,
     arguments: Option<HashMap<String, serde_json::Value>>,
 }
 
+// The API exchanges arguments as a JSON encoded string rather than an object.
+impl Serialize for FunctionCall {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        #[derive(Serialize)]
+        struct Inner<'a> {
+            name: &'a str,
+            arguments: String,
+        }
+
+        let arguments = match &self.arguments {
+            Some(arguments) => {
+                serde_json::to_string(arguments).map_err(serde::ser::Error::custom)?
+            }
+            None => String::from("{}"),
+        };
+
+        Inner {
+            name: &self.name,
+            arguments,
+        }
+        .serialize(serializer)
+    }
+}
+
 impl<'de> Deserialize<'de> for FunctionCall {
     fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
     where
@@ -115,16 +176,13 @@ impl<'de> Deserialize<'de> for FunctionCall {
         }
 
         let helper = Inner::deserialize(deserializer)?;
-        let arguments_map: Option<HashMap<String, serde_json::Value>> = helper
+        let arguments = helper
             .arguments
-            .map(|args| serde_json::from_str(&args))
+            .as_deref()
+            .map(parse_arguments)
             .transpose()
-            .map_err(serde::de::Error::custom)?;
-
-        let arguments = match arguments_map {
-            Some(ref m) if m.is_empty() => None,
-            _ => arguments_map,
-        };
+            .map_err(serde::de::Error::custom)?
+            .flatten();
 
         Ok(FunctionCall {
             name: helper.name,
@@ -133,7 +191,35 @@ impl<'de> Deserialize<'de> for FunctionCall {
     }
 }
 
-#[derive(Clone, Debug, Deserialize, Serialize)]
+impl FunctionCall {
+    pub fn new(name: String, arguments: &str) -> Result<Self, error::Error> {
+        Ok(Self {
+            name,
+            arguments: parse_arguments(arguments)?,
+        })
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn arguments(&self) -> Option<&HashMap<String, serde_json::Value>> {
+        self.arguments.as_ref()
+    }
+}
+
+fn parse_arguments(
+    arguments: &str,
+) -> Result<Option<HashMap<String, serde_json::Value>>, serde_json::Error> {
+    let arguments: HashMap<String, serde_json::Value> = serde_json::from_str(arguments)?;
+
+    match arguments.is_empty() {
+        true => Ok(None),
+        false => Ok(Some(arguments)),
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
 #[serde(rename_all = "lowercase")]
 pub enum Role {
     System,
@@ -150,6 +236,7 @@ impl FromStr for Role {
             "system" => Ok(Self::System),
             "assistant" => Ok(Self::Assistant),
             "user" => Ok(Self::User),
+            "function" => Ok(Self::Function),
             _ => Err(Self::Err::UnsupportedRole(s.to_string())),
         }
     }
@@ -160,7 +247,9 @@ pub struct Response {
     pub id: String,
     pub object: Object,
     pub created: usize,
-    pub model: Model,
+
+    // Deployments report ids of their own, such as Azure's gpt-35-turbo.
+    pub model: String,
     pub choices: Vec<Choice>,
     pub usage: Usage,
 }
@@ -169,7 +258,91 @@ pub struct Response {
 pub struct Choice {
     pub index: usize,
     pub message: Message,
-    pub finish_reason: FinishReason,
+    pub finish_reason: Option<FinishReason>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct Chunk {
+    pub id: String,
+    pub object: Object,
+    pub created: usize,
+
+    // Deployments report ids of their own, such as Azure's gpt-35-turbo.
+    pub model: String,
+    pub choices: Vec<ChunkChoice>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ChunkChoice {
+    pub index: usize,
+    pub delta: Delta,
+    pub finish_reason: Option<FinishReason>,
+}
+
+#[derive(Clone, Debug, Default, Deserialize)]
+pub struct Delta {
+    pub role: Option<Role>,
+    pub content: Option<String>,
+    pub function_call: Option<FunctionCallDelta>,
+}
+
+#[derive(Clone, Debug, Default, Deserialize)]
+pub struct FunctionCallDelta {
+    pub name: Option<String>,
+    pub arguments: Option<String>,
+}
+
+#[derive(Debug, Default)]
+pub struct MessageAccumulator {
+    role: Option<Role>,
+    content: Option<String>,
+    function_name: Option<String>,
+    function_arguments: Option<String>,
+}
+
+impl MessageAccumulator {
+    pub fn push(&mut self, delta: &Delta) {
+        if let Some(role) = &delta.role {
+            self.role = Some(role.clone());
+        }
+
+        if let Some(content) = &delta.content {
+            self.content
+                .get_or_insert_with(String::new)
+                .push_str(content);
+        }
+
+        if let Some(function_call) = &delta.function_call {
+            if let Some(name) = &function_call.name {
+                self.function_name
+                    .get_or_insert_with(String::new)
+                    .push_str(name);
+            }
+
+            if let Some(arguments) = &function_call.arguments {
+                self.function_arguments
+                    .get_or_insert_with(String::new)
+                    .push_str(arguments);
+            }
+        }
+    }
+
+    pub fn finish(self) -> Result<Message, error::Error> {
+        let function_call = match self.function_name {
+            Some(name) => Some(FunctionCall::new(
+                name,
+                self.function_arguments.as_deref().unwrap_or("{}"),
+            )?),
+            None => None,
+        };
+
+        Ok(Message {
+            role: self.role.unwrap_or(Role::Assistant),
+            content: self.content,
+            name: None,
+            function_call,
+        })
+    }
 }
 
 #[allow(non_camel_case_types)]
@@ -218,16 +391,28 @@ impl FromStr for Model {
     }
 }
 
+impl Model {
+    // The number of tokens the prompt and the reply share.
+    pub fn context_window(&self) -> usize {
+        match self {
+            Self::Gpt4 | Self::Gpt4_0613 => 8192,
+            Self::Gpt4_32k | Self::Gpt4_32k_0613 => 32768,
+            Self::Gpt3dot5Turbo | Self::Gpt3dot5Turbo_0613 => 4096,
+            Self::Gpt3dot5Turbo_16k | Self::Gpt3dot5Turbo_16k_0613 => 16384,
+        }
+    }
+}
+
 // text-babbage-001, text-ada-001
 
-#[derive(Debug, Deserialize)]
+#[derive(Clone, Debug, Deserialize, Serialize)]
 pub struct Usage {
     pub prompt_tokens: usize,
     pub completion_tokens: usize,
     pub total_tokens: usize,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Clone, Debug, Deserialize)]
 pub enum FinishReason {
     #[serde(rename = "length")]
     Length,
@@ -237,4 +422,103 @@ pub enum FinishReason {
 
     #[serde(rename = "function_call")]
     FunctionCall,
+
+    #[serde(rename = "content_filter")]
+    ContentFilter,
+
+    // Reasons introduced after this client was written.
+    #[serde(other)]
+    Other,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn delta(
+        role: Option<Role>,
+        content: Option<&str>,
+        function_call: Option<(Option<&str>, Option<&str>)>,
+    ) -> Delta {
+        Delta {
+            role,
+            content: content.map(String::from),
+            function_call: function_call.map(|(name, arguments)| FunctionCallDelta {
+                name: name.map(String::from),
+                arguments: arguments.map(String::from),
+            }),
+        }
+    }
+
+    #[test]
+    fn accumulates_content() {
+        let mut message = MessageAccumulator::default();
+        message.push(&delta(Some(Role::Assistant), Some(""), None));
+        message.push(&delta(None, Some("Hel"), None));
+        message.push(&delta(None, Some("lo"), None));
+        message.push(&delta(None, None, None));
+
+        let message = message.finish().unwrap();
+
+        assert!(matches!(message.role, Role::Assistant));
+        assert_eq!(message.content.as_deref(), Some("Hello"));
+        assert!(message.function_call.is_none());
+    }
+
+    #[test]
+    fn defaults_to_the_assistant_role() {
+        let mut message = MessageAccumulator::default();
+        message.push(&delta(None, Some("hi"), None));
+
+        assert!(matches!(message.finish().unwrap().role, Role::Assistant));
+    }
+
+    #[test]
+    fn accumulates_function_calls() {
+        let mut message = MessageAccumulator::default();
+        message.push(&delta(
+            Some(Role::Assistant),
+            None,
+            Some((Some("get_weather"), Some(""))),
+        ));
+        message.push(&delta(None, None, Some((None, Some("{\"city\":")))));
+        message.push(&delta(None, None, Some((None, Some(" \"Paris\"}")))));
+
+        let message = message.finish().unwrap();
+        let function_call = message.function_call.unwrap();
+
+        assert!(message.content.is_none());
+        assert_eq!(function_call.name(), "get_weather");
+        assert_eq!(
+            function_call.arguments().unwrap()["city"],
+            serde_json::json!("Paris")
+        );
+    }
+
+    #[test]
+    fn function_call_without_arguments() {
+        let mut message = MessageAccumulator::default();
+        message.push(&delta(None, None, Some((Some("now"), None))));
+
+        let function_call = message.finish().unwrap().function_call.unwrap();
+
+        assert_eq!(function_call.name(), "now");
+        assert!(function_call.arguments().is_none());
+    }
+
+    #[test]
+    fn arguments_without_a_name_are_not_a_call() {
+        let mut message = MessageAccumulator::default();
+        message.push(&delta(None, None, Some((None, Some("{}")))));
+
+        assert!(message.finish().unwrap().function_call.is_none());
+    }
+
+    #[test]
+    fn invalid_arguments_are_an_error() {
+        let mut message = MessageAccumulator::default();
+        message.push(&delta(None, None, Some((Some("f"), Some("{\"a\":")))));
+
+        assert!(message.finish().is_err());
+    }
 }
@@ -1,7 +1,8 @@
 use super::object::Object;
+use super::sampling;
 use crate::error;
 use serde::{Deserialize, Serialize};
-use std::str::FromStr;
+use std::{collections::HashMap, str::FromStr};
 
 #[derive(Debug, Serialize)]
 pub struct Request {
@@ -18,7 +19,7 @@ pub struct Request {
     pub temperature: Option<f32>,
 
     #[serde(skip_serializing_if = "Option::is_none")]
-    pub top_p: Option<usize>,
+    pub top_p: Option<f32>,
 
     #[serde(skip_serializing_if = "Option::is_none")]
     pub n: Option<usize>,
@@ -33,19 +34,19 @@ pub struct Request {
     pub echo: Option<bool>,
 
     #[serde(skip_serializing_if = "Option::is_none")]
-    pub stop: Option<String>,
+    pub stop: Option<Vec<String>>,
 
     #[serde(skip_serializing_if = "Option::is_none")]
-    pub presence_penalty: Option<isize>,
+    pub presence_penalty: Option<f32>,
 
     #[serde(skip_serializing_if = "Option::is_none")]
-    pub frequency_penalty: Option<isize>,
+    pub frequency_penalty: Option<f32>,
 
     #[serde(skip_serializing_if = "Option::is_none")]
     pub best_of: Option<usize>,
 
     #[serde(skip_serializing_if = "Option::is_none")]
-    pub logit_bias: Option<String>,
+    pub logit_bias: Option<HashMap<usize, f32>>,
 
     #[serde(skip_serializing_if = "Option::is_none")]
     pub user: Option<String>,
@@ -78,18 +79,51 @@ impl Request {
         self
     }
 
+    pub fn n(mut self, n: Option<usize>) -> Self {
+        self.n = n;
+        self
+    }
+
     pub fn max_tokens(mut self, max_tokens: usize) -> Self {
         self.max_tokens = Some(max_tokens);
         self
     }
 
     pub fn temperature(mut self, temperature: f32) -> Self {
-        if temperature <= 2.0 || temperature >= 0.0 {
+        if (0.0..=2.0).contains(&temperature) {
             self.temperature = Some(temperature);
         }
 
         self
     }
+
+    pub fn top_p(mut self, top_p: Option<f32>) -> Result<Self, error::Error> {
+        self.top_p = sampling::top_p(top_p)?;
+        Ok(self)
+    }
+
+    pub fn stop(mut self, stop: Vec<String>) -> Result<Self, error::Error> {
+        self.stop = sampling::stop(stop)?;
+        Ok(self)
+    }
+
+    pub fn presence_penalty(mut self, presence_penalty: Option<f32>) -> Result<Self, error::Error> {
+        self.presence_penalty = sampling::presence_penalty(presence_penalty)?;
+        Ok(self)
+    }
+
+    pub fn frequency_penalty(
+        mut self,
+        frequency_penalty: Option<f32>,
+    ) -> Result<Self, error::Error> {
+        self.frequency_penalty = sampling::frequency_penalty(frequency_penalty)?;
+        Ok(self)
+    }
+
+    pub fn logit_bias(mut self, logit_bias: HashMap<usize, f32>) -> Result<Self, error::Error> {
+        self.logit_bias = sampling::logit_bias(logit_bias)?;
+        Ok(self)
+    }
 }
 
 #[derive(Debug, Deserialize)]
@@ -97,17 +131,30 @@ pub struct Response {
     pub id: String,
     pub object: Object,
     pub created: usize,
-    pub model: Model,
+
+    // Deployments report ids of their own, so this is kept as sent.
+    pub model: String,
     pub choices: Vec<Choice>,
     pub usage: Usage,
 }
 
+#[derive(Debug, Deserialize)]
+pub struct Chunk {
+    pub id: String,
+    pub object: Object,
+    pub created: usize,
+
+    // Deployments report ids of their own, so this is kept as sent.
+    pub model: String,
+    pub choices: Vec<Choice>,
+}
+
 #[derive(Debug, Deserialize)]
 pub struct Choice {
     pub text: String,
     pub index: usize,
     pub logprobs: Option<usize>,
-    pub fishish_reason: Option<FinishReason>,
+    pub finish_reason: Option<FinishReason>,
 }
 
 #[derive(Clone, Debug, Deserialize, Serialize)]
@@ -159,4 +206,11 @@ pub enum FinishReason {
 
     #[serde(rename = "stop")]
     Stop,
+
+    #[serde(rename = "content_filter")]
+    ContentFilter,
+
+    // Reasons introduced after this client was written.
+    #[serde(other)]
+    Other,
 }
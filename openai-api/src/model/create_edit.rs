@@ -16,7 +16,7 @@ pub struct Request {
     pub temperature: Option<f32>,
 
     #[serde(skip_serializing_if = "Option::is_none")]
-    pub top_p: Option<usize>,
+    pub top_p: Option<f32>,
 }
 
 impl Request {
@@ -30,6 +30,24 @@ impl Request {
             top_p: None,
         }
     }
+
+    pub fn temperature(mut self, temperature: f32) -> Self {
+        if (0.0..=2.0).contains(&temperature) {
+            self.temperature = Some(temperature);
+        }
+
+        self
+    }
+
+    pub fn top_p(mut self, top_p: Option<f32>) -> Self {
+        if let Some(top_p) = top_p {
+            if (0.0..=1.0).contains(&top_p) {
+                self.top_p = Some(top_p);
+            }
+        }
+
+        self
+    }
 }
 
 #[derive(Clone, Debug, Serialize)]
@@ -22,7 +22,9 @@ impl<'a> Request<'a> {
 pub struct Response {
     pub object: Object,
     pub data: Vec<Data>,
-    pub model: Model,
+
+    // Deployments report ids of their own, so this is kept as sent.
+    pub model: String,
     pub usage: Usage,
 }
 
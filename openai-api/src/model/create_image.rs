@@ -31,13 +31,23 @@ impl Request {
         }
     }
 
+    pub fn n(mut self, n: Option<usize>) -> Self {
+        self.n = n;
+        self
+    }
+
+    pub fn size(mut self, size: Option<Size>) -> Self {
+        self.size = size;
+        self
+    }
+
     pub fn response_format(mut self, response_format: ResponseFormat) -> Self {
         self.response_format = Some(response_format);
         self
     }
 }
 
-#[derive(Debug, Serialize)]
+#[derive(Clone, Debug, Serialize)]
 pub enum Size {
     #[serde(rename = "256x256")]
     _256x256_,
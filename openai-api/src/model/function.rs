@@ -1,7 +1,8 @@
-use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use crate::error;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::{collections::HashMap, fs, path};
 
-#[derive(Debug, Deserialize, Serialize)]
+#[derive(Clone, Debug, Deserialize, Serialize)]
 pub struct Function {
     pub name: String,
     pub description: String,
@@ -19,7 +20,12 @@ impl Function {
         }
     }
 
-    pub fn add_property(mut self, key: String, value: Parameter, required: bool) -> Self {
+    pub fn add_property(
+        mut self,
+        key: String,
+        value: Parameter,
+        required: bool,
+    ) -> Result<Self, error::Error> {
         match &mut self.parameters {
             Parameter::Object(obj) => {
                 obj.properties.insert(key.clone(), value);
@@ -28,39 +34,384 @@ impl Function {
                     obj.required.push(key)
                 }
             }
-            Parameter::String(_) => unreachable!(),
+            _ => {
+                return Err(error::Error::InvalidSchema(format!(
+                    "{}: parameters must be an object to add {}",
+                    self.name, key
+                )))
+            }
         }
 
-        self
+        Ok(self)
+    }
+
+    // The schema's `title` names the function and its `description` describes it, so a
+    // plain JSON Schema document can be used without wrapping it.
+    pub fn from_schema(schema: serde_json::Value) -> Result<Self, error::Error> {
+        if let Some(keyword) = unsupported_keyword(&schema) {
+            return Err(error::Error::InvalidSchema(format!(
+                "unsupported keyword {}",
+                keyword
+            )));
+        }
+
+        let name = schema
+            .get("title")
+            .and_then(|title| title.as_str())
+            .ok_or_else(|| error::Error::InvalidSchema(String::from("missing title")))?
+            .to_string();
+        let description = schema
+            .get("description")
+            .and_then(|description| description.as_str())
+            .unwrap_or_default()
+            .to_string();
+        let parameters = serde_json::from_value(schema)
+            .map_err(|error| error::Error::InvalidSchema(format!("{}: {}", name, error)))?;
+
+        let function = Self {
+            name,
+            description,
+            parameters,
+        };
+        function.validate()?;
+
+        Ok(function)
+    }
+
+    pub fn from_schema_file(path: impl AsRef<path::Path>) -> Result<Self, error::Error> {
+        let path = path.as_ref();
+        let content = fs::read_to_string(path).map_err(|error| {
+            error::Error::InvalidSchema(format!("{}: {}", path.display(), error))
+        })?;
+
+        Self::from_schema(serde_json::from_str(&content)?)
+    }
+
+    // Catches the mistakes the API rejects with a 400, before spending a request on them.
+    pub fn validate(&self) -> Result<(), error::Error> {
+        let valid_name = (1..=64).contains(&self.name.len())
+            && self
+                .name
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-');
+
+        if !valid_name {
+            return Err(error::Error::InvalidSchema(format!(
+                "{}: names must be 1 to 64 letters, digits, underscores or dashes",
+                self.name
+            )));
+        }
+
+        match &self.parameters {
+            Parameter::Object(_) => self.parameters.validate(&self.name),
+            _ => Err(error::Error::InvalidSchema(format!(
+                "{}: parameters must be an object",
+                self.name
+            ))),
+        }
     }
 }
 
-#[derive(Debug, Deserialize, Serialize)]
-#[serde(tag = "type")]
+// Describes a function by the type its arguments deserialize into. With the `schemars`
+// feature any `JsonSchema` type gets this for free, named after its schema title.
+pub trait FunctionSchema {
+    fn function() -> Result<Function, error::Error>;
+}
+
+#[cfg(feature = "schemars")]
+impl<T: schemars::JsonSchema> FunctionSchema for T {
+    fn function() -> Result<Function, error::Error> {
+        // The API understands neither `$ref` nor `type` arrays, so nested types are inlined
+        // and optional fields are simply left out of `required`.
+        let settings = schemars::gen::SchemaSettings::draft07().with(|settings| {
+            settings.inline_subschemas = true;
+            settings.option_add_null_type = false;
+        });
+        let schema = settings.into_generator().into_root_schema_for::<T>();
+
+        Function::from_schema(serde_json::to_value(schema)?)
+    }
+}
+
+fn unsupported_keyword(schema: &serde_json::Value) -> Option<&'static str> {
+    const UNSUPPORTED: [&str; 4] = ["$ref", "allOf", "not", "if"];
+
+    match schema {
+        serde_json::Value::Object(map) => UNSUPPORTED
+            .into_iter()
+            .find(|keyword| map.contains_key(*keyword))
+            .or_else(|| {
+                map.iter()
+                    .find_map(|(key, value)| match (key.as_str(), value) {
+                        // Property names are user data, so only their schemas are inspected.
+                        ("properties", serde_json::Value::Object(properties)) => {
+                            properties.values().find_map(unsupported_keyword)
+                        }
+                        _ => unsupported_keyword(value),
+                    })
+            }),
+        serde_json::Value::Array(values) => values.iter().find_map(unsupported_keyword),
+        _ => None,
+    }
+}
+
+#[derive(Clone, Debug)]
 pub enum Parameter {
+    Object(JsonObject),
+    String(JsonString),
+    Number(JsonNumber),
+    Integer(JsonNumber),
+    Boolean(JsonBoolean),
+    Array(JsonArray),
+    Null(JsonNull),
+    OneOf(JsonComposite),
+    AnyOf(JsonComposite),
+}
+
+impl Parameter {
+    fn validate(&self, path: &str) -> Result<(), error::Error> {
+        let invalid = |message: &str| {
+            Err(error::Error::InvalidSchema(format!(
+                "{}: {}",
+                path, message
+            )))
+        };
+
+        match self {
+            Parameter::Object(obj) => {
+                if let Some(key) = obj
+                    .required
+                    .iter()
+                    .find(|key| !obj.properties.contains_key(*key))
+                {
+                    return invalid(&format!("required property {} is not defined", key));
+                }
+
+                if let Some(AdditionalProperties::Schema(schema)) = &obj.additional_properties {
+                    schema.validate(&format!("{}.additionalProperties", path))?;
+                }
+
+                obj.properties
+                    .iter()
+                    .try_for_each(|(key, value)| value.validate(&format!("{}.{}", path, key)))
+            }
+            Parameter::String(string) if is_empty(&string.r#enum) => invalid("enum is empty"),
+            Parameter::Number(number) | Parameter::Integer(number) if is_empty(&number.r#enum) => {
+                invalid("enum is empty")
+            }
+            Parameter::Integer(JsonNumber {
+                r#enum: Some(values),
+                ..
+            }) if values
+                .iter()
+                .any(|value| !value.is_i64() && !value.is_u64()) =>
+            {
+                invalid("enum holds a value that is not an integer")
+            }
+            Parameter::Boolean(boolean) if is_empty(&boolean.r#enum) => invalid("enum is empty"),
+            Parameter::Array(array) => match &array.items {
+                Some(items) => items.validate(&format!("{}[]", path)),
+                None => invalid("arrays must declare items"),
+            },
+            Parameter::OneOf(composite) | Parameter::AnyOf(composite) => {
+                if composite.variants.is_empty() {
+                    return invalid("needs at least one variant");
+                }
+
+                composite
+                    .variants
+                    .iter()
+                    .enumerate()
+                    .try_for_each(|(index, variant)| {
+                        variant.validate(&format!("{}[{}]", path, index))
+                    })
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+fn is_empty<T>(values: &Option<Vec<T>>) -> bool {
+    values.as_ref().is_some_and(|values| values.is_empty())
+}
+
+// Typed schemas are tagged by `type`, while `oneOf` and `anyOf` carry no type of their own.
+#[derive(Deserialize)]
+#[serde(tag = "type")]
+enum Typed {
     #[serde(rename = "object")]
     Object(JsonObject),
 
     #[serde(rename = "string")]
     String(JsonString),
+
+    #[serde(rename = "number")]
+    Number(JsonNumber),
+
+    #[serde(rename = "integer")]
+    Integer(JsonNumber),
+
+    #[serde(rename = "boolean")]
+    Boolean(JsonBoolean),
+
+    #[serde(rename = "array")]
+    Array(JsonArray),
+
+    #[serde(rename = "null")]
+    Null(JsonNull),
+}
+
+#[derive(Serialize)]
+#[serde(tag = "type")]
+enum TypedRef<'a> {
+    #[serde(rename = "object")]
+    Object(&'a JsonObject),
+
+    #[serde(rename = "string")]
+    String(&'a JsonString),
+
+    #[serde(rename = "number")]
+    Number(&'a JsonNumber),
+
+    #[serde(rename = "integer")]
+    Integer(&'a JsonNumber),
+
+    #[serde(rename = "boolean")]
+    Boolean(&'a JsonBoolean),
+
+    #[serde(rename = "array")]
+    Array(&'a JsonArray),
+
+    #[serde(rename = "null")]
+    Null(&'a JsonNull),
+}
+
+#[derive(Deserialize, Serialize)]
+struct OneOf<V> {
+    #[serde(rename = "oneOf")]
+    variants: V,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    description: Option<String>,
+}
+
+#[derive(Deserialize, Serialize)]
+struct AnyOf<V> {
+    #[serde(rename = "anyOf")]
+    variants: V,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    description: Option<String>,
+}
+
+impl Serialize for Parameter {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        match self {
+            Parameter::Object(obj) => TypedRef::Object(obj).serialize(serializer),
+            Parameter::String(string) => TypedRef::String(string).serialize(serializer),
+            Parameter::Number(number) => TypedRef::Number(number).serialize(serializer),
+            Parameter::Integer(number) => TypedRef::Integer(number).serialize(serializer),
+            Parameter::Boolean(boolean) => TypedRef::Boolean(boolean).serialize(serializer),
+            Parameter::Array(array) => TypedRef::Array(array).serialize(serializer),
+            Parameter::Null(null) => TypedRef::Null(null).serialize(serializer),
+            Parameter::OneOf(composite) => OneOf {
+                variants: &composite.variants,
+                description: composite.description.clone(),
+            }
+            .serialize(serializer),
+            Parameter::AnyOf(composite) => AnyOf {
+                variants: &composite.variants,
+                description: composite.description.clone(),
+            }
+            .serialize(serializer),
+        }
+    }
 }
 
-#[derive(Debug, Deserialize, Serialize)]
+impl<'de> Deserialize<'de> for Parameter {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let value = serde_json::Value::deserialize(deserializer)?;
+
+        let parameter = if value.get("oneOf").is_some() {
+            serde_json::from_value::<OneOf<Vec<Parameter>>>(value).map(|one_of| {
+                Parameter::OneOf(JsonComposite::new(one_of.description, one_of.variants))
+            })
+        } else if value.get("anyOf").is_some() {
+            serde_json::from_value::<AnyOf<Vec<Parameter>>>(value).map(|any_of| {
+                Parameter::AnyOf(JsonComposite::new(any_of.description, any_of.variants))
+            })
+        } else {
+            serde_json::from_value::<Typed>(value).map(|typed| match typed {
+                Typed::Object(obj) => Parameter::Object(obj),
+                Typed::String(string) => Parameter::String(string),
+                Typed::Number(number) => Parameter::Number(number),
+                Typed::Integer(number) => Parameter::Integer(number),
+                Typed::Boolean(boolean) => Parameter::Boolean(boolean),
+                Typed::Array(array) => Parameter::Array(array),
+                Typed::Null(null) => Parameter::Null(null),
+            })
+        };
+
+        parameter.map_err(serde::de::Error::custom)
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
 pub struct JsonObject {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+
+    #[serde(default)]
     pub properties: HashMap<String, Parameter>,
+
+    #[serde(default)]
     pub required: Vec<String>,
+
+    #[serde(
+        rename = "additionalProperties",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub additional_properties: Option<AdditionalProperties>,
 }
 
 impl JsonObject {
     pub fn new(properties: HashMap<String, Parameter>, required: Vec<String>) -> Self {
         Self {
+            description: None,
             properties,
             required,
+            additional_properties: None,
         }
     }
+
+    pub fn description(mut self, description: Option<String>) -> Self {
+        self.description = description;
+        self
+    }
+
+    pub fn additional_properties(
+        mut self,
+        additional_properties: Option<AdditionalProperties>,
+    ) -> Self {
+        self.additional_properties = additional_properties;
+        self
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(untagged)]
+pub enum AdditionalProperties {
+    Allowed(bool),
+    Schema(Box<Parameter>),
 }
 
-#[derive(Debug, Deserialize, Serialize)]
+#[derive(Clone, Debug, Deserialize, Serialize)]
 pub struct JsonString {
     #[serde(skip_serializing_if = "Option::is_none")]
     pub description: Option<String>,
@@ -77,3 +428,248 @@ impl JsonString {
         }
     }
 }
+
+// Shared by `number` and `integer`.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct JsonNumber {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub r#enum: Option<Vec<serde_json::Number>>,
+}
+
+impl JsonNumber {
+    pub fn new(description: Option<String>, r#enum: Option<Vec<serde_json::Number>>) -> Self {
+        Self {
+            description,
+            r#enum,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct JsonBoolean {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub r#enum: Option<Vec<bool>>,
+}
+
+impl JsonBoolean {
+    pub fn new(description: Option<String>, r#enum: Option<Vec<bool>>) -> Self {
+        Self {
+            description,
+            r#enum,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct JsonArray {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub items: Option<Box<Parameter>>,
+}
+
+impl JsonArray {
+    pub fn new(description: Option<String>, items: Parameter) -> Self {
+        Self {
+            description,
+            items: Some(Box::new(items)),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct JsonNull {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+}
+
+impl JsonNull {
+    pub fn new(description: Option<String>) -> Self {
+        Self { description }
+    }
+}
+
+// The variants of a `oneOf` or `anyOf`.
+#[derive(Clone, Debug)]
+pub struct JsonComposite {
+    pub description: Option<String>,
+    pub variants: Vec<Parameter>,
+}
+
+impl JsonComposite {
+    pub fn new(description: Option<String>, variants: Vec<Parameter>) -> Self {
+        Self {
+            description,
+            variants,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn schema(properties: serde_json::Value, required: serde_json::Value) -> serde_json::Value {
+        json!({
+            "title": "get_weather",
+            "description": "Looks up the weather",
+            "type": "object",
+            "properties": properties,
+            "required": required,
+        })
+    }
+
+    fn error(schema: serde_json::Value) -> String {
+        match Function::from_schema(schema) {
+            Ok(function) => panic!("accepted {:?}", function),
+            Err(error) => error.to_string(),
+        }
+    }
+
+    #[test]
+    fn parameter_round_trips_through_json() {
+        let parameters = json!({
+            "type": "object",
+            "description": "Where and how",
+            "properties": {
+                "city": {"type": "string", "description": "The city"},
+                "unit": {"type": "string", "enum": ["celsius", "fahrenheit"]},
+                "days": {"type": "integer", "enum": [1, 3, 7]},
+                "hourly": {"type": "boolean"},
+                "tags": {"type": "array", "items": {"type": "string"}},
+                "at": {"oneOf": [{"type": "string"}, {"type": "null"}]},
+                "near": {"anyOf": [{"type": "number"}, {"type": "string"}], "description": "Coordinates or a place"},
+            },
+            "required": ["city"],
+            "additionalProperties": false,
+        });
+
+        let parameter: Parameter = serde_json::from_value(parameters.clone()).unwrap();
+
+        assert!(matches!(parameter, Parameter::Object(_)));
+        assert_eq!(serde_json::to_value(&parameter).unwrap(), parameters);
+    }
+
+    #[test]
+    fn schema_becomes_a_function() {
+        let function =
+            Function::from_schema(schema(json!({"city": {"type": "string"}}), json!(["city"])))
+                .unwrap();
+
+        assert_eq!(function.name, "get_weather");
+        assert_eq!(function.description, "Looks up the weather");
+        assert_eq!(
+            serde_json::to_value(&function.parameters).unwrap()["required"],
+            json!(["city"])
+        );
+    }
+
+    #[test]
+    fn required_property_must_be_defined() {
+        let error = error(schema(
+            json!({"city": {"type": "string"}}),
+            json!(["country"]),
+        ));
+
+        assert_eq!(
+            error,
+            "Invalid schema: get_weather: required property country is not defined"
+        );
+    }
+
+    #[test]
+    fn schema_needs_a_title() {
+        let mut schema = schema(json!({}), json!([]));
+        schema.as_object_mut().unwrap().remove("title");
+
+        assert_eq!(error(schema), "Invalid schema: missing title");
+    }
+
+    #[test]
+    fn wrong_types_are_rejected() {
+        let unknown = error(schema(json!({"when": {"type": "date"}}), json!([])));
+        let not_an_object = error(json!({"title": "get_weather", "type": "string"}));
+        let required_not_a_list = error(schema(json!({"city": {"type": "string"}}), json!("city")));
+        let array_without_items = error(schema(json!({"tags": {"type": "array"}}), json!([])));
+
+        assert!(unknown.starts_with("Invalid schema: get_weather: "));
+        assert_eq!(
+            not_an_object,
+            "Invalid schema: get_weather: parameters must be an object"
+        );
+        assert!(required_not_a_list.starts_with("Invalid schema: get_weather: "));
+        assert_eq!(
+            array_without_items,
+            "Invalid schema: get_weather.tags: arrays must declare items"
+        );
+    }
+
+    #[test]
+    fn enums_must_match_their_type() {
+        let fractional = error(schema(
+            json!({"days": {"type": "integer", "enum": [1, 1.5]}}),
+            json!([]),
+        ));
+        let mixed = error(schema(
+            json!({"unit": {"type": "string", "enum": ["celsius", 5]}}),
+            json!([]),
+        ));
+        let empty = error(schema(
+            json!({"unit": {"type": "string", "enum": []}}),
+            json!([]),
+        ));
+
+        assert_eq!(
+            fractional,
+            "Invalid schema: get_weather.days: enum holds a value that is not an integer"
+        );
+        assert!(mixed.starts_with("Invalid schema: get_weather: "));
+        assert_eq!(empty, "Invalid schema: get_weather.unit: enum is empty");
+    }
+
+    #[test]
+    fn unsupported_keywords_are_rejected() {
+        for (keyword, value) in [
+            ("$ref", json!("#/definitions/City")),
+            ("allOf", json!([{"type": "string"}])),
+            ("not", json!({"type": "null"})),
+            ("if", json!({"type": "string"})),
+        ] {
+            let mut city = json!({"type": "string"});
+            city[keyword] = value;
+
+            assert_eq!(
+                error(schema(json!({"city": city}), json!([]))),
+                format!("Invalid schema: unsupported keyword {}", keyword)
+            );
+        }
+    }
+
+    #[test]
+    fn property_names_may_match_keywords() {
+        let function = Function::from_schema(schema(
+            json!({"not": {"type": "boolean"}, "if": {"type": "string"}}),
+            json!(["not"]),
+        ));
+
+        assert!(function.is_ok());
+    }
+
+    #[test]
+    fn names_are_checked() {
+        let function = Function::new(String::from("get weather"), String::new());
+
+        assert!(function.validate().is_err());
+        assert!(Function::new(String::from("get_weather"), String::new())
+            .validate()
+            .is_ok());
+    }
+}
@@ -6,5 +6,7 @@ pub mod create_image;
 pub mod function;
 pub mod list_files;
 pub mod list_models;
+#[allow(clippy::module_inception)]
 pub mod model;
 pub mod object;
+pub mod sampling;
@@ -17,6 +17,9 @@ pub enum Object {
     #[serde(rename = "chat.completion")]
     ChatCompletion,
 
+    #[serde(rename = "chat.completion.chunk")]
+    ChatCompletionChunk,
+
     #[serde(rename = "file")]
     File,
 
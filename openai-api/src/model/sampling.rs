@@ -0,0 +1,114 @@
+use crate::error;
+use std::collections::HashMap;
+
+// Checks for the sampling parameters chat and completion requests share. Each takes what
+// the caller was given and returns what belongs in the request, or an error naming the
+// value the API would refuse.
+
+const MAX_STOP: usize = 4;
+
+pub fn top_p(top_p: Option<f32>) -> Result<Option<f32>, error::Error> {
+    within("top_p", top_p, 0.0, 1.0)
+}
+
+pub fn presence_penalty(presence_penalty: Option<f32>) -> Result<Option<f32>, error::Error> {
+    within("presence_penalty", presence_penalty, -2.0, 2.0)
+}
+
+pub fn frequency_penalty(frequency_penalty: Option<f32>) -> Result<Option<f32>, error::Error> {
+    within("frequency_penalty", frequency_penalty, -2.0, 2.0)
+}
+
+// No stop sequences leaves the parameter out.
+pub fn stop(stop: Vec<String>) -> Result<Option<Vec<String>>, error::Error> {
+    match stop.len() {
+        0 => Ok(None),
+        1..=MAX_STOP => Ok(Some(stop)),
+        count => Err(error::Error::InvalidArgument(format!(
+            "expected at most {} stop sequences, got {}",
+            MAX_STOP, count
+        ))),
+    }
+}
+
+// Maps token ids to a bias from -100 to 100. An empty map leaves the parameter out.
+pub fn logit_bias(
+    logit_bias: HashMap<usize, f32>,
+) -> Result<Option<HashMap<usize, f32>>, error::Error> {
+    for (token, bias) in &logit_bias {
+        if !(-100.0..=100.0).contains(bias) {
+            return Err(error::Error::InvalidArgument(format!(
+                "expected a bias from -100 to 100 for token {}, got {}",
+                token, bias
+            )));
+        }
+    }
+
+    Ok(Some(logit_bias).filter(|logit_bias| !logit_bias.is_empty()))
+}
+
+fn within(name: &str, value: Option<f32>, min: f32, max: f32) -> Result<Option<f32>, error::Error> {
+    match value {
+        Some(value) if !(min..=max).contains(&value) => Err(error::Error::InvalidArgument(
+            format!("expected {} from {} to {}, got {}", name, min, max, value),
+        )),
+        value => Ok(value),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keeps_values_in_range() {
+        assert_eq!(top_p(Some(0.0)).unwrap(), Some(0.0));
+        assert_eq!(top_p(Some(1.0)).unwrap(), Some(1.0));
+        assert_eq!(top_p(None).unwrap(), None);
+        assert_eq!(presence_penalty(Some(-2.0)).unwrap(), Some(-2.0));
+        assert_eq!(frequency_penalty(Some(2.0)).unwrap(), Some(2.0));
+    }
+
+    #[test]
+    fn rejects_values_out_of_range() {
+        for result in [
+            top_p(Some(1.5)),
+            top_p(Some(-0.1)),
+            top_p(Some(f32::NAN)),
+            presence_penalty(Some(2.5)),
+            frequency_penalty(Some(-3.0)),
+        ] {
+            assert!(matches!(result, Err(error::Error::InvalidArgument(_))));
+        }
+
+        assert_eq!(
+            top_p(Some(1.5)).unwrap_err().to_string(),
+            "Invalid argument: expected top_p from 0 to 1, got 1.5"
+        );
+    }
+
+    #[test]
+    fn rejects_more_than_four_stop_sequences() {
+        let sequences = |count| (0..count).map(|i| i.to_string()).collect::<Vec<_>>();
+
+        assert_eq!(stop(sequences(0)).unwrap(), None);
+        assert_eq!(stop(sequences(4)).unwrap(), Some(sequences(4)));
+        assert!(matches!(
+            stop(sequences(5)),
+            Err(error::Error::InvalidArgument(_))
+        ));
+    }
+
+    #[test]
+    fn rejects_biases_out_of_range() {
+        assert_eq!(logit_bias(HashMap::new()).unwrap(), None);
+        assert_eq!(
+            logit_bias(HashMap::from([(50256, -100.0)])).unwrap(),
+            Some(HashMap::from([(50256, -100.0)]))
+        );
+        assert!(matches!(
+            logit_bias(HashMap::from([(50256, -100.0), (1, 100.5)])),
+            Err(error::Error::InvalidArgument(_))
+        ));
+    }
+}
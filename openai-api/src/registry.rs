@@ -0,0 +1,235 @@
+use crate::{error, model};
+use futures::future::BoxFuture;
+use serde::{de::DeserializeOwned, Serialize};
+use std::{collections::HashMap, future::Future};
+
+type Handler = Box<
+    dyn Fn(serde_json::Value) -> BoxFuture<'static, Result<String, error::Error>> + Send + Sync,
+>;
+
+// Maps function names to typed async handlers, so a `FunctionCall` from the model can be
+// answered with the `Role::Function` message the next request needs.
+#[derive(Default)]
+pub struct FunctionRegistry {
+    functions: Vec<model::function::Function>,
+    handlers: HashMap<String, Handler>,
+}
+
+impl FunctionRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn register<T, F, Fut, R>(mut self, handler: F) -> Result<Self, error::Error>
+    where
+        T: model::function::FunctionSchema + DeserializeOwned + Send + 'static,
+        F: Fn(T) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Result<R, error::Error>> + Send + 'static,
+        R: Serialize,
+    {
+        let function = T::function()?;
+
+        let handler: Handler = Box::new(move |arguments| {
+            let arguments = serde_json::from_value::<T>(arguments);
+            let output = arguments.map(&handler);
+
+            Box::pin(async move {
+                let output = serde_json::to_value(output?.await?)?;
+
+                // Plain strings go to the model as is rather than as quoted JSON.
+                Ok(match output {
+                    serde_json::Value::String(output) => output,
+                    output => output.to_string(),
+                })
+            })
+        });
+
+        self.handlers.insert(function.name.clone(), handler);
+        self.functions
+            .retain(|existing| existing.name != function.name);
+        self.functions.push(function);
+
+        Ok(self)
+    }
+
+    pub fn functions(&self) -> Vec<model::function::Function> {
+        self.functions.clone()
+    }
+
+    pub async fn call(
+        &self,
+        function_call: &model::create_chat::FunctionCall,
+    ) -> Result<model::create_chat::Message, error::Error> {
+        let handler = self
+            .handlers
+            .get(function_call.name())
+            .ok_or_else(|| error::Error::UnknownFunction(function_call.name().to_string()))?;
+
+        let arguments = serde_json::Value::Object(
+            function_call
+                .arguments()
+                .map(|arguments| arguments.clone().into_iter().collect())
+                .unwrap_or_default(),
+        );
+
+        Ok(model::create_chat::Message {
+            role: model::create_chat::Role::Function,
+            content: Some(handler(arguments).await?),
+            name: Some(function_call.name().to_string()),
+            function_call: None,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde::Deserialize;
+    use serde_json::json;
+
+    #[derive(Deserialize)]
+    struct Add {
+        a: i64,
+        b: i64,
+    }
+
+    impl model::function::FunctionSchema for Add {
+        fn function() -> Result<model::function::Function, error::Error> {
+            model::function::Function::from_schema(json!({
+                "title": "add",
+                "description": "Adds two numbers",
+                "type": "object",
+                "properties": {"a": {"type": "integer"}, "b": {"type": "integer"}},
+                "required": ["a", "b"],
+            }))
+        }
+    }
+
+    #[derive(Deserialize)]
+    struct Greet {
+        name: String,
+    }
+
+    impl model::function::FunctionSchema for Greet {
+        fn function() -> Result<model::function::Function, error::Error> {
+            model::function::Function::from_schema(json!({
+                "title": "greet",
+                "type": "object",
+                "properties": {"name": {"type": "string"}},
+                "required": ["name"],
+            }))
+        }
+    }
+
+    fn registry() -> FunctionRegistry {
+        FunctionRegistry::new()
+            .register(|add: Add| async move { Ok(json!({"sum": add.a + add.b})) })
+            .unwrap()
+            .register(|greet: Greet| async move { Ok(format!("Hello, {}!", greet.name)) })
+            .unwrap()
+    }
+
+    fn call(name: &str, arguments: &str) -> model::create_chat::FunctionCall {
+        model::create_chat::FunctionCall::new(String::from(name), arguments).unwrap()
+    }
+
+    #[tokio::test]
+    async fn known_function_answers_with_a_function_message() {
+        let registry = registry();
+
+        let sum = registry
+            .call(&call("add", r#"{"a": 2, "b": 3}"#))
+            .await
+            .unwrap();
+        let greeting = registry
+            .call(&call("greet", r#"{"name": "Ada"}"#))
+            .await
+            .unwrap();
+
+        assert_eq!(sum.role, model::create_chat::Role::Function);
+        assert_eq!(sum.name.as_deref(), Some("add"));
+        assert_eq!(sum.content.as_deref(), Some(r#"{"sum":5}"#));
+        assert_eq!(greeting.content.as_deref(), Some("Hello, Ada!"));
+    }
+
+    #[tokio::test]
+    async fn unknown_function_is_an_error() {
+        let error = registry().call(&call("subtract", "{}")).await.unwrap_err();
+
+        assert!(matches!(error, error::Error::UnknownFunction(name) if name == "subtract"));
+    }
+
+    #[tokio::test]
+    async fn arguments_that_do_not_fit_are_an_error() {
+        let registry = registry();
+
+        let wrong_type = registry
+            .call(&call("add", r#"{"a": "two", "b": 3}"#))
+            .await
+            .unwrap_err();
+        let missing = registry
+            .call(&call("add", r#"{"a": 2}"#))
+            .await
+            .unwrap_err();
+
+        assert!(matches!(wrong_type, error::Error::JsonSerialization(_)));
+        assert!(matches!(missing, error::Error::JsonSerialization(_)));
+    }
+
+    #[test]
+    fn reregistering_replaces_the_function() {
+        let registry = registry()
+            .register(|add: Add| async move { Ok(add.a - add.b) })
+            .unwrap();
+
+        let names: Vec<String> = registry
+            .functions()
+            .into_iter()
+            .map(|function| function.name)
+            .collect();
+
+        assert_eq!(names, ["greet", "add"]);
+    }
+
+    #[cfg(feature = "schemars")]
+    #[test]
+    fn derived_schema_becomes_a_function() {
+        #[allow(dead_code)]
+        #[derive(Deserialize, schemars::JsonSchema)]
+        #[serde(rename = "get_weather")]
+        #[schemars(description = "Looks up the weather")]
+        struct GetWeather {
+            #[schemars(description = "The city")]
+            city: String,
+            unit: Option<Unit>,
+            days: Vec<u8>,
+        }
+
+        #[derive(Deserialize, schemars::JsonSchema)]
+        #[serde(rename_all = "lowercase")]
+        enum Unit {
+            Celsius,
+            Fahrenheit,
+        }
+
+        let function = <GetWeather as model::function::FunctionSchema>::function().unwrap();
+
+        assert_eq!(
+            serde_json::to_value(&function).unwrap(),
+            json!({
+                "name": "get_weather",
+                "description": "Looks up the weather",
+                "parameters": {
+                    "type": "object",
+                    "description": "Looks up the weather",
+                    "properties": {
+                        "city": {"type": "string", "description": "The city"},
+                        "unit": {"type": "string", "enum": ["celsius", "fahrenheit"]},
+                        "days": {"type": "array", "items": {"type": "integer"}},
+                    },
+                    "required": ["city", "days"],
+                },
+            })
+        );
+    }
+}
@@ -0,0 +1,314 @@
+use crate::{error, model, ApiResponse, Datasource, EventStream};
+use async_trait::async_trait;
+use rand::Rng;
+use std::{future::Future, time};
+
+#[derive(Clone, Debug)]
+pub struct RetryPolicy {
+    pub max_attempts: usize,
+    pub initial_backoff: time::Duration,
+    pub max_backoff: time::Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: 3,
+            initial_backoff: time::Duration::from_millis(500),
+            max_backoff: time::Duration::from_secs(60),
+        }
+    }
+}
+
+impl RetryPolicy {
+    pub fn max_attempts(mut self, max_attempts: usize) -> Self {
+        self.max_attempts = max_attempts.max(1);
+        self
+    }
+
+    pub fn initial_backoff(mut self, initial_backoff: time::Duration) -> Self {
+        self.initial_backoff = initial_backoff;
+        self
+    }
+
+    pub fn max_backoff(mut self, max_backoff: time::Duration) -> Self {
+        self.max_backoff = max_backoff;
+        self
+    }
+
+    // Honors a server-provided delay up to the maximum backoff, otherwise doubles the backoff per attempt with
+    // equal jitter so that concurrent callers spread out.
+    fn delay(&self, attempt: usize, retry_after: Option<time::Duration>) -> time::Duration {
+        if let Some(retry_after) = retry_after {
+            return retry_after.min(self.max_backoff);
+        }
+
+        let exponent = u32::try_from(attempt.saturating_sub(1)).unwrap_or(u32::MAX);
+        let backoff = self
+            .initial_backoff
+            .saturating_mul(2_u32.saturating_pow(exponent))
+            .min(self.max_backoff);
+        let half = backoff / 2;
+
+        half + half.mul_f64(rand::thread_rng().gen::<f64>())
+    }
+}
+
+pub struct Retry<D> {
+    inner: D,
+    policy: RetryPolicy,
+}
+
+impl<D> Retry<D> {
+    pub fn new(inner: D, policy: RetryPolicy) -> Self {
+        Self { inner, policy }
+    }
+
+    pub fn into_inner(self) -> D {
+        self.inner
+    }
+
+    async fn retry<T, F, Fut>(&self, call: F) -> Result<T, error::Error>
+    where
+        F: FnMut() -> Fut + Send,
+        Fut: Future<Output = Result<T, error::Error>> + Send,
+        T: Send,
+    {
+        self.attempt(error::Error::is_retryable, call).await
+    }
+
+    async fn retry_post<T, F, Fut>(&self, call: F) -> Result<T, error::Error>
+    where
+        F: FnMut() -> Fut + Send,
+        Fut: Future<Output = Result<T, error::Error>> + Send,
+        T: Send,
+    {
+        self.attempt(error::Error::is_retryable_post, call).await
+    }
+
+    async fn attempt<T, F, Fut>(
+        &self,
+        retryable: fn(&error::Error) -> bool,
+        mut call: F,
+    ) -> Result<T, error::Error>
+    where
+        F: FnMut() -> Fut + Send,
+        Fut: Future<Output = Result<T, error::Error>> + Send,
+        T: Send,
+    {
+        let mut attempt = 1;
+
+        loop {
+            match call().await {
+                Err(error) if retryable(&error) && attempt < self.policy.max_attempts => {
+                    tokio::time::sleep(self.policy.delay(attempt, error.retry_after())).await;
+                    attempt += 1;
+                }
+                result => return result,
+            }
+        }
+    }
+}
+
+#[async_trait]
+impl<D> Datasource for Retry<D>
+where
+    D: Datasource + Send + Sync,
+{
+    async fn list_models(&self) -> Result<model::list_models::Response, error::Error> {
+        self.retry(|| self.inner.list_models()).await
+    }
+
+    async fn create_completion(
+        &self,
+        request: &model::create_completion::Request,
+    ) -> Result<model::create_completion::Response, error::Error> {
+        self.retry_post(|| self.inner.create_completion(request))
+            .await
+    }
+
+    async fn create_completion_stream(
+        &self,
+        request: &model::create_completion::Request,
+    ) -> Result<EventStream<model::create_completion::Choice>, error::Error> {
+        self.retry_post(|| self.inner.create_completion_stream(request))
+            .await
+    }
+
+    async fn create_chat(
+        &self,
+        request: &model::create_chat::Request,
+    ) -> Result<model::create_chat::Response, error::Error> {
+        self.retry_post(|| self.inner.create_chat(request)).await
+    }
+
+    async fn create_chat_stream(
+        &self,
+        request: &model::create_chat::Request,
+    ) -> Result<EventStream<model::create_chat::Chunk>, error::Error> {
+        self.retry_post(|| self.inner.create_chat_stream(request))
+            .await
+    }
+
+    async fn create_image(
+        &self,
+        request: &model::create_image::Request,
+    ) -> Result<model::create_image::Response, error::Error> {
+        self.retry_post(|| self.inner.create_image(request)).await
+    }
+
+    async fn create_edit(
+        &self,
+        request: &model::create_edit::Request,
+    ) -> Result<model::create_edit::Response, error::Error> {
+        self.retry_post(|| self.inner.create_edit(request)).await
+    }
+
+    async fn list_files(&self) -> Result<model::list_files::Response, error::Error> {
+        self.retry(|| self.inner.list_files()).await
+    }
+
+    async fn create_embedding(
+        &self,
+        request: &model::create_embedding::Request,
+    ) -> Result<model::create_embedding::Response, error::Error> {
+        self.retry_post(|| self.inner.create_embedding(request))
+            .await
+    }
+
+    async fn list_models_with_meta(
+        &self,
+    ) -> Result<ApiResponse<model::list_models::Response>, error::Error> {
+        self.retry(|| self.inner.list_models_with_meta()).await
+    }
+
+    async fn create_completion_with_meta(
+        &self,
+        request: &model::create_completion::Request,
+    ) -> Result<ApiResponse<model::create_completion::Response>, error::Error> {
+        self.retry_post(|| self.inner.create_completion_with_meta(request))
+            .await
+    }
+
+    async fn create_completion_stream_with_meta(
+        &self,
+        request: &model::create_completion::Request,
+    ) -> Result<ApiResponse<EventStream<model::create_completion::Choice>>, error::Error> {
+        self.retry_post(|| self.inner.create_completion_stream_with_meta(request))
+            .await
+    }
+
+    async fn create_chat_with_meta(
+        &self,
+        request: &model::create_chat::Request,
+    ) -> Result<ApiResponse<model::create_chat::Response>, error::Error> {
+        self.retry_post(|| self.inner.create_chat_with_meta(request))
+            .await
+    }
+
+    async fn create_chat_stream_with_meta(
+        &self,
+        request: &model::create_chat::Request,
+    ) -> Result<ApiResponse<EventStream<model::create_chat::Chunk>>, error::Error> {
+        self.retry_post(|| self.inner.create_chat_stream_with_meta(request))
+            .await
+    }
+
+    async fn create_image_with_meta(
+        &self,
+        request: &model::create_image::Request,
+    ) -> Result<ApiResponse<model::create_image::Response>, error::Error> {
+        self.retry_post(|| self.inner.create_image_with_meta(request))
+            .await
+    }
+
+    async fn create_edit_with_meta(
+        &self,
+        request: &model::create_edit::Request,
+    ) -> Result<ApiResponse<model::create_edit::Response>, error::Error> {
+        self.retry_post(|| self.inner.create_edit_with_meta(request))
+            .await
+    }
+
+    async fn list_files_with_meta(
+        &self,
+    ) -> Result<ApiResponse<model::list_files::Response>, error::Error> {
+        self.retry(|| self.inner.list_files_with_meta()).await
+    }
+
+    async fn create_embedding_with_meta(
+        &self,
+        request: &model::create_embedding::Request,
+    ) -> Result<ApiResponse<model::create_embedding::Response>, error::Error> {
+        self.retry_post(|| self.inner.create_embedding_with_meta(request))
+            .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    fn policy() -> RetryPolicy {
+        RetryPolicy::default()
+            .initial_backoff(time::Duration::ZERO)
+            .max_backoff(time::Duration::from_millis(5))
+    }
+
+    async fn calls(retry: &Retry<()>, post: bool, error: fn() -> error::Error) -> usize {
+        let calls = AtomicUsize::new(0);
+        let call = || async {
+            calls.fetch_add(1, Ordering::SeqCst);
+            Err::<(), _>(error())
+        };
+
+        let result = match post {
+            true => retry.retry_post(call).await,
+            false => retry.retry(call).await,
+        };
+
+        assert!(result.is_err());
+        calls.into_inner()
+    }
+
+    #[test]
+    fn server_delay_is_clamped_to_max_backoff() {
+        let policy = policy();
+
+        assert_eq!(
+            policy.delay(1, Some(time::Duration::from_secs(3600))),
+            time::Duration::from_millis(5)
+        );
+        assert_eq!(
+            policy.delay(1, Some(time::Duration::from_millis(1))),
+            time::Duration::from_millis(1)
+        );
+        assert!(policy.delay(30, None) <= time::Duration::from_millis(5));
+    }
+
+    #[tokio::test]
+    async fn timeouts_are_retried_for_gets_only() {
+        let retry = Retry::new((), policy());
+        let timeout = || error::Error::Timeout(String::from("timed out"));
+
+        assert_eq!(calls(&retry, false, timeout).await, 3);
+        assert_eq!(calls(&retry, true, timeout).await, 1);
+    }
+
+    #[tokio::test]
+    async fn connection_failures_are_retried_for_posts() {
+        let retry = Retry::new((), policy());
+        let refused = || error::Error::Connection(String::from("refused"));
+
+        assert_eq!(calls(&retry, true, refused).await, 3);
+    }
+
+    #[tokio::test]
+    async fn client_errors_are_not_retried() {
+        let retry = Retry::new((), policy());
+        let invalid = || error::Error::InvalidResponse(String::from("bad"));
+
+        assert_eq!(calls(&retry, false, invalid).await, 1);
+    }
+}
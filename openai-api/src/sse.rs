@@ -0,0 +1,268 @@
+use crate::error;
+use futures::{stream, Stream, StreamExt};
+use serde::de::DeserializeOwned;
+use std::{pin::Pin, time};
+
+type ByteStream = Pin<Box<dyn Stream<Item = Result<bytes::Bytes, reqwest::Error>> + Send>>;
+
+const DONE: &str = "[DONE]";
+
+struct State {
+    bytes: ByteStream,
+    idle: Option<time::Duration>,
+    buffer: Vec<u8>,
+    data: String,
+    eof: bool,
+}
+
+// Decodes a `text/event-stream` body into the JSON payloads of its `data:` fields,
+// stopping at the `[DONE]` sentinel the API sends after the last chunk. A stream that goes
+// quiet for longer than `idle` ends with a timeout, however long it has run before.
+pub fn events<T>(
+    response: reqwest::Response,
+    idle: Option<time::Duration>,
+) -> impl Stream<Item = Result<T, error::Error>>
+where
+    T: DeserializeOwned,
+{
+    decode(Box::pin(response.bytes_stream()), idle)
+}
+
+fn decode<T>(
+    bytes: ByteStream,
+    idle: Option<time::Duration>,
+) -> impl Stream<Item = Result<T, error::Error>>
+where
+    T: DeserializeOwned,
+{
+    let state = State {
+        bytes,
+        idle,
+        buffer: Vec::new(),
+        data: String::new(),
+        eof: false,
+    };
+
+    stream::try_unfold(state, |mut state| async move {
+        loop {
+            if let Some(position) = state.buffer.iter().position(|byte| *byte == b'\n') {
+                let line: Vec<u8> = state.buffer.drain(..=position).collect();
+                let line = String::from_utf8_lossy(&line);
+                let line = line.trim_end_matches(['\r', '\n']);
+
+                if line.is_empty() {
+                    if state.data.is_empty() {
+                        continue;
+                    }
+
+                    let data = std::mem::take(&mut state.data);
+
+                    if data == DONE {
+                        return Ok(None);
+                    }
+
+                    let event: T = serde_json::from_str(&data)?;
+
+                    return Ok(Some((event, state)));
+                }
+
+                if let Some(value) = line.strip_prefix("data:") {
+                    if !state.data.is_empty() {
+                        state.data.push('\n');
+                    }
+
+                    state
+                        .data
+                        .push_str(value.strip_prefix(' ').unwrap_or(value));
+                }
+
+                continue;
+            }
+
+            if state.eof {
+                return Ok(None);
+            }
+
+            let next = match state.idle {
+                Some(idle) => tokio::time::timeout(idle, state.bytes.next())
+                    .await
+                    .map_err(|_| {
+                        error::Error::Timeout(format!("no data from the stream for {:?}", idle))
+                    })?,
+                None => state.bytes.next().await,
+            };
+
+            match next {
+                Some(chunk) => state.buffer.extend_from_slice(&chunk?),
+                None => {
+                    state.eof = true;
+                    state.buffer.extend_from_slice(b"\n\n");
+                }
+            }
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::{
+        io::{AsyncReadExt, AsyncWriteExt},
+        net::TcpListener,
+    };
+
+    #[derive(Debug, serde::Deserialize, PartialEq)]
+    struct Event {
+        n: usize,
+    }
+
+    async fn collect(chunks: &[&'static str]) -> Vec<Result<Event, error::Error>> {
+        let chunks: Vec<Result<bytes::Bytes, reqwest::Error>> = chunks
+            .iter()
+            .map(|chunk| Ok(bytes::Bytes::from_static(chunk.as_bytes())))
+            .collect();
+        let bytes = stream::iter(chunks);
+
+        decode(Box::pin(bytes), None).collect().await
+    }
+
+    async fn events_of(chunks: &[&'static str]) -> Vec<usize> {
+        collect(chunks)
+            .await
+            .into_iter()
+            .map(|event| event.unwrap().n)
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn data_split_across_chunks() {
+        let chunks = ["da", "ta: {\"n\"", ": 1}\n", "\ndata: {\"n\": 2}\n\n"];
+
+        assert_eq!(events_of(&chunks).await, vec![1, 2]);
+    }
+
+    #[tokio::test]
+    async fn multi_line_data_is_joined() {
+        let chunks = ["data: {\"n\":\ndata: 3}\n\n"];
+
+        assert_eq!(events_of(&chunks).await, vec![3]);
+    }
+
+    #[tokio::test]
+    async fn crlf_line_endings() {
+        let chunks = ["data: {\"n\": 4}\r\n\r", "\ndata: {\"n\": 5}\r\n\r\n"];
+
+        assert_eq!(events_of(&chunks).await, vec![4, 5]);
+    }
+
+    #[tokio::test]
+    async fn done_ends_the_stream() {
+        let chunks = ["data: {\"n\": 6}\n\ndata: [DONE]\n\ndata: {\"n\": 7}\n\n"];
+
+        assert_eq!(events_of(&chunks).await, vec![6]);
+    }
+
+    #[tokio::test]
+    async fn eof_without_trailing_blank_line() {
+        let chunks = ["data: {\"n\": 8}\n\ndata: {\"n\": 9}"];
+
+        assert_eq!(events_of(&chunks).await, vec![8, 9]);
+    }
+
+    #[tokio::test]
+    async fn comments_and_other_fields_are_ignored() {
+        let chunks = [": keep-alive\n\nevent: message\nid: 1\ndata: {\"n\": 10}\n\n"];
+
+        assert_eq!(events_of(&chunks).await, vec![10]);
+    }
+
+    #[tokio::test]
+    async fn invalid_json_is_an_error() {
+        let events = collect(&["data: {\"n\":\n\n"]).await;
+
+        assert_eq!(events.len(), 1);
+        assert!(events[0].is_err());
+    }
+
+    fn paced(chunks: &[&'static str], gap: std::time::Duration) -> ByteStream {
+        let chunks: Vec<Result<bytes::Bytes, reqwest::Error>> = chunks
+            .iter()
+            .map(|chunk| Ok(bytes::Bytes::from_static(chunk.as_bytes())))
+            .collect();
+
+        Box::pin(stream::iter(chunks).then(move |chunk| async move {
+            tokio::time::sleep(gap).await;
+            chunk
+        }))
+    }
+
+    #[tokio::test]
+    async fn slow_stream_outlives_the_idle_timeout() {
+        let chunks = ["data: {\"n\": 1}\n\n"; 6];
+        let bytes = paced(&chunks, std::time::Duration::from_millis(40));
+        let events: Vec<Result<Event, error::Error>> =
+            decode(bytes, Some(std::time::Duration::from_millis(150)))
+                .collect()
+                .await;
+
+        assert_eq!(events.len(), 6);
+        assert!(events.iter().all(Result::is_ok));
+    }
+
+    #[tokio::test]
+    async fn stalled_stream_times_out() {
+        let chunks = ["data: {\"n\": 1}\n\n", "data: {\"n\": 2}\n\n"];
+        let bytes = paced(&chunks, std::time::Duration::from_millis(200));
+        let events: Vec<Result<Event, error::Error>> =
+            decode(bytes, Some(std::time::Duration::from_millis(50)))
+                .collect()
+                .await;
+
+        assert_eq!(events.len(), 1);
+        assert!(matches!(events[0], Err(error::Error::Timeout(_))));
+    }
+
+    // Serves a response body in pieces over a real socket, as the API does.
+    #[tokio::test]
+    async fn stand_in_server() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+
+        tokio::spawn(async move {
+            let (mut socket, _) = listener.accept().await.unwrap();
+            let mut request = vec![];
+
+            while !request.ends_with(b"\r\n\r\n") {
+                request.push(socket.read_u8().await.unwrap());
+            }
+
+            let pieces = [
+                "HTTP/1.1 200 OK\r\ncontent-type: text/event-stream\r\nconnection: close\r\n\r\n",
+                "data: {\"n\"",
+                ": 11}\r\n\r\ndata: {\"n\": 12}\n\n",
+                "data: [DONE]\n\n",
+            ];
+
+            for piece in pieces {
+                socket.write_all(piece.as_bytes()).await.unwrap();
+                socket.flush().await.unwrap();
+                tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+            }
+        });
+
+        let response = reqwest::Client::builder()
+            .no_proxy()
+            .build()
+            .unwrap()
+            .get(format!("http://{}/", address))
+            .send()
+            .await
+            .unwrap();
+        let events: Vec<usize> = events::<Event>(response, None)
+            .map(|event| event.unwrap().n)
+            .collect()
+            .await;
+
+        assert_eq!(events, vec![11, 12]);
+    }
+}
@@ -0,0 +1,465 @@
+use crate::{error, model, Datasource, EventStream};
+use async_trait::async_trait;
+use futures::{stream, StreamExt};
+use model::object::Object;
+use serde::Serialize;
+use std::{collections::VecDeque, sync};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Method {
+    ListModels,
+    CreateCompletion,
+    CreateCompletionStream,
+    CreateChat,
+    CreateChatStream,
+    CreateImage,
+    CreateEdit,
+    ListFiles,
+    CreateEmbedding,
+}
+
+#[derive(Clone, Debug)]
+pub struct RecordedRequest {
+    pub method: Method,
+    pub body: Option<serde_json::Value>,
+}
+
+#[derive(Default)]
+struct Script {
+    list_models: VecDeque<Result<model::list_models::Response, error::Error>>,
+    create_completion: VecDeque<Result<model::create_completion::Response, error::Error>>,
+    create_completion_stream: VecDeque<Result<Vec<model::create_completion::Choice>, error::Error>>,
+    create_chat: VecDeque<Result<model::create_chat::Response, error::Error>>,
+    create_chat_stream: VecDeque<Result<Vec<model::create_chat::Chunk>, error::Error>>,
+    create_image: VecDeque<Result<model::create_image::Response, error::Error>>,
+    create_edit: VecDeque<Result<model::create_edit::Response, error::Error>>,
+    list_files: VecDeque<Result<model::list_files::Response, error::Error>>,
+    create_embedding: VecDeque<Result<model::create_embedding::Response, error::Error>>,
+    requests: Vec<RecordedRequest>,
+}
+
+// Serves scripted results in the order they were pushed, per method, and records every
+// request it receives. A call with nothing left in its script fails with `InvalidResponse`.
+#[derive(Default)]
+pub struct MockDatasource {
+    script: sync::Mutex<Script>,
+}
+
+impl MockDatasource {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn push_list_models(&self, result: Result<model::list_models::Response, error::Error>) {
+        self.script().list_models.push_back(result);
+    }
+
+    pub fn push_create_completion(
+        &self,
+        result: Result<model::create_completion::Response, error::Error>,
+    ) {
+        self.script().create_completion.push_back(result);
+    }
+
+    pub fn push_create_completion_stream(
+        &self,
+        result: Result<Vec<model::create_completion::Choice>, error::Error>,
+    ) {
+        self.script().create_completion_stream.push_back(result);
+    }
+
+    pub fn push_create_chat(&self, result: Result<model::create_chat::Response, error::Error>) {
+        self.script().create_chat.push_back(result);
+    }
+
+    pub fn push_create_chat_stream(
+        &self,
+        result: Result<Vec<model::create_chat::Chunk>, error::Error>,
+    ) {
+        self.script().create_chat_stream.push_back(result);
+    }
+
+    pub fn push_create_image(&self, result: Result<model::create_image::Response, error::Error>) {
+        self.script().create_image.push_back(result);
+    }
+
+    pub fn push_create_edit(&self, result: Result<model::create_edit::Response, error::Error>) {
+        self.script().create_edit.push_back(result);
+    }
+
+    pub fn push_list_files(&self, result: Result<model::list_files::Response, error::Error>) {
+        self.script().list_files.push_back(result);
+    }
+
+    pub fn push_create_embedding(
+        &self,
+        result: Result<model::create_embedding::Response, error::Error>,
+    ) {
+        self.script().create_embedding.push_back(result);
+    }
+
+    pub fn requests(&self) -> Vec<RecordedRequest> {
+        self.script().requests.clone()
+    }
+
+    pub fn requests_for(&self, method: Method) -> Vec<RecordedRequest> {
+        self.script()
+            .requests
+            .iter()
+            .filter(|request| request.method == method)
+            .cloned()
+            .collect()
+    }
+
+    fn script(&self) -> sync::MutexGuard<'_, Script> {
+        self.script
+            .lock()
+            .unwrap_or_else(|error| error.into_inner())
+    }
+
+    fn record(&self, method: Method, body: Option<&impl Serialize>) -> Result<(), error::Error> {
+        let body = body.map(serde_json::to_value).transpose()?;
+
+        self.script()
+            .requests
+            .push(RecordedRequest { method, body });
+
+        Ok(())
+    }
+}
+
+fn next<T>(
+    queue: &mut VecDeque<Result<T, error::Error>>,
+    method: Method,
+) -> Result<T, error::Error> {
+    queue.pop_front().unwrap_or_else(|| {
+        Err(error::Error::InvalidResponse(format!(
+            "no scripted response for {:?}",
+            method
+        )))
+    })
+}
+
+#[async_trait]
+impl Datasource for MockDatasource {
+    async fn list_models(&self) -> Result<model::list_models::Response, error::Error> {
+        self.record(Method::ListModels, None::<&()>)?;
+
+        next(&mut self.script().list_models, Method::ListModels)
+    }
+
+    async fn create_completion(
+        &self,
+        request: &model::create_completion::Request,
+    ) -> Result<model::create_completion::Response, error::Error> {
+        self.record(Method::CreateCompletion, Some(request))?;
+
+        next(
+            &mut self.script().create_completion,
+            Method::CreateCompletion,
+        )
+    }
+
+    async fn create_completion_stream(
+        &self,
+        request: &model::create_completion::Request,
+    ) -> Result<EventStream<model::create_completion::Choice>, error::Error> {
+        self.record(Method::CreateCompletionStream, Some(request))?;
+
+        let choices = next(
+            &mut self.script().create_completion_stream,
+            Method::CreateCompletionStream,
+        )?;
+
+        Ok(stream::iter(choices.into_iter().map(Ok)).boxed())
+    }
+
+    async fn create_chat(
+        &self,
+        request: &model::create_chat::Request,
+    ) -> Result<model::create_chat::Response, error::Error> {
+        self.record(Method::CreateChat, Some(request))?;
+
+        next(&mut self.script().create_chat, Method::CreateChat)
+    }
+
+    async fn create_chat_stream(
+        &self,
+        request: &model::create_chat::Request,
+    ) -> Result<EventStream<model::create_chat::Chunk>, error::Error> {
+        self.record(Method::CreateChatStream, Some(request))?;
+
+        let chunks = next(
+            &mut self.script().create_chat_stream,
+            Method::CreateChatStream,
+        )?;
+
+        Ok(stream::iter(chunks.into_iter().map(Ok)).boxed())
+    }
+
+    async fn create_image(
+        &self,
+        request: &model::create_image::Request,
+    ) -> Result<model::create_image::Response, error::Error> {
+        self.record(Method::CreateImage, Some(request))?;
+
+        next(&mut self.script().create_image, Method::CreateImage)
+    }
+
+    async fn create_edit(
+        &self,
+        request: &model::create_edit::Request,
+    ) -> Result<model::create_edit::Response, error::Error> {
+        self.record(Method::CreateEdit, Some(request))?;
+
+        next(&mut self.script().create_edit, Method::CreateEdit)
+    }
+
+    async fn list_files(&self) -> Result<model::list_files::Response, error::Error> {
+        self.record(Method::ListFiles, None::<&()>)?;
+
+        next(&mut self.script().list_files, Method::ListFiles)
+    }
+
+    async fn create_embedding(
+        &self,
+        request: &model::create_embedding::Request,
+    ) -> Result<model::create_embedding::Response, error::Error> {
+        self.record(Method::CreateEmbedding, Some(request))?;
+
+        next(&mut self.script().create_embedding, Method::CreateEmbedding)
+    }
+}
+
+fn created() -> usize {
+    usize::try_from(chrono::Utc::now().timestamp()).unwrap_or_default()
+}
+
+fn model_id(model: &impl Serialize) -> String {
+    serde_json::to_value(model)
+        .ok()
+        .and_then(|value| value.as_str().map(String::from))
+        .unwrap_or_default()
+}
+
+// Roughly four characters per token, which is close enough for English test fixtures.
+fn estimate_tokens(text: &str) -> usize {
+    text.chars().count().div_ceil(4)
+}
+
+pub fn chat_response(
+    model: model::create_chat::Model,
+    content: &str,
+) -> model::create_chat::Response {
+    let completion_tokens = estimate_tokens(content);
+
+    model::create_chat::Response {
+        id: String::from("chatcmpl-mock"),
+        object: Object::ChatCompletion,
+        created: created(),
+        model: model_id(&model),
+        choices: vec![model::create_chat::Choice {
+            index: 0,
+            message: model::create_chat::Message {
+                role: model::create_chat::Role::Assistant,
+                content: Some(content.to_string()),
+                name: None,
+                function_call: None,
+            },
+            finish_reason: Some(model::create_chat::FinishReason::Stop),
+        }],
+        usage: model::create_chat::Usage {
+            prompt_tokens: 0,
+            completion_tokens,
+            total_tokens: completion_tokens,
+        },
+    }
+}
+
+pub fn chat_function_call_response(
+    model: model::create_chat::Model,
+    name: &str,
+    arguments: &str,
+) -> Result<model::create_chat::Response, error::Error> {
+    let completion_tokens = estimate_tokens(name) + estimate_tokens(arguments);
+
+    Ok(model::create_chat::Response {
+        id: String::from("chatcmpl-mock"),
+        object: Object::ChatCompletion,
+        created: created(),
+        model: model_id(&model),
+        choices: vec![model::create_chat::Choice {
+            index: 0,
+            message: model::create_chat::Message {
+                role: model::create_chat::Role::Assistant,
+                content: None,
+                name: None,
+                function_call: Some(model::create_chat::FunctionCall::new(
+                    name.to_string(),
+                    arguments,
+                )?),
+            },
+            finish_reason: Some(model::create_chat::FinishReason::FunctionCall),
+        }],
+        usage: model::create_chat::Usage {
+            prompt_tokens: 0,
+            completion_tokens,
+            total_tokens: completion_tokens,
+        },
+    })
+}
+
+// Splits `fragments` into the chunk sequence the API streams: a role delta, one delta per
+// fragment, then an empty delta carrying the finish reason.
+pub fn chat_chunks(
+    model: model::create_chat::Model,
+    fragments: &[&str],
+) -> Vec<model::create_chat::Chunk> {
+    let created = created();
+    let chunk = |delta: model::create_chat::Delta, finish_reason| model::create_chat::Chunk {
+        id: String::from("chatcmpl-mock"),
+        object: Object::ChatCompletionChunk,
+        created,
+        model: model_id(&model),
+        choices: vec![model::create_chat::ChunkChoice {
+            index: 0,
+            delta,
+            finish_reason,
+        }],
+    };
+
+    let role = model::create_chat::Delta {
+        role: Some(model::create_chat::Role::Assistant),
+        ..Default::default()
+    };
+
+    std::iter::once(chunk(role, None))
+        .chain(fragments.iter().map(|fragment| {
+            let delta = model::create_chat::Delta {
+                content: Some(fragment.to_string()),
+                ..Default::default()
+            };
+
+            chunk(delta, None)
+        }))
+        .chain(std::iter::once(chunk(
+            Default::default(),
+            Some(model::create_chat::FinishReason::Stop),
+        )))
+        .collect()
+}
+
+pub fn completion_response(
+    model: model::create_completion::Model,
+    text: &str,
+) -> model::create_completion::Response {
+    let completion_tokens = estimate_tokens(text);
+
+    model::create_completion::Response {
+        id: String::from("cmpl-mock"),
+        object: Object::TextCompletion,
+        created: created(),
+        model: model_id(&model),
+        choices: vec![completion_choice(0, text)],
+        usage: model::create_completion::Usage {
+            prompt_tokens: 0,
+            completion_tokens,
+            total_tokens: completion_tokens,
+        },
+    }
+}
+
+pub fn completion_choice(index: usize, text: &str) -> model::create_completion::Choice {
+    model::create_completion::Choice {
+        text: text.to_string(),
+        index,
+        logprobs: None,
+        finish_reason: None,
+    }
+}
+
+pub fn edit_response(text: &str) -> model::create_edit::Response {
+    let completion_tokens = estimate_tokens(text);
+
+    model::create_edit::Response {
+        object: model::create_edit::Object::Edit,
+        created: created(),
+        choices: vec![model::create_edit::Choice {
+            text: text.to_string(),
+            index: 0,
+        }],
+        usage: model::create_edit::Usage {
+            prompt_tokens: 0,
+            completion_tokens,
+            total_tokens: completion_tokens,
+        },
+    }
+}
+
+pub fn embedding_response(
+    model: model::create_embedding::Model,
+    embeddings: Vec<Vec<f32>>,
+) -> model::create_embedding::Response {
+    model::create_embedding::Response {
+        object: Object::List,
+        data: embeddings
+            .into_iter()
+            .enumerate()
+            .map(|(index, embedding)| model::create_embedding::Data {
+                object: Object::Embedding,
+                embedding,
+                index,
+            })
+            .collect(),
+        model: model_id(&model),
+        usage: model::create_embedding::Usage {
+            prompt_tokens: 0,
+            total_tokens: 0,
+        },
+    }
+}
+
+pub fn image_response(urls: Vec<url::Url>) -> model::create_image::Response {
+    model::create_image::Response {
+        created: created(),
+        data: urls
+            .into_iter()
+            .map(|url| model::create_image::Data::Url(Some(url)))
+            .collect(),
+    }
+}
+
+pub fn models_response(ids: &[&str]) -> model::list_models::Response {
+    model::list_models::Response {
+        object: Object::List,
+        data: ids
+            .iter()
+            .map(|id| model::list_models::Model {
+                id: id.to_string(),
+                object: Object::Model,
+                created: created(),
+                owned_by: String::from("openai"),
+                permission: vec![],
+                root: id.to_string(),
+                parent: None,
+            })
+            .collect(),
+    }
+}
+
+pub fn files_response(files: &[(&str, &str)]) -> model::list_files::Response {
+    model::list_files::Response {
+        object: Object::List,
+        data: files
+            .iter()
+            .map(|(filename, purpose)| model::list_files::File {
+                id: format!("file-{}", filename),
+                object: Object::File,
+                bytes: 0,
+                created_at: created(),
+                filename: filename.to_string(),
+                purpose: purpose.to_string(),
+            })
+            .collect(),
+    }
+}
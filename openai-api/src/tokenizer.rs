@@ -0,0 +1,276 @@
+use crate::{error, model};
+use std::{fmt, str::FromStr, sync};
+use tiktoken_rs::CoreBPE;
+
+// Byte-level BPE over the vocabularies bundled with tiktoken-rs, so counting never needs the
+// network. Text is encoded as ordinary text: special tokens such as `<|endoftext|>` in the
+// input are split like any other text, which is how the API treats user content.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Encoding {
+    Cl100kBase,
+    P50kBase,
+    R50kBase,
+}
+
+static CL100K_BASE: sync::OnceLock<CoreBPE> = sync::OnceLock::new();
+static P50K_BASE: sync::OnceLock<CoreBPE> = sync::OnceLock::new();
+static R50K_BASE: sync::OnceLock<CoreBPE> = sync::OnceLock::new();
+
+impl Encoding {
+    pub fn encode(&self, text: &str) -> Vec<usize> {
+        self.bpe().encode_ordinary(text)
+    }
+
+    pub fn decode(&self, tokens: &[usize]) -> Result<String, error::Error> {
+        let (ordinary, end_of_text) = match self {
+            Self::Cl100kBase => (100256, 100257),
+            Self::P50kBase => (50281, 50256),
+            Self::R50kBase => (50256, 50256),
+        };
+
+        // The decoder panics on ranks it does not know, so they are rejected up front.
+        if let Some(token) = tokens
+            .iter()
+            .find(|token| **token >= ordinary && **token != end_of_text)
+        {
+            return Err(error::Error::Tokenizer(format!(
+                "token {} is not in {}",
+                token, self
+            )));
+        }
+
+        self.bpe()
+            .decode(tokens.to_vec())
+            .map_err(|error| error::Error::Tokenizer(error.to_string()))
+    }
+
+    pub fn count(&self, text: &str) -> usize {
+        self.encode(text).len()
+    }
+
+    // Counts what a chat request's messages cost as `prompt_tokens`: every message is wrapped
+    // in three tokens, a name costs one more, and the reply is primed with three. This is the
+    // accounting of the 0613 models; function definitions are not included.
+    pub fn count_messages(&self, messages: &[model::create_chat::Message]) -> usize {
+        let tokens: usize = messages
+            .iter()
+            .map(|message| {
+                let mut tokens = 3 + self.count(role(&message.role));
+
+                if let Some(content) = &message.content {
+                    tokens += self.count(content);
+                }
+
+                if let Some(name) = &message.name {
+                    tokens += self.count(name) + 1;
+                }
+
+                if let Some(function_call) = &message.function_call {
+                    let arguments = serde_json::to_string(
+                        &function_call.arguments().cloned().unwrap_or_default(),
+                    )
+                    .unwrap_or_default();
+
+                    tokens += self.count(function_call.name()) + self.count(&arguments) + 3;
+                }
+
+                tokens
+            })
+            .sum();
+
+        tokens + 3
+    }
+
+    fn bpe(&self) -> &'static CoreBPE {
+        let cell = match self {
+            Self::Cl100kBase => &CL100K_BASE,
+            Self::P50kBase => &P50K_BASE,
+            Self::R50kBase => &R50K_BASE,
+        };
+
+        // The vocabularies are compiled in, so loading them can only fail on a broken build.
+        cell.get_or_init(|| {
+            match self {
+                Self::Cl100kBase => tiktoken_rs::cl100k_base(),
+                Self::P50kBase => tiktoken_rs::p50k_base(),
+                Self::R50kBase => tiktoken_rs::r50k_base(),
+            }
+            .expect("bundled BPE vocabulary")
+        })
+    }
+}
+
+fn role(role: &model::create_chat::Role) -> &'static str {
+    match role {
+        model::create_chat::Role::System => "system",
+        model::create_chat::Role::Assistant => "assistant",
+        model::create_chat::Role::User => "user",
+        model::create_chat::Role::Function => "function",
+    }
+}
+
+impl FromStr for Encoding {
+    type Err = error::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "cl100k_base" => Ok(Self::Cl100kBase),
+            "p50k_base" => Ok(Self::P50kBase),
+            "r50k_base" => Ok(Self::R50kBase),
+            _ => Err(Self::Err::UnsupportedEncoding(s.to_string())),
+        }
+    }
+}
+
+impl fmt::Display for Encoding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Cl100kBase => "cl100k_base",
+            Self::P50kBase => "p50k_base",
+            Self::R50kBase => "r50k_base",
+        })
+    }
+}
+
+impl From<&model::create_chat::Model> for Encoding {
+    fn from(_: &model::create_chat::Model) -> Self {
+        Self::Cl100kBase
+    }
+}
+
+impl From<&model::create_completion::Model> for Encoding {
+    fn from(model: &model::create_completion::Model) -> Self {
+        match model {
+            model::create_completion::Model::TextDavinci003
+            | model::create_completion::Model::TextDavinci002 => Self::P50kBase,
+            model::create_completion::Model::TextCurie001
+            | model::create_completion::Model::TextBabbage001
+            | model::create_completion::Model::TextAda001 => Self::R50kBase,
+        }
+    }
+}
+
+impl From<&model::create_embedding::Model> for Encoding {
+    fn from(_: &model::create_embedding::Model) -> Self {
+        Self::Cl100kBase
+    }
+}
+
+// The edit models use p50k_edit, which only adds special tokens to p50k_base.
+impl From<&model::create_edit::Model> for Encoding {
+    fn from(_: &model::create_edit::Model) -> Self {
+        Self::P50kBase
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use model::create_chat::{Message, Role};
+
+    fn message(role: Role, name: Option<&str>, content: &str) -> Message {
+        Message {
+            role,
+            content: Some(String::from(content)),
+            name: name.map(String::from),
+            function_call: None,
+        }
+    }
+
+    // Counts published in OpenAI's cookbook on counting tokens with tiktoken.
+    #[test]
+    fn known_strings_count_as_published() {
+        assert_eq!(
+            Encoding::Cl100kBase.encode("tiktoken is great!"),
+            [83, 1609, 5963, 374, 2294, 0]
+        );
+        assert_eq!(
+            Encoding::Cl100kBase.count("antidisestablishmentarianism"),
+            6
+        );
+        assert_eq!(Encoding::P50kBase.count("antidisestablishmentarianism"), 5);
+        assert_eq!(Encoding::R50kBase.count("antidisestablishmentarianism"), 5);
+        assert_eq!(Encoding::Cl100kBase.count("2 + 2 = 4"), 7);
+        assert_eq!(Encoding::P50kBase.count("2 + 2 = 4"), 5);
+        assert_eq!(Encoding::Cl100kBase.count(""), 0);
+    }
+
+    #[test]
+    fn special_tokens_are_encoded_as_text() {
+        let tokens = Encoding::Cl100kBase.encode("<|endoftext|>");
+
+        assert!(tokens.len() > 1);
+        assert!(!tokens.contains(&100257));
+    }
+
+    #[test]
+    fn encode_and_decode_round_trip() {
+        for encoding in [Encoding::Cl100kBase, Encoding::P50kBase, Encoding::R50kBase] {
+            for text in ["Hello, world!", "fn main() {}\n", "naïve café 東京 🦀", ""] {
+                let tokens = encoding.encode(text);
+
+                assert_eq!(encoding.decode(&tokens).unwrap(), text, "{}", encoding);
+            }
+        }
+    }
+
+    #[test]
+    fn unknown_tokens_do_not_decode() {
+        assert!(Encoding::Cl100kBase.decode(&[100256]).is_err());
+        assert!(Encoding::R50kBase.decode(&[60000]).is_err());
+        assert_eq!(
+            Encoding::R50kBase.decode(&[50256]).unwrap(),
+            "<|endoftext|>"
+        );
+    }
+
+    // The cookbook's example conversation, which the API bills as 129 prompt tokens on
+    // gpt-3.5-turbo-0613 and gpt-4-0613.
+    #[test]
+    fn messages_count_as_the_api_bills_them() {
+        let messages = [
+            message(
+                Role::System,
+                None,
+                "You are a helpful, pattern-following assistant that translates corporate jargon into plain English.",
+            ),
+            message(
+                Role::System,
+                Some("example_user"),
+                "New synergies will help drive top-line growth.",
+            ),
+            message(
+                Role::System,
+                Some("example_assistant"),
+                "Things working well together will increase revenue.",
+            ),
+            message(
+                Role::System,
+                Some("example_user"),
+                "Let's circle back when we have more bandwidth to touch base on opportunities for increased leverage.",
+            ),
+            message(
+                Role::System,
+                Some("example_assistant"),
+                "Let's talk later when we're less busy about how to do better.",
+            ),
+            message(
+                Role::User,
+                None,
+                "This late pivot means we don't have time to boil the ocean for the client deliverable.",
+            ),
+        ];
+
+        assert_eq!(Encoding::Cl100kBase.count_messages(&messages), 129);
+        assert_eq!(Encoding::Cl100kBase.count_messages(&[]), 3);
+    }
+
+    #[test]
+    fn encoding_names_round_trip() {
+        for name in ["cl100k_base", "p50k_base", "r50k_base"] {
+            assert_eq!(name.parse::<Encoding>().unwrap().to_string(), name);
+        }
+
+        assert!("gpt2".parse::<Encoding>().is_err());
+    }
+}
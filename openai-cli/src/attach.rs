@@ -0,0 +1,293 @@
+use anyhow::{anyhow, Error};
+use openai_api::{model::create_chat, tokenizer::Encoding};
+use std::{fs, path};
+
+// Text files have no NUL bytes; looking at the start of a file is enough to tell.
+const SNIFF_BYTES: usize = 8000;
+
+pub struct File {
+    pub path: String,
+    pub tokens: usize,
+    block: String,
+}
+
+// Files waiting to go out with the next user message. Without an explicit budget they may
+// take up half of the model's context window.
+pub struct Attachments {
+    files: Vec<File>,
+    budget: Option<usize>,
+}
+
+impl Attachments {
+    pub fn new(budget: Option<usize>) -> Self {
+        Self {
+            files: vec![],
+            budget,
+        }
+    }
+
+    // Attaches a file, a directory or a glob. Directories and globs skip whatever
+    // .gitignore excludes, binary files are skipped with a warning, and nothing is attached
+    // when the files would go over the budget.
+    pub fn add(&mut self, pattern: &str, model: &create_chat::Model) -> Result<&[File], Error> {
+        let encoding = Encoding::from(model);
+        let budget = self.budget.unwrap_or(model.context_window() / 2);
+        let mut files = vec![];
+        let mut attached = false;
+
+        for path in expand(pattern)? {
+            // Overlapping globs would otherwise send a file twice.
+            if self
+                .files
+                .iter()
+                .any(|file| path::Path::new(&file.path) == path)
+            {
+                attached = true;
+                continue;
+            }
+
+            let bytes =
+                fs::read(&path).map_err(|error| anyhow!("{}: {}", path.display(), error))?;
+            let content = match String::from_utf8(bytes) {
+                Ok(content) if !content.as_bytes().iter().take(SNIFF_BYTES).any(|b| *b == 0) => {
+                    content
+                }
+                _ => {
+                    eprintln!(
+                        "{}",
+                        console::Style::new()
+                            .dim()
+                            .apply_to(format!("Skipped binary file {}", path.display()))
+                    );
+                    continue;
+                }
+            };
+
+            let path = path.display().to_string();
+            let block = block(&path, &content);
+
+            files.push(File {
+                path,
+                tokens: encoding.count(&block),
+                block,
+            });
+        }
+
+        if files.is_empty() && !attached {
+            return Err(anyhow!("no text files to attach in {}", pattern));
+        }
+
+        let total = self.tokens() + files.iter().map(|file| file.tokens).sum::<usize>();
+
+        if total > budget {
+            return Err(anyhow!(
+                "attaching {} would take the attachments to {} tokens, over the budget of {}",
+                pattern,
+                total,
+                budget
+            ));
+        }
+
+        let start = self.files.len();
+        self.files.extend(files);
+
+        Ok(&self.files[start..])
+    }
+
+    fn tokens(&self) -> usize {
+        self.files.iter().map(|file| file.tokens).sum()
+    }
+
+    // Puts the pending files ahead of the message text and clears them.
+    pub fn take(&mut self, content: String) -> String {
+        if self.files.is_empty() {
+            return content;
+        }
+
+        let mut message: Vec<String> = self.files.drain(..).map(|file| file.block).collect();
+        message.push(content);
+        message.join("\n\n")
+    }
+}
+
+// The fence is longer than any run of backticks in the file, so code that itself contains
+// fences cannot end the block early.
+fn block(path: &str, content: &str) -> String {
+    let longest = content
+        .split(|c| c != '`')
+        .map(str::len)
+        .max()
+        .unwrap_or_default();
+    let fence = "`".repeat(longest.max(2) + 1);
+
+    format!(
+        "File: {}\n{}\n{}\n{}",
+        path,
+        fence,
+        content.trim_end_matches('\n'),
+        fence
+    )
+}
+
+// A file named outright is attached as is; directories and globs are walked with the
+// ignore rules applied.
+fn expand(pattern: &str) -> Result<Vec<path::PathBuf>, Error> {
+    let path = path::Path::new(pattern);
+
+    if path.is_file() {
+        return Ok(vec![path.to_path_buf()]);
+    }
+
+    let (root, glob) = match path.is_dir() {
+        true => (path.to_path_buf(), None),
+        false if root(pattern) == path => {
+            return Err(anyhow!("{}: no such file or directory", pattern))
+        }
+        false => {
+            let glob = globset::GlobBuilder::new(pattern)
+                .literal_separator(true)
+                .build()
+                .map_err(|error| anyhow!("{}: {}", pattern, error))?
+                .compile_matcher();
+
+            (root(pattern), Some(glob))
+        }
+    };
+
+    let mut files = vec![];
+
+    for entry in ignore::WalkBuilder::new(&root).require_git(false).build() {
+        let entry = entry?;
+
+        if !entry
+            .file_type()
+            .is_some_and(|file_type| file_type.is_file())
+        {
+            continue;
+        }
+
+        // Walking from `.` yields `./a.rs`, which `*.rs` should still match.
+        let path = entry.into_path();
+        let path = match path.strip_prefix(".") {
+            Ok(relative) if root == path::Path::new(".") => relative.to_path_buf(),
+            _ => path,
+        };
+
+        if glob.as_ref().is_none_or(|glob| glob.is_match(&path)) {
+            files.push(path);
+        }
+    }
+
+    if files.is_empty() {
+        return Err(anyhow!("no files match {}", pattern));
+    }
+
+    files.sort();
+
+    Ok(files)
+}
+
+// The leading components of a glob that contain no wildcards, where the walk can start.
+fn root(pattern: &str) -> path::PathBuf {
+    let root: path::PathBuf = path::Path::new(pattern)
+        .components()
+        .take_while(|component| {
+            !component
+                .as_os_str()
+                .to_string_lossy()
+                .contains(['*', '?', '[', '{'])
+        })
+        .collect();
+
+    match root.as_os_str().is_empty() {
+        true => path::PathBuf::from("."),
+        false => root,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A fresh directory holding a.rs, b.txt, sub/c.rs and an ignored ignored.rs.
+    fn tree(name: &str) -> path::PathBuf {
+        let directory =
+            std::env::temp_dir().join(format!("openai-cli-attach-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&directory);
+
+        fs::create_dir_all(directory.join("sub")).unwrap();
+
+        for (file, content) in [
+            ("a.rs", "fn a() {}"),
+            ("b.txt", "b"),
+            ("sub/c.rs", "fn c() {}"),
+            ("ignored.rs", "fn ignored() {}"),
+            (".gitignore", "ignored.rs\n"),
+        ] {
+            fs::write(directory.join(file), content).unwrap();
+        }
+
+        directory
+    }
+
+    fn names(directory: &path::Path, files: Vec<path::PathBuf>) -> Vec<String> {
+        files
+            .iter()
+            .map(|file| file.strip_prefix(directory).unwrap().display().to_string())
+            .collect()
+    }
+
+    #[test]
+    fn root_stops_at_the_first_wildcard() {
+        assert_eq!(root("src/**/*.rs"), path::PathBuf::from("src"));
+        assert_eq!(root("src/a/[ab].rs"), path::PathBuf::from("src/a"));
+        assert_eq!(root("*.rs"), path::PathBuf::from("."));
+        assert_eq!(root("{a,b}/c.rs"), path::PathBuf::from("."));
+        assert_eq!(root("src/main.rs"), path::PathBuf::from("src/main.rs"));
+    }
+
+    #[test]
+    fn directories_are_walked_with_ignore_rules() {
+        let directory = tree("directory");
+        let files = expand(directory.to_str().unwrap()).unwrap();
+
+        assert_eq!(names(&directory, files), vec!["a.rs", "b.txt", "sub/c.rs"]);
+    }
+
+    #[test]
+    fn globs_do_not_cross_separators_unless_asked() {
+        let directory = tree("glob");
+        let pattern = |glob: &str| format!("{}/{}", directory.display(), glob);
+
+        let files = expand(&pattern("*.rs")).unwrap();
+        assert_eq!(names(&directory, files), vec!["a.rs"]);
+
+        let files = expand(&pattern("**/*.rs")).unwrap();
+        assert_eq!(names(&directory, files), vec!["a.rs", "sub/c.rs"]);
+    }
+
+    #[test]
+    fn named_files_are_attached_even_if_ignored() {
+        let directory = tree("named");
+        let file = directory.join("ignored.rs");
+
+        assert_eq!(expand(file.to_str().unwrap()).unwrap(), vec![file]);
+    }
+
+    #[test]
+    fn missing_paths_and_empty_globs_are_errors() {
+        let directory = tree("missing");
+
+        assert!(expand(&format!("{}/missing.rs", directory.display())).is_err());
+        assert!(expand(&format!("{}/*.md", directory.display())).is_err());
+    }
+
+    #[test]
+    fn block_fence_outlasts_backticks_in_the_file() {
+        assert_eq!(block("a.md", "x\n"), "File: a.md\n```\nx\n```");
+        assert_eq!(
+            block("a.md", "````\ny\n````"),
+            "File: a.md\n`````\n````\ny\n````\n`````"
+        );
+    }
+}
@@ -0,0 +1,236 @@
+use anyhow::{anyhow, Error};
+use openai_api::model::create_chat;
+use serde::Deserialize;
+use std::{collections::HashMap, env, fs, path};
+
+// `OPENAI_CLI_CONFIG_DIR` wins over the platform config directory, which keeps test setups
+// and shared team configs out of the user's home.
+pub fn directory() -> Result<path::PathBuf, Error> {
+    if let Some(directory) = env::var_os("OPENAI_CLI_CONFIG_DIR") {
+        return Ok(path::PathBuf::from(directory));
+    }
+
+    Ok(dirs::config_dir()
+        .ok_or_else(|| anyhow!("no config directory for this platform"))?
+        .join("openai-cli"))
+}
+
+// Names the user picks become file names, so they are held to plain characters and may not
+// start with a dot. That rules out hidden files, `..` and any path separator.
+pub fn check_name(kind: &str, name: &str) -> Result<(), Error> {
+    let valid = !name.is_empty()
+        && name
+            .chars()
+            .all(|c| c.is_alphanumeric() || matches!(c, '-' | '_' | '.'))
+        && !name.starts_with('.');
+
+    match valid {
+        true => Ok(()),
+        false => Err(anyhow!("invalid {} name: {}", kind, name)),
+    }
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct Persona {
+    pub system: Option<String>,
+    pub model: Option<create_chat::Model>,
+    pub temperature: Option<f32>,
+    pub max_tokens: Option<usize>,
+}
+
+impl Persona {
+    // Personas live in `personas/<name>.yaml` under the config directory.
+    pub fn load(name: &str) -> Result<Self, Error> {
+        Self::load_from(&directory()?, name)
+    }
+
+    fn load_from(directory: &path::Path, name: &str) -> Result<Self, Error> {
+        check_name("persona", name)?;
+
+        let path = directory.join("personas").join(format!("{}.yaml", name));
+
+        let content = fs::read_to_string(&path)
+            .map_err(|error| anyhow!("persona {} ({}): {}", name, path.display(), error))?;
+
+        serde_yaml::from_str(&content)
+            .map_err(|error| anyhow!("persona {} ({}): {}", name, path.display(), error))
+    }
+}
+
+// US dollars per 1,000 tokens.
+#[derive(Clone, Copy, Deserialize)]
+pub struct Price {
+    pub prompt: f64,
+    pub completion: f64,
+}
+
+impl Price {
+    pub fn cost(&self, prompt_tokens: usize, completion_tokens: usize) -> f64 {
+        (prompt_tokens as f64 * self.prompt + completion_tokens as f64 * self.completion) / 1000.0
+    }
+}
+
+// Model, prompt and completion list prices.
+const PRICES: &[(&str, f64, f64)] = &[
+    ("gpt-4", 0.03, 0.06),
+    ("gpt-4-0613", 0.03, 0.06),
+    ("gpt-4-32k", 0.06, 0.12),
+    ("gpt-4-32k-0613", 0.06, 0.12),
+    ("gpt-3.5-turbo", 0.0015, 0.002),
+    ("gpt-3.5-turbo-0613", 0.0015, 0.002),
+    ("gpt-3.5-turbo-16k", 0.003, 0.004),
+    ("gpt-3.5-turbo-16k-0613", 0.003, 0.004),
+    ("text-davinci-003", 0.02, 0.02),
+    ("text-davinci-002", 0.02, 0.02),
+    ("text-curie-001", 0.002, 0.002),
+    ("text-babbage-001", 0.0005, 0.0005),
+    ("text-ada-001", 0.0004, 0.0004),
+    ("text-embedding-ada-002", 0.0001, 0.0),
+];
+
+// The built-in list prices, with `prices.yaml` under the config directory overriding or
+// adding models:
+//
+//     gpt-4:
+//       prompt: 0.03
+//       completion: 0.06
+pub fn prices() -> Result<HashMap<String, Price>, Error> {
+    prices_from(&directory()?)
+}
+
+fn prices_from(directory: &path::Path) -> Result<HashMap<String, Price>, Error> {
+    let mut prices: HashMap<String, Price> = PRICES
+        .iter()
+        .map(|(model, prompt, completion)| {
+            let price = Price {
+                prompt: *prompt,
+                completion: *completion,
+            };
+
+            (model.to_string(), price)
+        })
+        .collect();
+
+    let path = directory.join("prices.yaml");
+
+    if path.exists() {
+        let content = fs::read_to_string(&path)
+            .map_err(|error| anyhow!("prices ({}): {}", path.display(), error))?;
+        let overrides: HashMap<String, Price> = serde_yaml::from_str(&content)
+            .map_err(|error| anyhow!("prices ({}): {}", path.display(), error))?;
+
+        prices.extend(overrides);
+    }
+
+    Ok(prices)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A fresh config directory holding the given files.
+    fn directory(name: &str, files: &[(&str, &str)]) -> path::PathBuf {
+        let directory =
+            env::temp_dir().join(format!("openai-cli-config-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&directory);
+
+        for (file, content) in files {
+            let path = directory.join(file);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, content).unwrap();
+        }
+
+        directory
+    }
+
+    #[test]
+    fn names_are_plain_file_names() {
+        for name in ["reviewer", "code-review_2", "v1.2", "ünïcode"] {
+            assert!(check_name("persona", name).is_ok(), "{}", name);
+        }
+
+        for name in [
+            "",
+            ".",
+            "..",
+            ".hidden",
+            "../../x",
+            "a/b",
+            "a\\b",
+            "a b",
+            "/etc/passwd",
+        ] {
+            assert!(check_name("persona", name).is_err(), "{}", name);
+        }
+    }
+
+    #[test]
+    fn persona_loads_from_its_file() {
+        let directory = directory(
+            "persona",
+            &[(
+                "personas/reviewer.yaml",
+                "system: Review code.\nmodel: gpt-4\ntemperature: 0.2\n",
+            )],
+        );
+
+        let persona = Persona::load_from(&directory, "reviewer").unwrap();
+
+        assert_eq!(persona.system.as_deref(), Some("Review code."));
+        assert!(matches!(persona.model, Some(create_chat::Model::Gpt4)));
+        assert_eq!(persona.temperature, Some(0.2));
+        assert_eq!(persona.max_tokens, None);
+    }
+
+    #[test]
+    fn persona_errors_name_the_file() {
+        let directory = directory("persona-errors", &[("personas/broken.yaml", "model: [")]);
+
+        let missing = Persona::load_from(&directory, "missing").unwrap_err();
+        let broken = Persona::load_from(&directory, "broken").unwrap_err();
+
+        assert!(missing.to_string().contains("missing.yaml"));
+        assert!(broken.to_string().contains("broken.yaml"));
+    }
+
+    #[test]
+    fn persona_name_cannot_leave_the_directory() {
+        let directory = directory("persona-escape", &[("secret.yaml", "system: leaked\n")]);
+
+        let error = Persona::load_from(&directory, "../secret").unwrap_err();
+
+        assert_eq!(error.to_string(), "invalid persona name: ../secret");
+    }
+
+    #[test]
+    fn prices_default_to_the_list() {
+        let prices = prices_from(&directory("prices-default", &[])).unwrap();
+
+        assert_eq!(prices["gpt-4"].cost(1000, 1000), 0.09);
+    }
+
+    #[test]
+    fn prices_file_overrides_and_adds_models() {
+        let directory = directory(
+            "prices-override",
+            &[(
+                "prices.yaml",
+                "gpt-4:\n  prompt: 0.01\n  completion: 0.02\nlocal:\n  prompt: 0.0\n  completion: 0.0\n",
+            )],
+        );
+
+        let prices = prices_from(&directory).unwrap();
+
+        assert_eq!(prices["gpt-4"].cost(1000, 1000), 0.03);
+        assert_eq!(prices["local"].cost(1000, 1000), 0.0);
+        assert_eq!(prices["gpt-3.5-turbo"].prompt, 0.0015);
+    }
+
+    #[test]
+    fn malformed_prices_file_is_an_error() {
+        let directory = directory("prices-malformed", &[("prices.yaml", "gpt-4: cheap\n")]);
+
+        assert!(prices_from(&directory).is_err());
+    }
+}
@@ -0,0 +1,529 @@
+use anyhow::{anyhow, Error};
+use openai_api::{model::create_chat, tokenizer::Encoding, Datasource};
+use std::{ops, str::FromStr};
+
+// Room left for the reply when the request does not set max_tokens.
+const DEFAULT_RESERVE: usize = 1024;
+const SUMMARY_TOKENS: usize = 256;
+const SUMMARY_NAME: &str = "summary";
+const SUMMARY_PROMPT: &str = "Summarize the conversation below in a few sentences. Keep names, \
+    facts, decisions and open questions, and leave out pleasantries.";
+
+#[derive(Clone, Copy)]
+pub enum Strategy {
+    DropOldest,
+    Pinned,
+    Summarize,
+}
+
+impl FromStr for Strategy {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "drop-oldest" => Ok(Self::DropOldest),
+            "pinned" => Ok(Self::Pinned),
+            "summarize" => Ok(Self::Summarize),
+            _ => Err(anyhow!("unsupported context strategy: {}", s)),
+        }
+    }
+}
+
+// Keeps a chat request inside its model's context window. History is given up a turn at a
+// time: a user message together with the replies and function results that follow it, so a
+// call is never separated from its result. System messages are always kept, and so is the
+// turn being answered.
+//
+// Only outgoing copies are trimmed, so the same history is given up again on every turn.
+// The last summary is kept to spare summarizing it again, and to fold it into the next one
+// when more history has to go.
+pub struct Window {
+    strategy: Strategy,
+    pinned: usize,
+    dropped: usize,
+    summary: Option<(Vec<create_chat::Message>, create_chat::Message)>,
+}
+
+impl Window {
+    // `pinned` is the number of leading turns the pinned strategy never drops.
+    pub fn new(strategy: Strategy, pinned: usize) -> Self {
+        Self {
+            strategy,
+            pinned,
+            dropped: 0,
+            summary: None,
+        }
+    }
+
+    pub async fn fit(
+        &mut self,
+        datasource: &(dyn Datasource + Send + Sync),
+        request: &mut create_chat::Request,
+    ) -> Result<(), Error> {
+        let encoding = Encoding::from(&request.model);
+        let window = request.model.context_window();
+        let limit = window.saturating_sub(request.max_tokens.unwrap_or(DEFAULT_RESERVE));
+
+        // Function definitions are sent in a compact form of their own; their JSON is a
+        // slight overestimate of it.
+        let functions = match &request.functions {
+            Some(functions) => encoding.count(&serde_json::to_string(functions)?),
+            None => 0,
+        };
+        let count =
+            |messages: &[create_chat::Message]| encoding.count_messages(messages) + functions;
+
+        if count(&request.messages) <= limit {
+            self.dropped = 0;
+            return Ok(());
+        }
+
+        let target = match self.strategy {
+            Strategy::Summarize => limit.saturating_sub(SUMMARY_TOKENS),
+            Strategy::DropOldest | Strategy::Pinned => limit,
+        };
+
+        let mut dropped = vec![];
+
+        while count(&request.messages) > target {
+            let Some(turn) = self.oldest(&request.messages) else {
+                break;
+            };
+
+            dropped.extend(request.messages.drain(turn));
+        }
+
+        // With nothing left to give up the request goes out as is and the API reports it.
+        if dropped.is_empty() {
+            return Ok(());
+        }
+
+        let verb = match self.strategy {
+            Strategy::Summarize => {
+                let summary = self
+                    .summary(datasource, &request.model, dropped.clone())
+                    .await?;
+                let at = request
+                    .messages
+                    .iter()
+                    .position(|message| !matches!(message.role, create_chat::Role::System))
+                    .unwrap_or(request.messages.len());
+
+                request.messages.insert(at, summary);
+
+                "Summarized"
+            }
+            Strategy::DropOldest | Strategy::Pinned => "Dropped",
+        };
+
+        // Said once for each change rather than on every turn.
+        if dropped.len() == std::mem::replace(&mut self.dropped, dropped.len()) {
+            return Ok(());
+        }
+
+        eprintln!(
+            "{}",
+            console::Style::new().dim().apply_to(format!(
+                "{} {} earlier messages to fit the {} token context window",
+                verb,
+                dropped.len(),
+                window
+            ))
+        );
+
+        Ok(())
+    }
+
+    async fn summary(
+        &mut self,
+        datasource: &(dyn Datasource + Send + Sync),
+        model: &create_chat::Model,
+        dropped: Vec<create_chat::Message>,
+    ) -> Result<create_chat::Message, Error> {
+        let messages = match &self.summary {
+            Some((summarized, summary)) if dropped == *summarized => return Ok(summary.clone()),
+            Some((summarized, summary)) if dropped.starts_with(summarized) => {
+                let mut messages = vec![summary.clone()];
+                messages.extend_from_slice(&dropped[summarized.len()..]);
+                messages
+            }
+            _ => dropped.clone(),
+        };
+
+        let summary = summarize(datasource, model, &messages).await?;
+        self.summary = Some((dropped, summary.clone()));
+
+        Ok(summary)
+    }
+
+    fn oldest(&self, messages: &[create_chat::Message]) -> Option<ops::Range<usize>> {
+        let mut turns = turns(messages);
+
+        // The last turn holds the message being answered.
+        turns.pop();
+
+        let skip = match self.strategy {
+            Strategy::Pinned => self.pinned,
+            Strategy::DropOldest | Strategy::Summarize => 0,
+        };
+
+        turns.into_iter().nth(skip)
+    }
+}
+
+// Earlier summaries start a turn of their own, so they are folded into the next summary
+// rather than piling up next to the system prompt.
+fn turns(messages: &[create_chat::Message]) -> Vec<ops::Range<usize>> {
+    let mut turns: Vec<ops::Range<usize>> = vec![];
+    let mut open = false;
+
+    for (index, message) in messages.iter().enumerate() {
+        let summary = message.name.as_deref() == Some(SUMMARY_NAME);
+
+        match message.role {
+            create_chat::Role::System if !summary => {
+                open = false;
+                continue;
+            }
+            create_chat::Role::System | create_chat::Role::User => open = false,
+            create_chat::Role::Assistant | create_chat::Role::Function => {}
+        }
+
+        match (open, turns.last_mut()) {
+            (true, Some(turn)) => turn.end = index + 1,
+            _ => turns.push(index..index + 1),
+        }
+
+        open = true;
+    }
+
+    turns
+}
+
+// What is given up can be far larger than the context window, so it is summarized a chunk
+// at a time, each chunk starting from the summary of the ones before it. A single message
+// too long for a chunk of its own is cut short.
+async fn summarize(
+    datasource: &(dyn Datasource + Send + Sync),
+    model: &create_chat::Model,
+    messages: &[create_chat::Message],
+) -> Result<create_chat::Message, Error> {
+    let encoding = Encoding::from(model);
+    let overhead = encoding.count_messages(&[
+        message(create_chat::Role::System, SUMMARY_PROMPT.to_string(), None),
+        message(create_chat::Role::User, String::new(), None),
+    ]);
+    let limit = model
+        .context_window()
+        .saturating_sub(SUMMARY_TOKENS + overhead);
+
+    // Entries are joined by a blank line, which costs at most a token more each.
+    let entries: Vec<(String, usize)> = messages
+        .iter()
+        .map(|message| match (&message.content, &message.function_call) {
+            (Some(content), _) => format!("{:?}: {}", message.role, content.trim_end()),
+            (None, Some(function_call)) => format!("{:?}: {:?}", message.role, function_call),
+            (None, None) => format!("{:?}:", message.role),
+        })
+        .map(|entry| truncate(&encoding, entry, limit / 2))
+        .map(|entry| {
+            let tokens = encoding.count(&entry) + 1;
+            (entry, tokens)
+        })
+        .collect();
+    let mut summary: Option<String> = None;
+    let mut next = 0;
+
+    while next < entries.len() {
+        let mut transcript = vec![];
+        let mut tokens = 0;
+
+        if let Some(summary) = summary.take() {
+            let summary = format!("Summary so far: {}", summary);
+            tokens += encoding.count(&summary) + 1;
+            transcript.push(summary);
+        }
+
+        // Each chunk takes at least one entry, so the loop always moves on.
+        let start = next;
+
+        while let Some((entry, cost)) = entries.get(next) {
+            if next > start && tokens + cost > limit {
+                break;
+            }
+
+            tokens += cost;
+            transcript.push(entry.clone());
+            next += 1;
+        }
+
+        let request = create_chat::Request::new(
+            model.clone(),
+            vec![
+                message(create_chat::Role::System, SUMMARY_PROMPT.to_string(), None),
+                message(create_chat::Role::User, transcript.join("\n\n"), None),
+            ],
+        )
+        .max_tokens(Some(SUMMARY_TOKENS));
+
+        let response = datasource.create_chat(&request).await?;
+        let content = response
+            .choices
+            .into_iter()
+            .next()
+            .and_then(|choice| choice.message.content)
+            .ok_or_else(|| anyhow!("the summary came back empty"))?;
+
+        summary = Some(content.trim().to_string());
+    }
+
+    Ok(message(
+        create_chat::Role::System,
+        format!(
+            "Summary of the earlier conversation: {}",
+            summary.unwrap_or_default()
+        ),
+        Some(SUMMARY_NAME.to_string()),
+    ))
+}
+
+fn truncate(encoding: &Encoding, text: String, tokens: usize) -> String {
+    let encoded = encoding.encode(&text);
+
+    if encoded.len() <= tokens {
+        return text;
+    }
+
+    // A cut inside a multi-byte character does not decode, so the cut moves back until it does.
+    (0..tokens)
+        .rev()
+        .find_map(|end| encoding.decode(&encoded[..end]).ok())
+        .map(|text| format!("{} [cut short]", text))
+        .unwrap_or_default()
+}
+
+fn message(role: create_chat::Role, content: String, name: Option<String>) -> create_chat::Message {
+    create_chat::Message {
+        role,
+        content: Some(content),
+        name,
+        function_call: None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use create_chat::Role;
+    use openai_api::testing::{self, MockDatasource};
+
+    const MODEL: create_chat::Model = create_chat::Model::Gpt3dot5Turbo;
+
+    // A system prompt and `turns` exchanges of about `words` tokens a message, then a question.
+    fn conversation(turns: usize, words: usize) -> create_chat::Request {
+        let mut messages = vec![message(Role::System, String::from("Be brief."), None)];
+
+        for turn in 0..turns {
+            for role in [Role::User, Role::Assistant] {
+                let content = format!("{} {}", turn, "word ".repeat(words));
+                messages.push(message(role, content, None));
+            }
+        }
+
+        messages.push(message(Role::User, String::from("And now?"), None));
+
+        create_chat::Request::new(MODEL, messages).max_tokens(Some(1000))
+    }
+
+    fn limit(request: &create_chat::Request) -> usize {
+        MODEL.context_window() - request.max_tokens.unwrap()
+    }
+
+    fn summaries(datasource: &MockDatasource) -> Vec<String> {
+        datasource
+            .requests_for(testing::Method::CreateChat)
+            .into_iter()
+            .map(|request| {
+                let body = request.body.unwrap();
+                body["messages"][1]["content"].as_str().unwrap().to_string()
+            })
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn fitting_request_is_left_alone() {
+        let datasource = MockDatasource::new();
+        let mut request = conversation(2, 10);
+        let messages = request.messages.clone();
+
+        Window::new(Strategy::Summarize, 0)
+            .fit(&datasource, &mut request)
+            .await
+            .unwrap();
+
+        assert_eq!(request.messages, messages);
+        assert!(datasource.requests().is_empty());
+    }
+
+    #[tokio::test]
+    async fn drop_oldest_keeps_the_system_prompt_and_question() {
+        let datasource = MockDatasource::new();
+        let mut request = conversation(10, 400);
+
+        Window::new(Strategy::DropOldest, 0)
+            .fit(&datasource, &mut request)
+            .await
+            .unwrap();
+
+        let encoding = Encoding::from(&MODEL);
+
+        assert!(encoding.count_messages(&request.messages) <= limit(&request));
+        assert_eq!(request.messages[0].content.as_deref(), Some("Be brief."));
+        assert_eq!(
+            request.messages.last().unwrap().content.as_deref(),
+            Some("And now?")
+        );
+        assert!(request.messages[1]
+            .content
+            .as_deref()
+            .unwrap()
+            .starts_with("7 "));
+        assert!(datasource.requests().is_empty());
+    }
+
+    #[tokio::test]
+    async fn long_history_is_summarized_in_chunks() {
+        let datasource = MockDatasource::new();
+
+        for summary in ["first", "second", "third", "fourth"] {
+            datasource.push_create_chat(Ok(testing::chat_response(MODEL, summary)));
+        }
+
+        let mut window = Window::new(Strategy::Summarize, 0);
+        let mut request = conversation(10, 400);
+
+        window.fit(&datasource, &mut request).await.unwrap();
+
+        let encoding = Encoding::from(&MODEL);
+        let summaries = summaries(&datasource);
+
+        // Each summary request fits the window on its own and carries the previous summary.
+        assert!(summaries.len() >= 2);
+
+        for (index, transcript) in summaries.iter().enumerate() {
+            assert!(encoding.count(transcript) + SUMMARY_TOKENS <= MODEL.context_window());
+
+            if index > 0 {
+                assert!(transcript.starts_with("Summary so far: "));
+            }
+        }
+
+        let last = ["first", "second", "third", "fourth"][summaries.len() - 1];
+
+        assert!(encoding.count_messages(&request.messages) <= limit(&request));
+        assert_eq!(request.messages[1].name.as_deref(), Some(SUMMARY_NAME));
+        assert_eq!(
+            request.messages[1].content,
+            Some(format!("Summary of the earlier conversation: {}", last))
+        );
+        assert_eq!(
+            request.messages.last().unwrap().content.as_deref(),
+            Some("And now?")
+        );
+
+        // The same history on the next turn reuses the summary.
+        let mut again = conversation(10, 400);
+        window.fit(&datasource, &mut again).await.unwrap();
+
+        assert_eq!(again.messages, request.messages);
+        assert_eq!(
+            datasource.requests_for(testing::Method::CreateChat).len(),
+            summaries.len()
+        );
+    }
+
+    #[test]
+    fn overlong_entries_are_cut_short() {
+        let encoding = Encoding::from(&MODEL);
+        let text = "naïve 東京 ".repeat(100);
+
+        let cut = truncate(&encoding, text.clone(), 20);
+
+        assert!(cut.ends_with(" [cut short]"));
+        assert!(encoding.count(&cut) <= 25);
+        assert_eq!(truncate(&encoding, String::from("short"), 20), "short");
+    }
+
+    fn transcript(roles: &[Role]) -> Vec<create_chat::Message> {
+        roles
+            .iter()
+            .map(|role| message(role.clone(), String::new(), None))
+            .collect()
+    }
+
+    #[test]
+    fn turns_group_replies_and_function_results() {
+        let messages = transcript(&[
+            Role::System,
+            Role::User,
+            Role::Assistant,
+            Role::User,
+            Role::Assistant,
+            Role::Function,
+            Role::Assistant,
+            Role::User,
+        ]);
+
+        assert_eq!(turns(&messages), vec![1..3, 3..7, 7..8]);
+    }
+
+    #[test]
+    fn system_messages_are_not_part_of_a_turn() {
+        let messages = transcript(&[Role::User, Role::System, Role::Assistant, Role::User]);
+
+        assert_eq!(turns(&messages), vec![0..1, 2..3, 3..4]);
+    }
+
+    #[test]
+    fn summaries_start_a_turn() {
+        let mut messages = transcript(&[Role::System, Role::System, Role::User, Role::Assistant]);
+        messages[1].name = Some(SUMMARY_NAME.to_string());
+
+        assert_eq!(turns(&messages), vec![1..2, 2..4]);
+    }
+
+    #[test]
+    fn oldest_never_offers_the_turn_being_answered() {
+        let window = Window::new(Strategy::DropOldest, 0);
+
+        assert_eq!(
+            window.oldest(&transcript(&[Role::System, Role::User])),
+            None
+        );
+        assert_eq!(
+            window.oldest(&transcript(&[Role::User, Role::Assistant, Role::User])),
+            Some(0..2)
+        );
+    }
+
+    #[test]
+    fn oldest_skips_pinned_turns() {
+        let messages = transcript(&[
+            Role::User,
+            Role::Assistant,
+            Role::User,
+            Role::Assistant,
+            Role::User,
+        ]);
+
+        assert_eq!(
+            Window::new(Strategy::Pinned, 1).oldest(&messages),
+            Some(2..4)
+        );
+        assert_eq!(Window::new(Strategy::Pinned, 2).oldest(&messages), None);
+        assert_eq!(
+            Window::new(Strategy::Summarize, 2).oldest(&messages),
+            Some(0..2)
+        );
+    }
+}
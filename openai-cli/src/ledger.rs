@@ -0,0 +1,530 @@
+use anyhow::{anyhow, Error};
+use async_trait::async_trait;
+use futures::StreamExt;
+use openai_api::{error, model, tokenizer::Encoding, ApiResponse, Datasource, EventStream};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::BTreeMap,
+    fs,
+    io::{self, BufRead, Write},
+    path, sync,
+};
+
+// Streamed replies carry no usage, so their tokens are counted locally and marked estimated.
+#[derive(Deserialize, Serialize)]
+pub struct Entry {
+    pub timestamp: chrono::DateTime<chrono::Utc>,
+    pub endpoint: String,
+    pub model: Option<String>,
+    pub prompt_tokens: usize,
+    pub completion_tokens: usize,
+
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub estimated: bool,
+
+    pub command: String,
+
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tags: Vec<String>,
+}
+
+// One JSON entry per line, appended under the platform data directory.
+pub struct Ledger {
+    path: path::PathBuf,
+}
+
+impl Ledger {
+    pub fn new() -> Result<Self, Error> {
+        let path = dirs::data_dir()
+            .ok_or_else(|| anyhow!("no data directory for this platform"))?
+            .join("openai-cli")
+            .join("usage.jsonl");
+
+        Ok(Self { path })
+    }
+
+    pub fn append(&self, entry: &Entry) -> Result<(), Error> {
+        if let Some(directory) = self.path.parent() {
+            fs::create_dir_all(directory)?;
+        }
+
+        let mut line = serde_json::to_string(entry)?;
+        line.push('\n');
+
+        fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)?
+            .write_all(line.as_bytes())?;
+
+        Ok(())
+    }
+
+    pub fn entries(&self) -> Result<Vec<Entry>, Error> {
+        let file = match fs::File::open(&self.path) {
+            Ok(file) => file,
+            Err(error) if error.kind() == io::ErrorKind::NotFound => return Ok(vec![]),
+            Err(error) => return Err(error.into()),
+        };
+
+        let mut entries = vec![];
+
+        for (number, line) in io::BufReader::new(file).lines().enumerate() {
+            let line = line?;
+
+            if line.trim().is_empty() {
+                continue;
+            }
+
+            entries.push(
+                serde_json::from_str(&line).map_err(|error| {
+                    anyhow!("{}:{}: {}", self.path.display(), number + 1, error)
+                })?,
+            );
+        }
+
+        Ok(entries)
+    }
+}
+
+// Appends every call that goes through it to the ledger. A ledger that cannot be written
+// is logged rather than failing the call it describes.
+pub struct Metered {
+    inner: sync::Arc<dyn Datasource + Send + Sync>,
+    ledger: sync::Arc<Ledger>,
+    command: String,
+    tags: Vec<String>,
+}
+
+impl Metered {
+    pub fn new(
+        inner: sync::Arc<dyn Datasource + Send + Sync>,
+        ledger: Ledger,
+        command: &str,
+        tags: Vec<String>,
+    ) -> Self {
+        Self {
+            inner,
+            ledger: sync::Arc::new(ledger),
+            command: command.to_string(),
+            tags,
+        }
+    }
+
+    fn entry(&self, endpoint: &str, model: Option<String>) -> Entry {
+        Entry {
+            timestamp: chrono::Utc::now(),
+            endpoint: endpoint.to_string(),
+            model,
+            prompt_tokens: 0,
+            completion_tokens: 0,
+            estimated: false,
+            command: self.command.clone(),
+            tags: self.tags.clone(),
+        }
+    }
+
+    fn record<T>(&self, response: ApiResponse<T>, entry: Entry) -> ApiResponse<T> {
+        append(&self.ledger, &entry);
+
+        response
+    }
+
+    // Passes the stream through, collecting every choice it yields, and records it once the
+    // stream is dropped.
+    fn record_stream<T: Send + 'static>(
+        &self,
+        response: ApiResponse<EventStream<T>>,
+        entry: Entry,
+        encoding: Encoding,
+        collect: fn(&T, &mut BTreeMap<usize, Reply>),
+    ) -> ApiResponse<EventStream<T>> {
+        let mut recorder = Recorder {
+            ledger: self.ledger.clone(),
+            entry,
+            encoding,
+            replies: BTreeMap::new(),
+        };
+
+        let body = response.body.map(move |item| {
+            if let Ok(item) = &item {
+                recorder.collect(item, collect);
+            }
+
+            item
+        });
+
+        ApiResponse::new(body.boxed(), response.meta)
+    }
+}
+
+// What a streamed choice has delivered so far.
+#[derive(Default)]
+struct Reply {
+    content: String,
+    name: String,
+    arguments: String,
+}
+
+// Records a stream's entry when it is dropped, so one that fails or is abandoned part way
+// is still charged for what it delivered.
+struct Recorder {
+    ledger: sync::Arc<Ledger>,
+    entry: Entry,
+    encoding: Encoding,
+    replies: BTreeMap<usize, Reply>,
+}
+
+impl Recorder {
+    fn collect<T>(&mut self, item: &T, collect: fn(&T, &mut BTreeMap<usize, Reply>)) {
+        collect(item, &mut self.replies);
+    }
+}
+
+impl Drop for Recorder {
+    fn drop(&mut self) {
+        self.entry.completion_tokens = self
+            .replies
+            .values()
+            .map(|reply| {
+                completion_tokens(
+                    &self.encoding,
+                    &reply.content,
+                    &reply.name,
+                    &reply.arguments,
+                )
+            })
+            .sum();
+        self.entry.estimated = true;
+
+        append(&self.ledger, &self.entry);
+    }
+}
+
+// A reply's completion tokens when the API reports none: its content plus any function
+// call's name and arguments. Shared with the chat's running totals so both agree.
+pub fn completion_tokens(encoding: &Encoding, content: &str, name: &str, arguments: &str) -> usize {
+    encoding.count(content) + encoding.count(name) + encoding.count(arguments)
+}
+
+fn append(ledger: &Ledger, entry: &Entry) {
+    if let Err(error) = ledger.append(entry) {
+        log::warn!("usage ledger: {}", error);
+    }
+}
+
+fn name<T: Serialize>(model: &T) -> Option<String> {
+    serde_json::to_value(model)
+        .ok()
+        .and_then(|value| value.as_str().map(String::from))
+}
+
+#[async_trait]
+impl Datasource for Metered {
+    async fn list_models(&self) -> Result<model::list_models::Response, error::Error> {
+        Ok(self.list_models_with_meta().await?.body)
+    }
+
+    async fn create_completion(
+        &self,
+        request: &model::create_completion::Request,
+    ) -> Result<model::create_completion::Response, error::Error> {
+        Ok(self.create_completion_with_meta(request).await?.body)
+    }
+
+    async fn create_completion_stream(
+        &self,
+        request: &model::create_completion::Request,
+    ) -> Result<EventStream<model::create_completion::Choice>, error::Error> {
+        Ok(self.create_completion_stream_with_meta(request).await?.body)
+    }
+
+    async fn create_chat(
+        &self,
+        request: &model::create_chat::Request,
+    ) -> Result<model::create_chat::Response, error::Error> {
+        Ok(self.create_chat_with_meta(request).await?.body)
+    }
+
+    async fn create_chat_stream(
+        &self,
+        request: &model::create_chat::Request,
+    ) -> Result<EventStream<model::create_chat::Chunk>, error::Error> {
+        Ok(self.create_chat_stream_with_meta(request).await?.body)
+    }
+
+    async fn create_image(
+        &self,
+        request: &model::create_image::Request,
+    ) -> Result<model::create_image::Response, error::Error> {
+        Ok(self.create_image_with_meta(request).await?.body)
+    }
+
+    async fn create_edit(
+        &self,
+        request: &model::create_edit::Request,
+    ) -> Result<model::create_edit::Response, error::Error> {
+        Ok(self.create_edit_with_meta(request).await?.body)
+    }
+
+    async fn list_files(&self) -> Result<model::list_files::Response, error::Error> {
+        Ok(self.list_files_with_meta().await?.body)
+    }
+
+    async fn create_embedding(
+        &self,
+        request: &model::create_embedding::Request,
+    ) -> Result<model::create_embedding::Response, error::Error> {
+        Ok(self.create_embedding_with_meta(request).await?.body)
+    }
+
+    async fn list_models_with_meta(
+        &self,
+    ) -> Result<ApiResponse<model::list_models::Response>, error::Error> {
+        let response = self.inner.list_models_with_meta().await?;
+
+        Ok(self.record(response, self.entry("models", None)))
+    }
+
+    async fn create_completion_with_meta(
+        &self,
+        request: &model::create_completion::Request,
+    ) -> Result<ApiResponse<model::create_completion::Response>, error::Error> {
+        let response = self.inner.create_completion_with_meta(request).await?;
+        let mut entry = self.entry("completions", name(&request.model));
+        entry.prompt_tokens = response.body.usage.prompt_tokens;
+        entry.completion_tokens = response.body.usage.completion_tokens;
+
+        Ok(self.record(response, entry))
+    }
+
+    async fn create_completion_stream_with_meta(
+        &self,
+        request: &model::create_completion::Request,
+    ) -> Result<ApiResponse<EventStream<model::create_completion::Choice>>, error::Error> {
+        let response = self
+            .inner
+            .create_completion_stream_with_meta(request)
+            .await?;
+        let encoding = Encoding::from(&request.model);
+        let mut entry = self.entry("completions", name(&request.model));
+        entry.prompt_tokens = encoding.count(&request.prompt);
+
+        Ok(
+            self.record_stream(response, entry, encoding, |choice, replies| {
+                replies
+                    .entry(choice.index)
+                    .or_default()
+                    .content
+                    .push_str(&choice.text);
+            }),
+        )
+    }
+
+    async fn create_chat_with_meta(
+        &self,
+        request: &model::create_chat::Request,
+    ) -> Result<ApiResponse<model::create_chat::Response>, error::Error> {
+        let response = self.inner.create_chat_with_meta(request).await?;
+        let mut entry = self.entry("chat/completions", name(&request.model));
+        entry.prompt_tokens = response.body.usage.prompt_tokens;
+        entry.completion_tokens = response.body.usage.completion_tokens;
+
+        Ok(self.record(response, entry))
+    }
+
+    async fn create_chat_stream_with_meta(
+        &self,
+        request: &model::create_chat::Request,
+    ) -> Result<ApiResponse<EventStream<model::create_chat::Chunk>>, error::Error> {
+        let response = self.inner.create_chat_stream_with_meta(request).await?;
+        let encoding = Encoding::from(&request.model);
+        let mut entry = self.entry("chat/completions", name(&request.model));
+        entry.prompt_tokens = encoding.count_messages(&request.messages);
+
+        Ok(
+            self.record_stream(response, entry, encoding, |chunk, replies| {
+                for choice in &chunk.choices {
+                    let reply = replies.entry(choice.index).or_default();
+                    let delta = &choice.delta;
+
+                    reply
+                        .content
+                        .push_str(delta.content.as_deref().unwrap_or_default());
+
+                    if let Some(function_call) = &delta.function_call {
+                        reply
+                            .name
+                            .push_str(function_call.name.as_deref().unwrap_or_default());
+                        reply
+                            .arguments
+                            .push_str(function_call.arguments.as_deref().unwrap_or_default());
+                    }
+                }
+            }),
+        )
+    }
+
+    async fn create_image_with_meta(
+        &self,
+        request: &model::create_image::Request,
+    ) -> Result<ApiResponse<model::create_image::Response>, error::Error> {
+        let response = self.inner.create_image_with_meta(request).await?;
+
+        Ok(self.record(response, self.entry("images/generations", None)))
+    }
+
+    async fn create_edit_with_meta(
+        &self,
+        request: &model::create_edit::Request,
+    ) -> Result<ApiResponse<model::create_edit::Response>, error::Error> {
+        let response = self.inner.create_edit_with_meta(request).await?;
+        let mut entry = self.entry("edits", name(&request.model));
+        entry.prompt_tokens = response.body.usage.prompt_tokens;
+        entry.completion_tokens = response.body.usage.completion_tokens;
+
+        Ok(self.record(response, entry))
+    }
+
+    async fn list_files_with_meta(
+        &self,
+    ) -> Result<ApiResponse<model::list_files::Response>, error::Error> {
+        let response = self.inner.list_files_with_meta().await?;
+
+        Ok(self.record(response, self.entry("files", None)))
+    }
+
+    async fn create_embedding_with_meta(
+        &self,
+        request: &model::create_embedding::Request,
+    ) -> Result<ApiResponse<model::create_embedding::Response>, error::Error> {
+        let response = self.inner.create_embedding_with_meta(request).await?;
+        let mut entry = self.entry("embeddings", name(&request.model));
+        entry.prompt_tokens = response.body.usage.prompt_tokens;
+
+        Ok(self.record(response, entry))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use model::create_chat::{Chunk, ChunkChoice, Delta, FunctionCallDelta, Model};
+    use openai_api::testing::{self, MockDatasource};
+
+    fn metered(name: &str) -> (sync::Arc<MockDatasource>, Metered) {
+        let path = std::env::temp_dir().join(format!(
+            "openai-cli-ledger-{}-{}.jsonl",
+            name,
+            std::process::id()
+        ));
+        let _ = fs::remove_file(&path);
+
+        let datasource = sync::Arc::new(MockDatasource::new());
+        let metered = Metered::new(
+            datasource.clone(),
+            Ledger { path },
+            "chat",
+            vec![String::from("test")],
+        );
+
+        (datasource, metered)
+    }
+
+    fn request() -> model::create_chat::Request {
+        model::create_chat::Request::new(
+            Model::Gpt4,
+            vec![model::create_chat::Message {
+                role: model::create_chat::Role::User,
+                content: Some(String::from("Hi")),
+                name: None,
+                function_call: None,
+            }],
+        )
+    }
+
+    fn chunk(choices: Vec<(usize, Delta)>) -> Chunk {
+        Chunk {
+            id: String::from("chatcmpl-mock"),
+            object: serde_json::from_value(serde_json::json!("chat.completion.chunk")).unwrap(),
+            created: 1,
+            model: String::from("gpt-4"),
+            choices: choices
+                .into_iter()
+                .map(|(index, delta)| ChunkChoice {
+                    index,
+                    delta,
+                    finish_reason: None,
+                })
+                .collect(),
+        }
+    }
+
+    fn content(text: &str) -> Delta {
+        Delta {
+            content: Some(String::from(text)),
+            ..Delta::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn stream_dropped_early_is_still_recorded() {
+        let (datasource, metered) = metered("dropped");
+        datasource.push_create_chat_stream(Ok(testing::chat_chunks(
+            Model::Gpt4,
+            &["Hello", " world", " again"],
+        )));
+
+        let mut stream = metered.create_chat_stream(&request()).await.unwrap();
+        stream.next().await.unwrap().unwrap();
+        stream.next().await.unwrap().unwrap();
+
+        assert!(metered.ledger.entries().unwrap().is_empty());
+
+        drop(stream);
+
+        let entries = metered.ledger.entries().unwrap();
+
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].endpoint, "chat/completions");
+        assert_eq!(entries[0].completion_tokens, 1);
+        assert!(entries[0].estimated);
+        assert_eq!(entries[0].tags, ["test"]);
+    }
+
+    #[tokio::test]
+    async fn every_choice_and_function_call_is_counted() {
+        let (datasource, metered) = metered("choices");
+        let call = Delta {
+            function_call: Some(FunctionCallDelta {
+                name: Some(String::from("get_weather")),
+                arguments: Some(String::from(r#"{"city": "Paris"}"#)),
+            }),
+            ..Delta::default()
+        };
+        datasource.push_create_chat_stream(Ok(vec![
+            chunk(vec![(0, content("Hello")), (1, content("Good"))]),
+            chunk(vec![(0, content(" world")), (1, content(" morning"))]),
+            chunk(vec![(2, call)]),
+        ]));
+
+        let chunks: Vec<_> = metered
+            .create_chat_stream(&request())
+            .await
+            .unwrap()
+            .collect()
+            .await;
+
+        let encoding = Encoding::Cl100kBase;
+        let expected = completion_tokens(&encoding, "Hello world", "", "")
+            + completion_tokens(&encoding, "Good morning", "", "")
+            + completion_tokens(&encoding, "", "get_weather", r#"{"city": "Paris"}"#);
+
+        assert_eq!(chunks.len(), 3);
+        assert_eq!(
+            metered.ledger.entries().unwrap()[0].completion_tokens,
+            expected
+        );
+        assert!(completion_tokens(&encoding, "", "get_weather", "{}") > 0);
+    }
+}
@@ -1,17 +1,100 @@
 use anyhow::Error;
-use std::{env, sync, time};
+use std::{env, path, str::FromStr, sync, time};
 use structopt::StructOpt;
 
+mod attach;
+mod config;
+mod context;
+mod ledger;
+mod markdown;
 mod presentation;
+mod session;
+mod tools;
+mod verbose;
 
 use presentation::command::Command;
 
 #[derive(StructOpt)]
 struct Opt {
-    // #[structopt(short, long)]
-    // version: bool,
+    /// Required unless replaying or running an offline command such as `tokens`
     #[structopt(long, env = "OPENAI_API_KEY", hide_env_values = true)]
-    api_key: String,
+    api_key: Option<String>,
+
+    /// The API to talk to: `openai` or `azure`
+    #[structopt(long, env = "OPENAI_PROVIDER", default_value = "openai")]
+    provider: Provider,
+
+    /// Includes the version path, as in http://localhost:8000/v1. With `--provider azure` it
+    /// is the resource endpoint instead
+    #[structopt(long, env = "OPENAI_BASE_URL")]
+    base_url: Option<String>,
+
+    /// The Azure OpenAI API version
+    #[structopt(long, env = "OPENAI_API_VERSION")]
+    api_version: Option<String>,
+
+    /// Routes a model to an Azure deployment, as in `gpt-4=my-gpt4`. Can be repeated
+    #[structopt(long = "deployment", number_of_values = 1, parse(try_from_str = parse_deployment))]
+    deployments: Vec<(String, String)>,
+
+    /// Sent as the OpenAI-Organization header
+    #[structopt(long, env = "OPENAI_ORGANIZATION")]
+    organization: Option<String>,
+
+    /// Sent as the OpenAI-Project header
+    #[structopt(long, env = "OPENAI_PROJECT")]
+    project: Option<String>,
+
+    /// An extra header for every request, as in `X-Team: search`. Can be repeated
+    #[structopt(long = "header", number_of_values = 1, parse(try_from_str = parse_header))]
+    headers: Vec<(String, String)>,
+
+    /// Replaces the default User-Agent header
+    #[structopt(long, env = "OPENAI_USER_AGENT")]
+    user_agent: Option<String>,
+
+    /// Seconds to wait for a connection
+    #[structopt(long, env = "OPENAI_CONNECT_TIMEOUT")]
+    connect_timeout: Option<u64>,
+
+    /// Seconds to wait for a response. Streamed replies may run longer, as long as no gap
+    /// between their chunks does
+    #[structopt(long, env = "OPENAI_TIMEOUT", default_value = "64")]
+    timeout: u64,
+
+    /// Retries after rate limits, server errors and failed connections
+    #[structopt(long, env = "OPENAI_MAX_RETRIES", default_value = "2")]
+    max_retries: usize,
+
+    /// Requests per minute to stay under. Zero, like leaving it unset, means no limit
+    #[structopt(long, env = "OPENAI_MAX_RPM")]
+    max_rpm: Option<u32>,
+
+    /// Tokens per minute to stay under. Zero, like leaving it unset, means no limit
+    #[structopt(long, env = "OPENAI_MAX_TPM")]
+    max_tpm: Option<u32>,
+
+    /// Records every request and response into this directory
+    #[structopt(long, conflicts_with = "replay")]
+    record: Option<path::PathBuf>,
+
+    /// Answers requests from a recorded directory without going online
+    #[structopt(long)]
+    replay: Option<path::PathBuf>,
+
+    /// How replayed requests are matched: `body` requires identical requests, `model` only
+    /// the same endpoint and model
+    #[structopt(long, env = "OPENAI_CASSETTE_MATCHING", default_value = "body")]
+    matching: openai_api::Matching,
+
+    /// Labels this run's entries in the usage ledger, e.g. with a project or ticket. Can be
+    /// repeated
+    #[structopt(long = "tag", number_of_values = 1)]
+    tags: Vec<String>,
+
+    /// Prints each response's request id, latency and rate limits to stderr
+    #[structopt(short, long)]
+    verbose: bool,
 
     #[structopt(subcommand)]
     subcommand: Subcommand,
@@ -26,6 +109,55 @@ enum Subcommand {
     Edit(presentation::edit::Opt),
     File(presentation::file::Opt),
     Embedding(presentation::embedding::Opt),
+    Tokens(presentation::tokens::Opt),
+    Usage(presentation::usage::Opt),
+}
+
+impl Subcommand {
+    fn name(&self) -> &'static str {
+        match self {
+            Subcommand::Model(_) => "model",
+            Subcommand::Completion(_) => "completion",
+            Subcommand::Chat(_) => "chat",
+            Subcommand::Image(_) => "image",
+            Subcommand::Edit(_) => "edit",
+            Subcommand::File(_) => "file",
+            Subcommand::Embedding(_) => "embedding",
+            Subcommand::Tokens(_) => "tokens",
+            Subcommand::Usage(_) => "usage",
+        }
+    }
+}
+
+enum Provider {
+    OpenAI,
+    Azure,
+}
+
+impl FromStr for Provider {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "openai" => Ok(Self::OpenAI),
+            "azure" => Ok(Self::Azure),
+            _ => Err(anyhow::anyhow!("unsupported provider: {}", s)),
+        }
+    }
+}
+
+fn parse_deployment(s: &str) -> Result<(String, String), Error> {
+    match s.split_once('=') {
+        Some((model, deployment)) => Ok((model.trim().to_string(), deployment.trim().to_string())),
+        None => Err(anyhow::anyhow!("expected MODEL=DEPLOYMENT, got {}", s)),
+    }
+}
+
+fn parse_header(s: &str) -> Result<(String, String), Error> {
+    match s.split_once(':') {
+        Some((name, value)) => Ok((name.trim().to_string(), value.trim().to_string())),
+        None => Err(anyhow::anyhow!("expected NAME:VALUE, got {}", s)),
+    }
 }
 
 #[tokio::main]
@@ -34,27 +166,116 @@ async fn main() -> Result<(), Error> {
     env_logger::init();
 
     let opt = Opt::from_args();
-    let api_key = opt.api_key;
 
-    // if opt.version {
-    //     return println!("{}", env::var("CARGO_PKG_VERSION").unwrap());
-    // }
+    match &opt.subcommand {
+        Subcommand::Tokens(opt) => return opt.run(),
+        Subcommand::Usage(opt) => return opt.run(),
+        _ => {}
+    }
+
+    if opt.api_key.is_none() && opt.replay.is_none() {
+        return Err(anyhow::anyhow!(
+            "an API key is required, set --api-key or OPENAI_API_KEY"
+        ));
+    }
+
+    let connect_timeout = opt.connect_timeout.map(time::Duration::from_secs);
+    let timeout = Some(time::Duration::from_secs(opt.timeout));
+    let retry_policy = openai_api::RetryPolicy::default().max_attempts(opt.max_retries + 1);
+    let rate_limits = openai_api::RateLimits::default()
+        .requests_per_minute(opt.max_rpm)
+        .tokens_per_minute(opt.max_tpm);
+
+    // Replayed calls cost nothing, so they stay out of the usage ledger.
+    let replaying = opt.replay.is_some();
+
+    let datasource: sync::Arc<dyn openai_api::Datasource + Send + Sync> = match opt.provider {
+        Provider::OpenAI => {
+            let builder = opt.headers.into_iter().fold(
+                openai_api::OpenAIApi::builder()
+                    .api_key(opt.api_key.unwrap_or_default())
+                    .base_url(opt.base_url)
+                    .organization(opt.organization)
+                    .project(opt.project)
+                    .user_agent(opt.user_agent)
+                    .connect_timeout(connect_timeout)
+                    .timeout(timeout)
+                    .record(opt.record)
+                    .replay(opt.replay)
+                    .matching(opt.matching),
+                |builder, (name, value)| builder.header(name, value),
+            );
+
+            sync::Arc::new(openai_api::Retry::new(
+                openai_api::RateLimited::new(builder.build()?, rate_limits),
+                retry_policy,
+            ))
+        }
+        Provider::Azure => {
+            if opt.record.is_some() || opt.replay.is_some() {
+                return Err(anyhow::anyhow!(
+                    "recording and replaying are only supported with the openai provider"
+                ));
+            }
+
+            let builder = opt.headers.into_iter().fold(
+                openai_api::AzureOpenAIApi::builder()
+                    .api_key(opt.api_key.unwrap_or_default())
+                    .endpoint(opt.base_url)
+                    .api_version(opt.api_version)
+                    .user_agent(opt.user_agent)
+                    .connect_timeout(connect_timeout)
+                    .timeout(timeout),
+                |builder, (name, value)| builder.header(name, value),
+            );
+
+            let builder = opt
+                .deployments
+                .into_iter()
+                .fold(builder, |builder, (model, deployment)| {
+                    builder.deployment(model, deployment)
+                });
+
+            sync::Arc::new(openai_api::Retry::new(
+                openai_api::RateLimited::new(builder.build()?, rate_limits),
+                retry_policy,
+            ))
+        }
+    };
+
+    // Without a data directory there is nowhere to keep the ledger, which is no reason to
+    // refuse the request.
+    let ledger = match replaying {
+        true => None,
+        false => ledger::Ledger::new()
+            .map_err(|error| log::warn!("usage ledger: {}", error))
+            .ok(),
+    };
+
+    let datasource: sync::Arc<dyn openai_api::Datasource + Send + Sync> = match ledger {
+        Some(ledger) => sync::Arc::new(ledger::Metered::new(
+            datasource,
+            ledger,
+            opt.subcommand.name(),
+            opt.tags,
+        )),
+        None => datasource,
+    };
 
-    let http_client = sync::Arc::new(
-        reqwest::Client::builder()
-            .timeout(time::Duration::from_secs(64))
-            .build()
-            .expect("Creating HTTP Client"),
-    );
+    let datasource: sync::Arc<dyn openai_api::Datasource + Send + Sync> = match opt.verbose {
+        true => sync::Arc::new(verbose::Verbose::new(datasource)),
+        false => datasource,
+    };
 
     match opt.subcommand {
-        Subcommand::Model(opt) => opt.run(http_client, api_key).await?,
-        Subcommand::Completion(opt) => opt.run(http_client, api_key).await?,
-        Subcommand::Chat(opt) => opt.run(http_client, api_key).await?,
-        Subcommand::Image(opt) => opt.run(http_client, api_key).await?,
-        Subcommand::Edit(opt) => opt.run(http_client, api_key).await?,
-        Subcommand::File(opt) => opt.run(http_client, api_key).await?,
-        Subcommand::Embedding(opt) => opt.run(http_client, api_key).await?,
+        Subcommand::Model(opt) => opt.run(datasource).await?,
+        Subcommand::Completion(opt) => opt.run(datasource).await?,
+        Subcommand::Chat(opt) => opt.run(datasource).await?,
+        Subcommand::Image(opt) => opt.run(datasource).await?,
+        Subcommand::Edit(opt) => opt.run(datasource).await?,
+        Subcommand::File(opt) => opt.run(datasource).await?,
+        Subcommand::Embedding(opt) => opt.run(datasource).await?,
+        Subcommand::Tokens(_) | Subcommand::Usage(_) => unreachable!(),
     }
 
     Ok(())
@@ -0,0 +1,588 @@
+use console::{Alignment, Style};
+use pulldown_cmark::{CodeBlockKind, Event, HeadingLevel, Options, Parser, Tag};
+use std::sync;
+use syntect::{
+    easy::HighlightLines,
+    highlighting::{Theme, ThemeSet},
+    parsing::SyntaxSet,
+    util::{as_24_bit_terminal_escaped, LinesWithEndings},
+};
+
+const THEME: &str = "base16-ocean.dark";
+const RULE_WIDTH: usize = 40;
+
+static SYNTAXES: sync::OnceLock<SyntaxSet> = sync::OnceLock::new();
+static THEMES: sync::OnceLock<ThemeSet> = sync::OnceLock::new();
+
+// Renders markdown for the terminal with `console` styles. The result has no trailing newline.
+pub fn render(markdown: &str) -> String {
+    let mut writer = Writer::default();
+    let options =
+        Options::ENABLE_TABLES | Options::ENABLE_STRIKETHROUGH | Options::ENABLE_TASKLISTS;
+
+    for event in Parser::new_ext(markdown, options) {
+        writer.event(event);
+    }
+
+    writer.out.trim_end().to_string()
+}
+
+// Renders a streamed reply a block at a time. A block is complete at a blank line outside a
+// code fence once the next block has begun, so lists and fences are never cut in half.
+#[derive(Default)]
+pub struct Stream {
+    buffer: String,
+    started: bool,
+}
+
+impl Stream {
+    pub fn push(&mut self, chunk: &str) -> String {
+        self.buffer.push_str(chunk);
+
+        match boundary(&self.buffer) {
+            Some(end) => {
+                let block: String = self.buffer.drain(..end).collect();
+                self.block(&block)
+            }
+            None => String::new(),
+        }
+    }
+
+    pub fn finish(&mut self) -> String {
+        let block = std::mem::take(&mut self.buffer);
+        self.block(&block)
+    }
+
+    fn block(&mut self, block: &str) -> String {
+        let rendered = render(block);
+
+        match (rendered.is_empty(), self.started) {
+            (true, _) => rendered,
+            (false, true) => format!("\n\n{}", rendered),
+            (false, false) => {
+                self.started = true;
+                rendered
+            }
+        }
+    }
+}
+
+// The offset just past the last blank line that is outside a fence and followed by an
+// unindented line, which would otherwise continue the block above it.
+fn boundary(text: &str) -> Option<usize> {
+    let mut fenced = false;
+    let mut blank = None;
+    let mut boundary = None;
+    let mut offset = 0;
+
+    for line in text.split_inclusive('\n') {
+        let trimmed = line.trim_start();
+
+        if let Some(end) = blank.take() {
+            if !line.starts_with(char::is_whitespace) && !fenced {
+                boundary = Some(end);
+            }
+        }
+
+        if trimmed.starts_with("```") || trimmed.starts_with("~~~") {
+            fenced = !fenced;
+        }
+
+        offset += line.len();
+
+        if line.ends_with('\n') && line.trim().is_empty() && !fenced {
+            blank = Some(offset);
+        }
+    }
+
+    boundary
+}
+
+#[derive(Default)]
+struct Writer {
+    out: String,
+    prefixes: Vec<String>,
+    line_start: bool,
+    item_start: bool,
+    lists: Vec<Option<u64>>,
+    bold: usize,
+    italic: usize,
+    strikethrough: usize,
+    underlined: usize,
+    links: Vec<String>,
+    code: Option<(String, String)>,
+    table: Option<Table>,
+}
+
+struct Table {
+    alignments: Vec<pulldown_cmark::Alignment>,
+    rows: Vec<Vec<String>>,
+    head: bool,
+}
+
+impl Writer {
+    fn event(&mut self, event: Event) {
+        match event {
+            Event::Start(tag) => self.start(tag),
+            Event::End(tag) => self.end(tag),
+            Event::Text(text) => match &mut self.code {
+                Some((_, code)) => code.push_str(&text),
+                None => self.text(&text, self.style()),
+            },
+            Event::Code(code) => self.text(&code, self.style().yellow()),
+            Event::Html(html) => self.text(&html, self.style()),
+            Event::FootnoteReference(label) => self.text(&format!("[^{}]", label), self.style()),
+            Event::SoftBreak => self.text(" ", Style::new()),
+            Event::HardBreak => self.newline(),
+            Event::Rule => {
+                self.block();
+                self.text(&"─".repeat(RULE_WIDTH), Style::new().dim());
+            }
+            Event::TaskListMarker(checked) => {
+                let marker = match checked {
+                    true => "[x] ",
+                    false => "[ ] ",
+                };
+
+                self.text(marker, Style::new().dim());
+            }
+        }
+    }
+
+    fn start(&mut self, tag: Tag) {
+        match tag {
+            Tag::Paragraph => match self.item_start {
+                true => self.item_start = false,
+                false => self.block(),
+            },
+            Tag::Heading(level, _, _) => {
+                self.block();
+                self.bold += 1;
+
+                if level == HeadingLevel::H1 {
+                    self.underlined += 1;
+                }
+            }
+            Tag::BlockQuote => {
+                self.block();
+                self.prefixes
+                    .push(Style::new().dim().apply_to("│ ").to_string());
+            }
+            Tag::CodeBlock(kind) => {
+                self.block();
+
+                let language = match kind {
+                    CodeBlockKind::Fenced(info) => info
+                        .split_whitespace()
+                        .next()
+                        .unwrap_or_default()
+                        .to_string(),
+                    CodeBlockKind::Indented => String::new(),
+                };
+
+                self.code = Some((language, String::new()));
+            }
+            Tag::List(start) => {
+                match self.lists.is_empty() {
+                    true => self.block(),
+                    false => self.newline(),
+                }
+
+                self.lists.push(start);
+            }
+            Tag::Item => {
+                if !self.line_start {
+                    self.newline();
+                }
+
+                let marker = match self.lists.last_mut() {
+                    Some(Some(number)) => {
+                        *number += 1;
+                        format!("{}. ", *number - 1)
+                    }
+                    _ => String::from("• "),
+                };
+
+                self.text(&marker, Style::new().dim());
+                self.prefixes.push(" ".repeat(marker.chars().count()));
+                self.item_start = true;
+            }
+            Tag::Table(alignments) => {
+                self.block();
+                self.table = Some(Table {
+                    alignments,
+                    rows: vec![],
+                    head: false,
+                });
+            }
+            Tag::TableHead => {
+                if let Some(table) = &mut self.table {
+                    table.head = true;
+                    table.rows.push(vec![]);
+                }
+            }
+            Tag::TableRow => {
+                if let Some(table) = &mut self.table {
+                    table.rows.push(vec![]);
+                }
+            }
+            Tag::TableCell => {
+                if let Some(row) = self.table.as_mut().and_then(|table| table.rows.last_mut()) {
+                    row.push(String::new());
+                }
+            }
+            Tag::Emphasis => self.italic += 1,
+            Tag::Strong => self.bold += 1,
+            Tag::Strikethrough => self.strikethrough += 1,
+            Tag::Link(_, url, _) | Tag::Image(_, url, _) => {
+                self.underlined += 1;
+                self.links.push(url.to_string());
+            }
+            Tag::FootnoteDefinition(label) => {
+                self.block();
+                self.text(&format!("[^{}]: ", label), Style::new().dim());
+            }
+        }
+    }
+
+    fn end(&mut self, tag: Tag) {
+        match tag {
+            Tag::Paragraph | Tag::FootnoteDefinition(_) => {}
+            Tag::Heading(level, _, _) => {
+                self.bold -= 1;
+
+                if level == HeadingLevel::H1 {
+                    self.underlined -= 1;
+                }
+            }
+            Tag::BlockQuote => {
+                self.prefixes.pop();
+            }
+            Tag::CodeBlock(_) => {
+                if let Some((language, code)) = self.code.take() {
+                    self.code_block(&language, &code);
+                }
+            }
+            Tag::List(_) => {
+                self.lists.pop();
+            }
+            Tag::Item => {
+                self.prefixes.pop();
+                self.item_start = false;
+            }
+            Tag::Table(_) => {
+                if let Some(table) = self.table.take() {
+                    self.table_block(table);
+                }
+            }
+            Tag::TableHead => {
+                if let Some(table) = &mut self.table {
+                    table.head = false;
+                }
+            }
+            Tag::TableRow | Tag::TableCell => {}
+            Tag::Emphasis => self.italic -= 1,
+            Tag::Strong => self.bold -= 1,
+            Tag::Strikethrough => self.strikethrough -= 1,
+            Tag::Link(..) | Tag::Image(..) => {
+                self.underlined -= 1;
+
+                if let Some(url) = self.links.pop() {
+                    self.text(&format!(" ({})", url), Style::new().dim());
+                }
+            }
+        }
+    }
+
+    fn style(&self) -> Style {
+        let mut style = Style::new();
+
+        if self.bold > 0 || self.table.as_ref().is_some_and(|table| table.head) {
+            style = style.bold();
+        }
+
+        if self.italic > 0 {
+            style = style.italic();
+        }
+
+        if self.strikethrough > 0 {
+            style = style.strikethrough();
+        }
+
+        if self.underlined > 0 {
+            style = style.underlined();
+        }
+
+        style
+    }
+
+    fn text(&mut self, text: &str, style: Style) {
+        if let Some(cell) = self
+            .table
+            .as_mut()
+            .and_then(|table| table.rows.last_mut())
+            .and_then(|row| row.last_mut())
+        {
+            cell.push_str(&style.apply_to(text).to_string());
+            return;
+        }
+
+        for (index, line) in text.split('\n').enumerate() {
+            if index > 0 {
+                self.newline();
+            }
+
+            if !line.is_empty() {
+                self.raw(&style.apply_to(line).to_string());
+            }
+        }
+    }
+
+    // Writes already styled text, prefixed when it starts a line.
+    fn raw(&mut self, text: &str) {
+        if self.line_start {
+            self.out.push_str(&self.prefixes.concat());
+            self.line_start = false;
+        }
+
+        self.out.push_str(text);
+    }
+
+    fn newline(&mut self) {
+        self.out.push('\n');
+        self.line_start = true;
+    }
+
+    // Separates a block from whatever came before it with a blank line.
+    fn block(&mut self) {
+        if self.out.is_empty() {
+            self.line_start = true;
+            return;
+        }
+
+        if !self.line_start {
+            self.newline();
+        }
+
+        if !self.out.ends_with("\n\n") {
+            self.out.push_str(self.prefixes.concat().trim_end());
+            self.newline();
+        }
+    }
+
+    fn code_block(&mut self, language: &str, code: &str) {
+        let code = code.trim_end_matches('\n');
+
+        if !console::colors_enabled() {
+            for (index, line) in code.split('\n').enumerate() {
+                if index > 0 {
+                    self.newline();
+                }
+
+                self.raw(line);
+            }
+
+            return;
+        }
+
+        let syntaxes = SYNTAXES.get_or_init(SyntaxSet::load_defaults_newlines);
+        let syntax = syntaxes
+            .find_syntax_by_token(language)
+            .unwrap_or_else(|| syntaxes.find_syntax_plain_text());
+        let mut highlighter = HighlightLines::new(syntax, theme());
+
+        for (index, line) in LinesWithEndings::from(code).enumerate() {
+            if index > 0 {
+                self.newline();
+            }
+
+            // A line the highlighter cannot parse is still shown, just without colors.
+            // The line ending is dropped before the reset, which would otherwise keep it from
+            // being trimmed and leave a blank line after every line of code.
+            let line = match highlighter.highlight_line(line, syntaxes) {
+                Ok(ranges) => format!(
+                    "{}\x1b[0m",
+                    as_24_bit_terminal_escaped(&ranges, false).trim_end_matches('\n')
+                ),
+                Err(_) => line.trim_end_matches('\n').to_string(),
+            };
+
+            self.raw(&line);
+        }
+    }
+
+    fn table_block(&mut self, table: Table) {
+        let columns = table.rows.iter().map(Vec::len).max().unwrap_or_default();
+        let widths: Vec<usize> = (0..columns)
+            .map(|column| {
+                table
+                    .rows
+                    .iter()
+                    .filter_map(|row| row.get(column))
+                    .map(|cell| console::measure_text_width(cell))
+                    .max()
+                    .unwrap_or_default()
+            })
+            .collect();
+        let separator = Style::new().dim().apply_to(" │ ").to_string();
+
+        for (index, row) in table.rows.iter().enumerate() {
+            if index > 0 {
+                self.newline();
+            }
+
+            let cells: Vec<String> = widths
+                .iter()
+                .enumerate()
+                .map(|(column, width)| {
+                    let cell = row.get(column).map(String::as_str).unwrap_or_default();
+                    let alignment = match table.alignments.get(column) {
+                        Some(pulldown_cmark::Alignment::Center) => Alignment::Center,
+                        Some(pulldown_cmark::Alignment::Right) => Alignment::Right,
+                        _ => Alignment::Left,
+                    };
+
+                    console::pad_str(cell, *width, alignment, None).into_owned()
+                })
+                .collect();
+
+            self.raw(cells.join(&separator).trim_end());
+
+            // The header row is ruled off from the body.
+            if index == 0 {
+                let rule: Vec<String> = widths.iter().map(|width| "─".repeat(*width)).collect();
+
+                self.newline();
+                self.raw(&Style::new().dim().apply_to(rule.join("─┼─")).to_string());
+            }
+        }
+    }
+}
+
+fn theme() -> &'static Theme {
+    let themes = THEMES.get_or_init(ThemeSet::load_defaults);
+
+    themes
+        .themes
+        .get(THEME)
+        .or_else(|| themes.themes.values().next())
+        .expect("bundled syntax themes")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Colors are a process-wide switch, so tests that render hold this while they run.
+    static COLORS: sync::Mutex<()> = sync::Mutex::new(());
+
+    fn colors(enabled: bool) -> sync::MutexGuard<'static, ()> {
+        let guard = COLORS.lock().unwrap_or_else(|error| error.into_inner());
+        console::set_colors_enabled(enabled);
+
+        guard
+    }
+
+    #[test]
+    fn headings_are_set_apart() {
+        let _colors = colors(false);
+
+        assert_eq!(
+            render("# Title\nIntro text.\n## Section\nMore."),
+            "Title\n\nIntro text.\n\nSection\n\nMore."
+        );
+    }
+
+    #[test]
+    fn lists_are_marked_and_indented() {
+        let _colors = colors(false);
+
+        assert_eq!(
+            render("- one\n- two\n  - nested\n\n3. three\n4. four"),
+            "• one\n• two\n  • nested\n\n3. three\n4. four"
+        );
+        assert_eq!(render("- [x] done\n- [ ] open"), "• [x] done\n• [ ] open");
+    }
+
+    #[test]
+    fn tables_are_aligned() {
+        let _colors = colors(false);
+
+        assert_eq!(
+            render("| Name | Qty |\n|------|----:|\n| apple | 3 |\n| fig | 12 |"),
+            "Name  │ Qty\n──────┼────\napple │   3\nfig   │  12"
+        );
+    }
+
+    #[test]
+    fn inline_markup_without_color() {
+        let _colors = colors(false);
+
+        assert_eq!(
+            render(
+                "Some **bold**, _italic_ and `code`, see [docs](https://example.com).\n\n> quoted"
+            ),
+            "Some bold, italic and code, see docs (https://example.com).\n\n│ quoted"
+        );
+    }
+
+    #[test]
+    fn code_without_color_is_left_plain() {
+        let _colors = colors(false);
+
+        assert_eq!(
+            render("Before\n\n```rust\nfn main() {\n    println!(\"hi\");\n}\n```\n\nAfter"),
+            "Before\n\nfn main() {\n    println!(\"hi\");\n}\n\nAfter"
+        );
+    }
+
+    #[test]
+    fn code_with_color_is_highlighted() {
+        let _colors = colors(true);
+
+        let rendered = render("```rust\nfn main() {}\nlet x = 1;\n```");
+        let unknown = render("```nosuchlanguage\nplain words\n```");
+
+        console::set_colors_enabled(false);
+
+        assert!(rendered.contains("\x1b[38;2;"));
+        assert_eq!(
+            console::strip_ansi_codes(&rendered),
+            "fn main() {}\nlet x = 1;"
+        );
+        assert_eq!(console::strip_ansi_codes(&unknown), "plain words");
+    }
+
+    #[test]
+    fn boundary_after_blank_line() {
+        assert_eq!(boundary("a\n\nb"), Some(3));
+        assert_eq!(boundary("a\n\nb\n\nc"), Some(6));
+    }
+
+    #[test]
+    fn no_boundary_without_a_following_line() {
+        assert_eq!(boundary("a\n"), None);
+        assert_eq!(boundary("a\n\n"), None);
+    }
+
+    #[test]
+    fn indented_line_continues_the_block() {
+        assert_eq!(boundary("- a\n\n  b"), None);
+    }
+
+    #[test]
+    fn blank_lines_inside_fences_are_not_boundaries() {
+        assert_eq!(boundary("```\na\n\nb\n```\n"), None);
+        assert_eq!(boundary("~~~\na\n\nb"), None);
+    }
+
+    #[test]
+    fn boundary_after_a_closed_fence() {
+        assert_eq!(boundary("```\na\n```\n\nb"), Some(11));
+    }
+
+    #[test]
+    fn whitespace_only_line_is_blank() {
+        assert_eq!(boundary("a\n  \nb"), Some(5));
+    }
+}
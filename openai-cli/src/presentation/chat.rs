@@ -1,11 +1,27 @@
 use anyhow::Error;
 use async_trait::async_trait;
 use console;
-use openai_api::Datasource;
-use std::{io, sync};
+use futures::StreamExt;
+use openai_api::{tokenizer::Encoding, Datasource};
+use std::{
+    fs,
+    io::{self, Write},
+    path, process,
+    str::FromStr,
+    sync,
+};
 use structopt::StructOpt;
 
-use super::command::Command;
+use super::{command::Command, sampling};
+use crate::{attach, config, context, ledger, markdown, session, tools};
+
+mod commands;
+mod editor;
+
+const DEFAULT_MODEL: &str = "gpt-3.5-turbo-0613";
+const DEFAULT_SYSTEM: &str = "You are a very helpful assistant";
+const EXIT_TRUNCATED: i32 = 3;
+const MAX_FUNCTION_CALLS: usize = 8;
 
 #[derive(StructOpt)]
 pub struct Opt {
@@ -13,140 +29,753 @@ pub struct Opt {
     pub subcommand: Subcommand,
 }
 
+// Parsed once per run, so the size of `Create` costs nothing worth boxing for.
+#[allow(clippy::large_enum_variant)]
 #[derive(StructOpt)]
 pub enum Subcommand {
     Create(Create),
+    Sessions(Sessions),
 }
 
 #[derive(StructOpt)]
 pub struct Create {
-    #[structopt(long, short, default_value = "gpt-3.5-turbo-0613")]
-    pub model: openai_api::model::create_chat::Model,
+    // Sends a single turn and prints only the reply. `-` reads the prompt from stdin.
+    pub prompt: Option<String>,
+
+    // Defaults to the resumed session's model, then to gpt-3.5-turbo-0613.
+    #[structopt(long, short)]
+    pub model: Option<openai_api::model::create_chat::Model>,
 
     #[structopt(long)]
     pub max_tokens: Option<usize>,
 
     #[structopt(long, short)]
     pub temperature: Option<f32>,
+
+    #[structopt(flatten)]
+    pub sampling: sampling::Opt,
+
+    #[structopt(long)]
+    pub no_stream: bool,
+
+    // Prints replies as the model wrote them instead of rendering their markdown.
+    #[structopt(long)]
+    pub raw: bool,
+
+    // Asks for several candidate replies to pick from, which are then not streamed.
+    #[structopt(short, long = "number", alias = "n")]
+    pub n: Option<usize>,
+
+    #[structopt(long)]
+    pub session: Option<String>,
+
+    #[structopt(long, conflicts_with = "system-file")]
+    pub system: Option<String>,
+
+    #[structopt(long)]
+    pub system_file: Option<path::PathBuf>,
+
+    /// Loaded from personas/<name>.yaml in the config directory. Explicit flags still win
+    #[structopt(long)]
+    pub persona: Option<String>,
+
+    // A JSON or YAML file mapping functions the model may call to local commands.
+    #[structopt(long)]
+    pub functions: Option<path::PathBuf>,
+
+    #[structopt(long)]
+    pub auto_approve: bool,
+
+    /// A file, directory or glob to attach to the first message. Can be repeated
+    #[structopt(long = "file", number_of_values = 1)]
+    pub files: Vec<String>,
+
+    /// The most tokens attached files may add to a message, by default half the context window
+    #[structopt(long)]
+    pub attach_budget: Option<usize>,
+
+    /// What gives way when the history outgrows the model's context window: `drop-oldest`
+    /// drops the oldest turns, `pinned` drops the oldest after the first --pin turns, and
+    /// `summarize` replaces them with a summary
+    #[structopt(long, default_value = "drop-oldest")]
+    pub context: context::Strategy,
+
+    /// The number of leading turns the pinned strategy keeps
+    #[structopt(long, default_value = "1")]
+    pub pin: usize,
+}
+
+#[derive(StructOpt)]
+pub enum Sessions {
+    List,
+    Show { name: String },
+    Delete { name: String },
+    Rename { from: String, to: String },
 }
 
 #[async_trait]
 impl Command for Opt {
-    async fn run(
-        &self,
-        http_client: sync::Arc<reqwest::Client>,
-        api_key: String,
-    ) -> Result<(), Error> {
-        let datasource = openai_api::OpenAIApi::new(http_client, api_key);
-
-        let messages: Vec<openai_api::model::create_chat::Message> =
-            vec![openai_api::model::create_chat::Message {
-                role: openai_api::model::create_chat::Role::System,
-                content: Some(String::from("You are a very helpful assistant")),
-                name: None,
-                function_call: None,
-            }];
-
-        // let functions = vec![openai_api::model::function::Function::new(
-        //     String::from("test"),
-        //     String::from("this function is great for testing"),
-        // )
-        // .add_property(
-        //     String::from("test"),
-        //     openai_api::model::function::Parameter::String(
-        //         openai_api::model::function::JsonString::new(Some(String::from("Test")), None),
-        //     ),
-        //     true,
-        // )
-        // .add_property(
-        //     String::from("test2"),
-        //     openai_api::model::function::Parameter::Object(
-        //         openai_api::model::function::JsonObject::new(
-        //             HashMap::from([
-        //                 (
-        //                     String::from("test"), openai_api::model::function::Parameter::Object(
-        //                         openai_api::model::function::JsonObject::new(
-        //                             HashMap::from([
-        //                                 (
-        //                                     String::from("test"),
-        //                                     openai_api::model::function::Parameter::String(
-        //                                         openai_api::model::function::JsonString::new(
-        //                                             None,
-        //                                             Some(vec![
-        //                                                 String::from("this"),
-        //                                                 String::from("is"),
-        //                                                 String::from("a"),
-        //                                                 String::from("test")
-        //                                             ])
-        //                                         )
-        //                                     )
-        //                                 )
-        //                             ]),
-        //                             vec![])
-        //                     )
-        //                 )
-        //             ]),
-        //             vec![]
-        //         )
-        //     ),
-        //     true,
-        // )];
-
-        let mut request = match &self.subcommand {
-            Subcommand::Create(opt) => {
-                openai_api::model::create_chat::Request::new(&opt.model, messages)
-                // .functions(functions)
+    async fn run(&self, datasource: sync::Arc<dyn Datasource + Send + Sync>) -> Result<(), Error> {
+        match &self.subcommand {
+            Subcommand::Create(opt) => create(datasource, opt).await,
+            Subcommand::Sessions(opt) => sessions(opt),
+        }
+    }
+}
+
+async fn create(
+    datasource: sync::Arc<dyn Datasource + Send + Sync>,
+    opt: &Create,
+) -> Result<(), Error> {
+    let store = session::Store::new()?;
+    let saved = match &opt.session {
+        Some(name) => store.load(name)?,
+        None => None,
+    };
+
+    let persona = match &opt.persona {
+        Some(name) => config::Persona::load(name)?,
+        None => config::Persona::default(),
+    };
+
+    let system = match (&opt.system, &opt.system_file) {
+        (Some(system), _) => Some(system.clone()),
+        (None, Some(system_file)) => Some(fs::read_to_string(system_file)?),
+        (None, None) => persona.system,
+    };
+
+    let model = match (opt.model.as_ref().or(persona.model.as_ref()), &saved) {
+        (Some(model), _) => model.clone(),
+        (None, Some(saved)) => saved.model.clone(),
+        (None, None) => openai_api::model::create_chat::Model::from_str(DEFAULT_MODEL)?,
+    };
+    let max_tokens = opt
+        .max_tokens
+        .or(persona.max_tokens)
+        .or(saved.as_ref().and_then(|saved| saved.max_tokens));
+    let temperature = opt
+        .temperature
+        .or(persona.temperature)
+        .or(saved.as_ref().and_then(|saved| saved.temperature));
+    let sampling = session::Sampling {
+        top_p: opt.sampling.top_p,
+        stop: opt.sampling.stop()?,
+        presence_penalty: opt.sampling.presence_penalty,
+        frequency_penalty: opt.sampling.frequency_penalty,
+        logit_bias: opt.sampling.logit_bias(),
+        n: opt.n,
+    }
+    .or(saved
+        .as_ref()
+        .map(|saved| saved.sampling.clone())
+        .unwrap_or_default());
+
+    let mut session = match saved {
+        Some(mut saved) => {
+            eprintln!(
+                "{}",
+                console::Style::new().dim().apply_to(format!(
+                    "Resuming session {} with {} messages",
+                    opt.session.as_deref().unwrap_or_default(),
+                    saved.messages.len()
+                ))
+            );
+
+            saved.model = model.clone();
+            saved.max_tokens = max_tokens;
+            saved.temperature = temperature;
+            saved.sampling = sampling;
+
+            // A resumed transcript keeps its system message unless a new one was asked for.
+            if let Some(system) = system {
+                let message = system_message(system);
+
+                match saved.messages.first() {
+                    Some(first)
+                        if matches!(first.role, openai_api::model::create_chat::Role::System) =>
+                    {
+                        saved.messages[0] = message
+                    }
+                    _ => saved.messages.insert(0, message),
+                }
+            }
+
+            saved
+        }
+        None => session::Session::new(
+            model.clone(),
+            max_tokens,
+            temperature,
+            sampling,
+            vec![system_message(
+                system.unwrap_or_else(|| String::from(DEFAULT_SYSTEM)),
+            )],
+        ),
+    };
+
+    let tools = opt
+        .functions
+        .as_deref()
+        .map(tools::Tools::load)
+        .transpose()?;
+
+    let mut request = openai_api::model::create_chat::Request::new(model, session.messages.clone())
+        .max_tokens(max_tokens)
+        .temperature(temperature);
+    session.sampling.apply(&mut request)?;
+
+    if let Some(tools) = &tools {
+        request = request.functions(tools.functions());
+    }
+
+    let mut conversation = Conversation {
+        datasource: datasource.as_ref(),
+        opt,
+        store: &store,
+        session: &mut session,
+        tools: tools.as_ref(),
+        window: context::Window::new(opt.context, opt.pin),
+        stream: streams(opt, &request),
+        markdown: !opt.raw && console::Term::stdout().is_term(),
+        attachments: attach::Attachments::new(opt.attach_budget),
+    };
+
+    for pattern in &opt.files {
+        let files = conversation.attachments.add(pattern, &request.model)?;
+
+        eprintln!("{}", console::Style::new().dim().apply_to(attached(files)));
+    }
+
+    if let Some(prompt) = &opt.prompt {
+        let content = match prompt.as_str() {
+            "-" => io::read_to_string(io::stdin())?,
+            prompt => prompt.to_string(),
+        };
+
+        if content.trim().is_empty() {
+            return Err(anyhow::anyhow!("empty prompt"));
+        }
+
+        request
+            .messages
+            .push(user_message(conversation.attachments.take(content)));
+
+        let finish_reason = conversation.turn(&mut request, None).await?;
+
+        // Scripts can tell a reply cut short by max_tokens apart from a complete one.
+        if let Some(openai_api::model::create_chat::FinishReason::Length) = finish_reason {
+            eprintln!("reply truncated at the token limit");
+            io::stdout().flush()?;
+            process::exit(EXIT_TRUNCATED);
+        }
+
+        return Ok(());
+    }
+
+    let styles = Styles {
+        assistant_response: console::Style::new().blue(),
+        assistant: console::Style::new().dim(),
+    };
+
+    println!(
+        "{}",
+        styles
+            .assistant_response
+            .apply_to("What can I assist you with?")
+    );
+
+    let mut editor = editor::Editor::new()?;
+
+    loop {
+        let Some(content) = editor.read()? else {
+            println!();
+
+            return Ok(());
+        };
+
+        // Lines starting with a slash control the conversation; `//` sends a literal slash.
+        match content.strip_prefix('/') {
+            Some(line) if !line.starts_with('/') => {
+                if let Err(error) = conversation.command(&mut request, line, &styles).await {
+                    eprintln!("{}", console::Style::new().red().apply_to(error));
+                }
+
+                continue;
+            }
+            Some(line) => request.messages.push(user_message(
+                conversation.attachments.take(line.to_string()),
+            )),
+            None => request
+                .messages
+                .push(user_message(conversation.attachments.take(content))),
+        }
+
+        // A failed turn leaves the conversation as it was, so the message can be sent again
+        // from the editor's history instead of sitting unanswered in the transcript.
+        let sent = request.messages.len();
+
+        if let Err(error) = conversation.turn(&mut request, Some(&styles)).await {
+            eprintln!("{}", console::Style::new().red().apply_to(error));
+
+            if request.messages.len() == sent {
+                request.messages.pop();
+            }
+        }
+
+        println!()
+    }
+}
+
+// Several candidates are only offered for a reply that arrives whole.
+fn streams(opt: &Create, request: &openai_api::model::create_chat::Request) -> bool {
+    !opt.no_stream && request.n.unwrap_or(1) <= 1
+}
+
+struct Styles {
+    assistant_response: console::Style,
+    assistant: console::Style,
+}
+
+struct Reply {
+    message: openai_api::model::create_chat::Message,
+    finish_reason: Option<openai_api::model::create_chat::FinishReason>,
+    usage: Option<openai_api::model::create_chat::Usage>,
+    estimated: bool,
+}
+
+// Prints the reply as it arrives. Without styles only the assistant content is written,
+// which is what one-shot mode pipes to other programs. Markdown is rendered a block at a
+// time, so a streamed reply still appears progressively.
+async fn reply(
+    datasource: &(dyn Datasource + Send + Sync),
+    request: &openai_api::model::create_chat::Request,
+    stream: bool,
+    markdown: bool,
+    styles: Option<&Styles>,
+) -> Result<Reply, Error> {
+    if stream {
+        let mut chunks = datasource.create_chat_stream(request).await?;
+        let mut message = openai_api::model::create_chat::MessageAccumulator::default();
+        let mut finish_reason = None;
+        let mut rendered = markdown::Stream::default();
+
+        if let Some(styles) = styles {
+            print!(
+                "{:#?}:{}",
+                styles
+                    .assistant
+                    .apply_to(openai_api::model::create_chat::Role::Assistant),
+                match markdown {
+                    true => "\n",
+                    false => " ",
+                }
+            );
+        }
+
+        while let Some(chunk) = chunks.next().await {
+            for choice in chunk?.choices.iter().filter(|choice| choice.index == 0) {
+                if let Some(content) = &choice.delta.content {
+                    match (markdown, styles) {
+                        (true, _) => print!("{}", rendered.push(content)),
+                        (false, Some(styles)) => {
+                            print!("{}", styles.assistant_response.apply_to(content))
+                        }
+                        (false, None) => print!("{}", content),
+                    }
+
+                    io::stdout().flush()?;
+                }
+
+                message.push(&choice.delta);
+
+                if choice.finish_reason.is_some() {
+                    finish_reason = choice.finish_reason.clone();
+                }
+            }
+        }
+
+        let message = message.finish()?;
+
+        if markdown {
+            print!("{}", rendered.finish());
+        }
+
+        // A function_call finish without a function name carries no call to show.
+        match (&message.function_call, styles) {
+            (Some(function_call), Some(styles)) => {
+                println!("{:#?}", styles.assistant_response.apply_to(function_call));
+            }
+            (Some(function_call), None) => eprintln!("{:?}", function_call),
+            (None, _) => println!(),
+        }
+
+        return Ok(Reply {
+            usage: Some(estimate_usage(request, &message)),
+            message,
+            finish_reason,
+            estimated: true,
+        });
+    }
+
+    let mut usage = None;
+
+    // Several candidates are listed for the user to keep one or ask again. One-shot mode
+    // prints them all and keeps the first.
+    loop {
+        let response = datasource.create_chat(request).await?;
+        usage = Some(add_usage(usage, &response.usage));
+
+        let index = match (response.choices.len(), styles) {
+            (0, _) => return Err(anyhow::anyhow!("the response has no choices")),
+            (1, _) => {
+                show(&response.choices[0], markdown, styles);
+                0
+            }
+            (count, Some(styles)) => {
+                for (index, choice) in response.choices.iter().enumerate() {
+                    print!("{} ", styles.assistant.apply_to(format!("[{}]", index + 1)));
+                    show(choice, markdown, Some(styles));
+                }
+
+                match choose(count, styles)? {
+                    Some(index) => index,
+                    None => continue,
+                }
+            }
+            (_, None) => {
+                for (index, choice) in response.choices.iter().enumerate() {
+                    if index > 0 {
+                        println!();
+                    }
+
+                    show(choice, markdown, None);
+                }
+
+                0
             }
         };
 
-        let assistant_response = console::Style::new().blue();
-        let assistant = console::Style::new().dim();
+        let choice = response.choices.into_iter().nth(index).unwrap();
+
+        return Ok(Reply {
+            message: choice.message,
+            finish_reason: choice.finish_reason,
+            usage,
+            estimated: false,
+        });
+    }
+}
+
+fn show(choice: &openai_api::model::create_chat::Choice, markdown: bool, styles: Option<&Styles>) {
+    let content = choice.message.content.as_deref().unwrap_or_default();
 
-        println!(
-            "{}",
-            assistant_response.apply_to("What can I assist you with?")
+    match (&choice.message.function_call, styles) {
+        (Some(function_call), Some(styles)) => {
+            println!(
+                "{:#?}: {:#?}",
+                styles.assistant.apply_to(&choice.message.role),
+                styles.assistant_response.apply_to(function_call)
+            );
+        }
+        (Some(function_call), None) => eprintln!("{:?}", function_call),
+        (_, Some(styles)) if markdown => println!(
+            "{:#?}:\n{}",
+            styles.assistant.apply_to(&choice.message.role),
+            markdown::render(content)
+        ),
+        (_, Some(styles)) => {
+            println!(
+                "{:#?}: {}",
+                styles.assistant.apply_to(&choice.message.role),
+                styles.assistant_response.apply_to(content)
+            );
+        }
+        (_, None) if markdown => println!("{}", markdown::render(content)),
+        (_, None) => println!("{}", content),
+    }
+}
+
+// Reads which candidate to keep: its number, `r` to regenerate, or nothing for the first.
+fn choose(count: usize, styles: &Styles) -> Result<Option<usize>, Error> {
+    loop {
+        print!(
+            "{} ",
+            styles
+                .assistant
+                .apply_to(format!("Keep which reply? [1-{}, r to regenerate]", count))
         );
+        io::stdout().flush()?;
+
+        let mut answer = String::new();
+
+        if io::stdin().read_line(&mut answer)? == 0 {
+            return Ok(Some(0));
+        }
+
+        match answer.trim() {
+            "" => return Ok(Some(0)),
+            "r" | "R" => return Ok(None),
+            answer => match answer.parse::<usize>() {
+                Ok(number) if (1..=count).contains(&number) => return Ok(Some(number - 1)),
+                _ => continue,
+            },
+        }
+    }
+}
+
+// Regenerated candidates are paid for too, so their usage is summed into the turn's.
+fn add_usage(
+    total: Option<openai_api::model::create_chat::Usage>,
+    usage: &openai_api::model::create_chat::Usage,
+) -> openai_api::model::create_chat::Usage {
+    match total {
+        Some(total) => openai_api::model::create_chat::Usage {
+            prompt_tokens: total.prompt_tokens + usage.prompt_tokens,
+            completion_tokens: total.completion_tokens + usage.completion_tokens,
+            total_tokens: total.total_tokens + usage.total_tokens,
+        },
+        None => usage.clone(),
+    }
+}
+
+// Streamed replies carry no usage, so it is counted locally as the ledger does.
+fn estimate_usage(
+    request: &openai_api::model::create_chat::Request,
+    message: &openai_api::model::create_chat::Message,
+) -> openai_api::model::create_chat::Usage {
+    let encoding = Encoding::from(&request.model);
+    let prompt_tokens = encoding.count_messages(&request.messages);
+    let (name, arguments) = match &message.function_call {
+        Some(function_call) => (
+            function_call.name(),
+            serde_json::to_string(&function_call.arguments().cloned().unwrap_or_default())
+                .unwrap_or_default(),
+        ),
+        None => ("", String::new()),
+    };
+    let completion_tokens = ledger::completion_tokens(
+        &encoding,
+        message.content.as_deref().unwrap_or_default(),
+        name,
+        &arguments,
+    );
+
+    openai_api::model::create_chat::Usage {
+        prompt_tokens,
+        completion_tokens,
+        total_tokens: prompt_tokens + completion_tokens,
+    }
+}
+
+struct Conversation<'a> {
+    datasource: &'a (dyn Datasource + Send + Sync),
+    opt: &'a Create,
+    store: &'a session::Store,
+    session: &'a mut session::Session,
+    tools: Option<&'a tools::Tools>,
+    window: context::Window,
+    stream: bool,
+    markdown: bool,
+    attachments: attach::Attachments,
+}
+
+impl Conversation<'_> {
+    // Answers function calls with the mapped commands and re-queries until the model
+    // replies normally, saving the session after every request.
+    async fn turn(
+        &mut self,
+        request: &mut openai_api::model::create_chat::Request,
+        styles: Option<&Styles>,
+    ) -> Result<Option<openai_api::model::create_chat::FinishReason>, Error> {
+        for _ in 0..=MAX_FUNCTION_CALLS {
+            // The session keeps the whole transcript; only what is sent is trimmed.
+            let mut outgoing = request.clone();
+            self.window.fit(self.datasource, &mut outgoing).await?;
+
+            let reply = reply(
+                self.datasource,
+                &outgoing,
+                self.stream,
+                self.markdown,
+                styles,
+            )
+            .await?;
+            let finish_reason = reply.finish_reason.clone();
+            let function_call = match (&finish_reason, self.tools) {
+                (Some(openai_api::model::create_chat::FinishReason::FunctionCall), Some(_)) => {
+                    reply.message.function_call.clone()
+                }
+                _ => None,
+            };
 
-        loop {
-            let mut content = String::new();
+            let (usage, estimated) = self.record(request, reply);
+            self.save(request, usage, estimated)?;
 
-            io::stdin()
-                .read_line(&mut content)
-                .expect("Failed to read input");
+            let (Some(function_call), Some(tools)) = (function_call, self.tools) else {
+                return Ok(finish_reason);
+            };
+
+            let output = tools.call(&function_call, self.opt.auto_approve).await?;
 
             request
                 .messages
                 .push(openai_api::model::create_chat::Message {
-                    role: openai_api::model::create_chat::Role::User,
-                    content: Some(content),
-                    name: None,
+                    role: openai_api::model::create_chat::Role::Function,
+                    content: Some(output),
+                    name: Some(function_call.name().to_string()),
                     function_call: None,
                 });
+        }
 
-            let response = datasource.create_chat(&request).await?;
+        Err(anyhow::anyhow!(
+            "gave up after {} consecutive function calls",
+            MAX_FUNCTION_CALLS
+        ))
+    }
 
-            match response.choices[0].finish_reason {
-                openai_api::model::create_chat::FinishReason::FunctionCall => {
-                    println!(
-                        "{:#?}: {:#?}",
-                        assistant.apply_to(&response.choices[0].message.role),
-                        assistant_response
-                            .apply_to(response.choices[0].message.function_call.as_ref().unwrap())
-                    );
-                }
-                _ => {
-                    println!(
-                        "{:#?}: {:#?}",
-                        assistant.apply_to(&response.choices[0].message.role),
-                        assistant_response
-                            .apply_to(&response.choices[0].message.content.as_ref().unwrap())
-                    );
-
-                    request.messages.push(response.choices[0].message.clone());
-                }
+    // Without functions to answer them, calls are shown but kept out of the history.
+    fn record(
+        &self,
+        request: &mut openai_api::model::create_chat::Request,
+        reply: Reply,
+    ) -> (Option<openai_api::model::create_chat::Usage>, bool) {
+        let unanswered = self.tools.is_none()
+            && matches!(
+                reply.finish_reason,
+                Some(openai_api::model::create_chat::FinishReason::FunctionCall)
+            );
+
+        if !unanswered {
+            request.messages.push(reply.message);
+        }
+
+        (reply.usage, reply.estimated)
+    }
+
+    fn save(
+        &mut self,
+        request: &openai_api::model::create_chat::Request,
+        usage: Option<openai_api::model::create_chat::Usage>,
+        estimated: bool,
+    ) -> Result<(), Error> {
+        self.session.turns.push(session::Turn {
+            messages: request.messages.len(),
+            usage,
+            estimated,
+        });
+
+        self.persist(request)
+    }
+
+    // Writes the transcript as it stands, without counting a turn.
+    fn persist(&mut self, request: &openai_api::model::create_chat::Request) -> Result<(), Error> {
+        self.session.messages = request.messages.clone();
+        self.session.updated = chrono::Utc::now();
+
+        if let Some(name) = &self.opt.session {
+            self.store.save(name, self.session)?;
+        }
+
+        Ok(())
+    }
+}
+
+// Totals that include locally counted turns are marked as approximate.
+fn tokens(session: &session::Session) -> String {
+    match session.estimated() {
+        true => format!("~{}", session.total_tokens()),
+        false => session.total_tokens().to_string(),
+    }
+}
+
+fn sessions(opt: &Sessions) -> Result<(), Error> {
+    let store = session::Store::new()?;
+    let dim = console::Style::new().dim();
+
+    match opt {
+        Sessions::List => {
+            for (name, session) in store.list()? {
+                println!(
+                    "{}\t{}\t{} turns\t{} tokens\t{}",
+                    name,
+                    model_name(&session.model),
+                    session.turns.len(),
+                    tokens(&session),
+                    dim.apply_to(session.updated.format("%Y-%m-%d %H:%M"))
+                );
             }
+        }
+        Sessions::Show { name } => {
+            let session = store
+                .load(name)?
+                .ok_or_else(|| anyhow::anyhow!("no session named {}", name))?;
+
+            println!(
+                "{}",
+                dim.apply_to(format!(
+                    "model: {} | max tokens: {} | temperature: {} | tokens: {}",
+                    model_name(&session.model),
+                    session
+                        .max_tokens
+                        .map_or(String::from("default"), |max_tokens| max_tokens.to_string()),
+                    session
+                        .temperature
+                        .map_or(String::from("default"), |temperature| temperature
+                            .to_string()),
+                    tokens(&session)
+                ))
+            );
 
-            println!()
+            for message in &session.messages {
+                let content = match (&message.content, &message.function_call) {
+                    (Some(content), _) => content.trim_end().to_string(),
+                    (None, Some(function_call)) => format!("{:?}", function_call),
+                    (None, None) => String::new(),
+                };
+
+                println!("{:#?}: {}", dim.apply_to(&message.role), content);
+            }
         }
+        Sessions::Delete { name } => store.delete(name)?,
+        Sessions::Rename { from, to } => store.rename(from, to)?,
+    }
+
+    Ok(())
+}
+
+fn attached(files: &[attach::File]) -> String {
+    if files.is_empty() {
+        return String::from("Those files are already attached");
+    }
+
+    files
+        .iter()
+        .map(|file| format!("Attached {} ({} tokens)", file.path, file.tokens))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+fn user_message(content: String) -> openai_api::model::create_chat::Message {
+    openai_api::model::create_chat::Message {
+        role: openai_api::model::create_chat::Role::User,
+        content: Some(content),
+        name: None,
+        function_call: None,
+    }
+}
+
+fn system_message(content: String) -> openai_api::model::create_chat::Message {
+    openai_api::model::create_chat::Message {
+        role: openai_api::model::create_chat::Role::System,
+        content: Some(content),
+        name: None,
+        function_call: None,
     }
 }
+
+fn model_name(model: &openai_api::model::create_chat::Model) -> String {
+    serde_json::to_value(model)
+        .ok()
+        .and_then(|value| value.as_str().map(String::from))
+        .unwrap_or_default()
+}
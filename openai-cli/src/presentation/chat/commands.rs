@@ -0,0 +1,201 @@
+use anyhow::{anyhow, Error};
+use openai_api::{model::create_chat, tokenizer::Encoding};
+use std::{fs, str::FromStr};
+
+use super::{attached, model_name, streams, system_message, Conversation, Styles};
+use crate::session;
+
+pub(super) const HELP: &[(&str, &str)] = &[
+    ("/reset", "start over, keeping the system prompt"),
+    ("/model [id]", "show or switch the model"),
+    ("/temperature [f]", "show or set the sampling temperature"),
+    ("/system [text]", "show or replace the system prompt"),
+    ("/undo", "drop the last exchange"),
+    ("/retry", "regenerate the last answer"),
+    ("/history", "print the conversation so far"),
+    ("/save <file>", "write the conversation to a file"),
+    ("/load <file>", "continue a conversation written by /save"),
+    ("/tokens", "count the prompt against the context window"),
+    (
+        "/attach <path>",
+        "add a file, directory or glob to the next message",
+    ),
+    ("/help", "list these commands"),
+];
+
+impl Conversation<'_> {
+    // Runs a REPL command against the in-flight request. Changes are saved to the session
+    // straight away, so they survive the process as a turn would.
+    pub(super) async fn command(
+        &mut self,
+        request: &mut create_chat::Request,
+        line: &str,
+        styles: &Styles,
+    ) -> Result<(), Error> {
+        let (name, argument) = match line.trim().split_once(char::is_whitespace) {
+            Some((name, argument)) => (name, Some(argument.trim())),
+            None => (line.trim(), None),
+        };
+
+        match (name, argument) {
+            ("reset", None) => {
+                request
+                    .messages
+                    .retain(|message| matches!(message.role, create_chat::Role::System));
+                notice(styles, "Conversation reset");
+            }
+            ("model", None) => notice(styles, &model_name(&request.model)),
+            ("model", Some(argument)) => {
+                let model = create_chat::Model::from_str(argument)?;
+
+                request.model = model.clone();
+                self.session.model = model;
+                notice(styles, &format!("Switched to {}", argument));
+            }
+            ("temperature", None) => notice(
+                styles,
+                &request
+                    .temperature
+                    .map_or(String::from("default"), |temperature| {
+                        temperature.to_string()
+                    }),
+            ),
+            ("temperature", Some(argument)) => {
+                let temperature = argument
+                    .parse::<f32>()
+                    .ok()
+                    .filter(|temperature| (0.0..=2.0).contains(temperature))
+                    .ok_or_else(|| {
+                        anyhow!("expected a temperature from 0 to 2, got {}", argument)
+                    })?;
+
+                request.temperature = Some(temperature);
+                self.session.temperature = Some(temperature);
+                notice(styles, &format!("Temperature set to {}", temperature));
+            }
+            ("system", None) => notice(
+                styles,
+                request
+                    .messages
+                    .iter()
+                    .find(|message| matches!(message.role, create_chat::Role::System))
+                    .and_then(|message| message.content.as_deref())
+                    .unwrap_or("no system prompt"),
+            ),
+            ("system", Some(argument)) => {
+                let message = system_message(argument.to_string());
+
+                match request.messages.first() {
+                    Some(first) if matches!(first.role, create_chat::Role::System) => {
+                        request.messages[0] = message
+                    }
+                    _ => request.messages.insert(0, message),
+                }
+
+                notice(styles, "System prompt replaced");
+            }
+            ("undo", None) => {
+                let start =
+                    last_exchange(&request.messages).ok_or_else(|| anyhow!("nothing to undo"))?;
+
+                request.messages.truncate(start);
+                notice(styles, "Dropped the last exchange");
+            }
+            ("retry", None) => {
+                let start =
+                    last_exchange(&request.messages).ok_or_else(|| anyhow!("nothing to retry"))?;
+
+                request.messages.truncate(start + 1);
+                self.turn(request, Some(styles)).await?;
+
+                return Ok(());
+            }
+            ("history", None) => {
+                for message in &request.messages {
+                    let content = match (&message.content, &message.function_call) {
+                        (Some(content), _) => content.trim_end().to_string(),
+                        (None, Some(function_call)) => format!("{:?}", function_call),
+                        (None, None) => String::new(),
+                    };
+
+                    println!(
+                        "{:#?}: {}",
+                        styles.assistant.apply_to(&message.role),
+                        content
+                    );
+                }
+            }
+            ("save", Some(path)) => {
+                self.session.messages = request.messages.clone();
+
+                fs::write(path, serde_json::to_string_pretty(self.session)?)
+                    .map_err(|error| anyhow!("{}: {}", path, error))?;
+                notice(styles, &format!("Saved to {}", path));
+            }
+            ("load", Some(path)) => {
+                let content =
+                    fs::read_to_string(path).map_err(|error| anyhow!("{}: {}", path, error))?;
+                let loaded: session::Session = serde_json::from_str(&content)
+                    .map_err(|error| anyhow!("{}: {}", path, error))?;
+
+                loaded.sampling.apply(request)?;
+                request.model = loaded.model.clone();
+                request.max_tokens = loaded.max_tokens;
+                request.temperature = loaded.temperature;
+                request.messages = loaded.messages.clone();
+
+                self.session.model = loaded.model;
+                self.session.max_tokens = loaded.max_tokens;
+                self.session.temperature = loaded.temperature;
+                self.session.sampling = loaded.sampling;
+                self.stream = streams(self.opt, request);
+                notice(
+                    styles,
+                    &format!("Loaded {} messages from {}", loaded.messages.len(), path),
+                );
+            }
+            ("tokens", None) => {
+                let encoding = Encoding::from(&request.model);
+
+                notice(
+                    styles,
+                    &format!(
+                        "{} prompt tokens of {} in the context window, {} used this session",
+                        encoding.count_messages(&request.messages),
+                        request.model.context_window(),
+                        self.session.total_tokens()
+                    ),
+                );
+            }
+            ("attach", Some(pattern)) => {
+                let files = self.attachments.add(pattern, &request.model)?;
+
+                notice(styles, &attached(files));
+            }
+            ("help", None) => {
+                for (command, description) in HELP {
+                    println!("{:<18} {}", command, styles.assistant.apply_to(description));
+                }
+            }
+            _ => match HELP.iter().find(|(command, _)| {
+                command.trim_start_matches('/').split(' ').next() == Some(name)
+            }) {
+                Some((usage, _)) => return Err(anyhow!("usage: {}", usage)),
+                None => return Err(anyhow!("unknown command /{}, /help lists them", name)),
+            },
+        }
+
+        self.persist(request)
+    }
+}
+
+// The index of the last user message, which starts the exchange /undo and /retry act on.
+fn last_exchange(messages: &[create_chat::Message]) -> Option<usize> {
+    messages
+        .iter()
+        .rposition(|message| matches!(message.role, create_chat::Role::User))
+}
+
+fn notice(styles: &Styles, text: &str) {
+    println!("{}", styles.assistant.apply_to(text));
+}
@@ -0,0 +1,143 @@
+use anyhow::{anyhow, Error};
+use rustyline::{
+    completion::{Completer, FilenameCompleter, Pair},
+    error::ReadlineError,
+    highlight::Highlighter,
+    hint::Hinter,
+    history::DefaultHistory,
+    validate::{ValidationContext, ValidationResult, Validator},
+    Cmd, EventHandler, KeyCode, KeyEvent, Modifiers,
+};
+use std::{fs, path};
+
+use super::commands::HELP;
+
+const PROMPT: &str = "> ";
+
+// Reads chat input with line editing and a history shared by every chat. Alt-Enter starts a
+// new line, as does an unclosed ``` fence, so pasted code arrives as one message.
+pub struct Editor {
+    editor: rustyline::Editor<Helper, DefaultHistory>,
+    history: Option<path::PathBuf>,
+}
+
+impl Editor {
+    pub fn new() -> Result<Self, Error> {
+        let mut editor = rustyline::Editor::new()?;
+
+        editor.set_helper(Some(Helper {
+            filenames: FilenameCompleter::new(),
+        }));
+        editor.bind_sequence(
+            KeyEvent(KeyCode::Enter, Modifiers::ALT),
+            EventHandler::Simple(Cmd::Newline),
+        );
+
+        let history =
+            dirs::data_dir().map(|directory| directory.join("openai-cli").join("history"));
+
+        if let Some(history) = &history {
+            // A first run has no history yet.
+            match editor.load_history(history) {
+                Ok(()) => {}
+                Err(ReadlineError::Io(error)) if error.kind() == std::io::ErrorKind::NotFound => {}
+                Err(error) => return Err(anyhow!("history {}: {}", history.display(), error)),
+            }
+        }
+
+        Ok(Self { editor, history })
+    }
+
+    // Ctrl-C discards what was typed and asks again; Ctrl-D or the end of piped input ends
+    // the conversation with `None`.
+    pub fn read(&mut self) -> Result<Option<String>, Error> {
+        loop {
+            let line = match self.editor.readline(PROMPT) {
+                Ok(line) => line,
+                Err(ReadlineError::Interrupted) => continue,
+                Err(ReadlineError::Eof) => return Ok(None),
+                Err(error) => return Err(error.into()),
+            };
+
+            if line.trim().is_empty() {
+                continue;
+            }
+
+            self.remember(&line)?;
+
+            return Ok(Some(line));
+        }
+    }
+
+    // Appends rather than rewrites, so concurrent chats do not drop each other's entries.
+    fn remember(&mut self, line: &str) -> Result<(), Error> {
+        self.editor.add_history_entry(line)?;
+
+        if let Some(history) = &self.history {
+            if let Some(directory) = history.parent() {
+                fs::create_dir_all(directory)?;
+            }
+
+            self.editor.append_history(history)?;
+        }
+
+        Ok(())
+    }
+}
+
+struct Helper {
+    filenames: FilenameCompleter,
+}
+
+impl rustyline::Helper for Helper {}
+
+impl Highlighter for Helper {}
+
+impl Hinter for Helper {
+    type Hint = String;
+}
+
+impl Validator for Helper {
+    fn validate(&self, ctx: &mut ValidationContext) -> rustyline::Result<ValidationResult> {
+        let fences = ctx
+            .input()
+            .lines()
+            .filter(|line| line.trim_start().starts_with("```"))
+            .count();
+
+        match fences % 2 {
+            0 => Ok(ValidationResult::Valid(None)),
+            _ => Ok(ValidationResult::Incomplete),
+        }
+    }
+}
+
+// Completes slash commands at the start of the line and file paths everywhere else.
+impl Completer for Helper {
+    type Candidate = Pair;
+
+    fn complete(
+        &self,
+        line: &str,
+        pos: usize,
+        ctx: &rustyline::Context<'_>,
+    ) -> rustyline::Result<(usize, Vec<Pair>)> {
+        let typed = &line[..pos];
+
+        if typed.starts_with('/') && !typed.contains(char::is_whitespace) {
+            let commands = HELP
+                .iter()
+                .filter_map(|(usage, _)| usage.split(' ').next())
+                .filter(|command| command.starts_with(typed))
+                .map(|command| Pair {
+                    display: command.to_string(),
+                    replacement: format!("{} ", command),
+                })
+                .collect();
+
+            return Ok((0, commands));
+        }
+
+        self.filenames.complete(line, pos, ctx)
+    }
+}
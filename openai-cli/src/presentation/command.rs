@@ -6,7 +6,6 @@ use std::sync;
 pub trait Command {
     async fn run(
         &self,
-        http_client: sync::Arc<reqwest::Client>,
-        api_key: String,
+        datasource: sync::Arc<dyn openai_api::Datasource + Send + Sync>,
     ) -> Result<(), Error>;
 }
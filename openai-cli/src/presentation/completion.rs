@@ -1,10 +1,15 @@
 use anyhow::Error;
 use async_trait::async_trait;
+use futures::StreamExt;
 use openai_api::Datasource;
-use std::sync;
+use std::{
+    collections::BTreeMap,
+    io::{self, Write},
+    sync,
+};
 use structopt::StructOpt;
 
-use super::command::Command;
+use super::{command::Command, sampling};
 
 #[derive(StructOpt)]
 pub struct Opt {
@@ -32,28 +37,93 @@ pub struct Create {
 
     #[structopt(long, short, default_value = "0.0")]
     pub temperature: f32,
+
+    #[structopt(short, long = "number")]
+    pub n: Option<usize>,
+
+    #[structopt(flatten)]
+    pub sampling: sampling::Opt,
+
+    #[structopt(long)]
+    pub stream: bool,
 }
 
 #[async_trait]
 impl Command for Opt {
-    async fn run(
-        &self,
-        http_client: sync::Arc<reqwest::Client>,
-        api_key: String,
-    ) -> Result<(), Error> {
-        let datasource = openai_api::OpenAIApi::new(http_client, api_key);
-        let request = match &self.subcommand {
-            Subcommand::Create(opt) => openai_api::model::create_completion::Request::new(
-                opt.model.clone(),
-                opt.prompt.clone(),
-            )
-            .max_tokens(opt.max_tokens)
-            .temperature(opt.temperature),
+    async fn run(&self, datasource: sync::Arc<dyn Datasource + Send + Sync>) -> Result<(), Error> {
+        let (request, stream) = match &self.subcommand {
+            Subcommand::Create(opt) => {
+                let request = openai_api::model::create_completion::Request::new(
+                    opt.model.clone(),
+                    opt.prompt.clone(),
+                )
+                .max_tokens(opt.max_tokens)
+                .temperature(opt.temperature)
+                .top_p(opt.sampling.top_p)?
+                .stop(opt.sampling.stop.clone())?
+                .presence_penalty(opt.sampling.presence_penalty)?
+                .frequency_penalty(opt.sampling.frequency_penalty)?
+                .logit_bias(opt.sampling.logit_bias())?
+                .n(opt.n);
+
+                match &opt.suffix {
+                    Some(suffix) => (request.suffix(suffix.clone()), opt.stream),
+                    None => (request, opt.stream),
+                }
+            }
         };
 
-        let response = datasource.create_completion(&request).await?;
+        if !stream {
+            let response = datasource.create_completion(&request).await?;
+
+            // Every choice is paid for, so all of them are shown, labelled as when streaming.
+            match response.choices.as_slice() {
+                [choice] => println!("{}", choice.text),
+                choices => {
+                    for choice in choices {
+                        println!("[{}] {}", choice.index, choice.text);
+                    }
+                }
+            }
+
+            return Ok(());
+        }
+
+        let mut choices = datasource.create_completion_stream(&request).await?;
+        let mut stdout = io::stdout();
+
+        // A single choice is written as it arrives. Several arrive interleaved, so each is
+        // held back until it finishes and then written whole with its label.
+        if request.n.unwrap_or(1) <= 1 {
+            while let Some(choice) = choices.next().await {
+                write!(stdout, "{}", choice?.text)?;
+                stdout.flush()?;
+            }
+
+            writeln!(stdout)?;
+
+            return Ok(());
+        }
+
+        let mut pending: BTreeMap<usize, String> = BTreeMap::new();
+
+        while let Some(choice) = choices.next().await {
+            let choice = choice?;
+            pending
+                .entry(choice.index)
+                .or_default()
+                .push_str(&choice.text);
+
+            if choice.finish_reason.is_some() {
+                if let Some(text) = pending.remove(&choice.index) {
+                    writeln!(stdout, "[{}] {}", choice.index, text)?;
+                }
+            }
+        }
 
-        println!("{}", response.choices[0].text);
+        for (index, text) in pending {
+            writeln!(stdout, "[{}] {}", index, text)?;
+        }
 
         Ok(())
     }
@@ -4,7 +4,7 @@ use openai_api::Datasource;
 use std::sync;
 use structopt::StructOpt;
 
-use super::command::Command;
+use super::{command::Command, sampling};
 
 #[derive(StructOpt)]
 pub struct Opt {
@@ -27,22 +27,23 @@ pub struct Create {
 
     #[structopt(long, short, default_value = "0.0")]
     pub temperature: f32,
+
+    // The edits endpoint takes no penalties, stop sequences or logit bias.
+    #[structopt(long, parse(try_from_str = sampling::parse_top_p))]
+    pub top_p: Option<f32>,
 }
 
 #[async_trait]
 impl Command for Opt {
-    async fn run(
-        &self,
-        http_client: sync::Arc<reqwest::Client>,
-        api_key: String,
-    ) -> Result<(), Error> {
-        let datasource = openai_api::OpenAIApi::new(http_client, api_key);
+    async fn run(&self, datasource: sync::Arc<dyn Datasource + Send + Sync>) -> Result<(), Error> {
         let request = match &self.subcommand {
             Subcommand::Create(opt) => openai_api::model::create_edit::Request::new(
                 opt.model.clone(),
                 opt.input.clone(),
                 opt.instruction.clone(),
-            ),
+            )
+            .temperature(opt.temperature)
+            .top_p(opt.top_p),
         };
 
         let response = datasource.create_edit(&request).await?;
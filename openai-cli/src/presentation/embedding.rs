@@ -30,13 +30,7 @@ pub struct Create {
 
 #[async_trait]
 impl Command for Opt {
-    async fn run(
-        &self,
-        http_client: sync::Arc<reqwest::Client>,
-        api_key: String,
-    ) -> Result<(), Error> {
-        let datasource = openai_api::OpenAIApi::new(http_client, api_key);
-
+    async fn run(&self, datasource: sync::Arc<dyn Datasource + Send + Sync>) -> Result<(), Error> {
         let request = match &self.subcommand {
             Subcommand::Create(opt) => openai_api::model::create_embedding::Request::new(
                 opt.model.clone(),
@@ -19,15 +19,14 @@ pub enum Subcommand {
 
 #[async_trait]
 impl Command for Opt {
-    async fn run(
-        &self,
-        http_client: sync::Arc<reqwest::Client>,
-        api_key: String,
-    ) -> Result<(), Error> {
-        let datasource = openai_api::OpenAIApi::new(http_client, api_key);
-        let files = datasource.list_files().await?;
+    async fn run(&self, datasource: sync::Arc<dyn Datasource + Send + Sync>) -> Result<(), Error> {
+        match &self.subcommand {
+            Subcommand::List => {
+                let files = datasource.list_files().await?;
 
-        println!("{:#?}", files);
+                println!("{:#?}", files);
+            }
+        }
 
         Ok(())
     }
@@ -38,15 +38,12 @@ pub struct Create {
 
 #[async_trait]
 impl Command for Opt {
-    async fn run(
-        &self,
-        http_client: sync::Arc<reqwest::Client>,
-        api_key: String,
-    ) -> Result<(), Error> {
-        let datasource = openai_api::OpenAIApi::new(http_client, api_key);
+    async fn run(&self, datasource: sync::Arc<dyn Datasource + Send + Sync>) -> Result<(), Error> {
         let request = match &self.subcommand {
             Subcommand::Create(opt) => {
                 { openai_api::model::create_image::Request::new(opt.prompt.clone()) }
+                    .n(opt.n)
+                    .size(opt.size.clone())
                     .response_format(opt.response_format.as_ref().unwrap().clone())
             }
         };
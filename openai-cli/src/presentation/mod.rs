@@ -6,3 +6,6 @@ pub mod embedding;
 pub mod file;
 pub mod image;
 pub mod model;
+pub mod sampling;
+pub mod tokens;
+pub mod usage;
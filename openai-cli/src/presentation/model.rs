@@ -19,18 +19,17 @@ pub enum Subcommand {
 
 #[async_trait]
 impl Command for Opt {
-    async fn run(
-        &self,
-        http_client: sync::Arc<reqwest::Client>,
-        api_key: String,
-    ) -> Result<(), Error> {
-        let datasource = openai_api::OpenAIApi::new(http_client, api_key);
-        let models = datasource.list_models().await?;
+    async fn run(&self, datasource: sync::Arc<dyn Datasource + Send + Sync>) -> Result<(), Error> {
+        match &self.subcommand {
+            Subcommand::List => {
+                let models = datasource.list_models().await?;
 
-        models
-            .data
-            .iter()
-            .for_each(|model| println!("{}", model.id));
+                models
+                    .data
+                    .iter()
+                    .for_each(|model| println!("{}", model.id));
+            }
+        }
 
         Ok(())
     }
@@ -0,0 +1,69 @@
+use anyhow::{anyhow, Error};
+use openai_api::model::sampling;
+use std::collections::HashMap;
+use structopt::StructOpt;
+
+// Sampling flags shared by `chat create` and `completion create`. Values outside the
+// ranges the API accepts are rejected here, before any request is made.
+#[derive(StructOpt)]
+pub struct Opt {
+    #[structopt(long, parse(try_from_str = parse_top_p))]
+    pub top_p: Option<f32>,
+
+    #[structopt(long, allow_hyphen_values = true, parse(try_from_str = parse_penalty))]
+    pub presence_penalty: Option<f32>,
+
+    #[structopt(long, allow_hyphen_values = true, parse(try_from_str = parse_penalty))]
+    pub frequency_penalty: Option<f32>,
+
+    // Ends the reply where this text would appear. Can be given up to four times.
+    #[structopt(long, number_of_values = 1)]
+    pub stop: Vec<String>,
+
+    // Biases a token id, as in `--logit-bias 50256=-100`. Can be repeated.
+    #[structopt(long, number_of_values = 1, parse(try_from_str = parse_logit_bias))]
+    pub logit_bias: Vec<(usize, f32)>,
+}
+
+impl Opt {
+    pub fn stop(&self) -> Result<Vec<String>, Error> {
+        sampling::stop(self.stop.clone())?;
+        Ok(self.stop.clone())
+    }
+
+    pub fn logit_bias(&self) -> HashMap<usize, f32> {
+        self.logit_bias.iter().copied().collect()
+    }
+}
+
+pub fn parse_top_p(s: &str) -> Result<f32, Error> {
+    s.parse::<f32>()
+        .ok()
+        .filter(|top_p| (0.0..=1.0).contains(top_p))
+        .ok_or_else(|| anyhow!("expected a top_p from 0 to 1, got {}", s))
+}
+
+fn parse_penalty(s: &str) -> Result<f32, Error> {
+    s.parse::<f32>()
+        .ok()
+        .filter(|penalty| (-2.0..=2.0).contains(penalty))
+        .ok_or_else(|| anyhow!("expected a penalty from -2 to 2, got {}", s))
+}
+
+fn parse_logit_bias(s: &str) -> Result<(usize, f32), Error> {
+    let (token, bias) = s
+        .split_once('=')
+        .ok_or_else(|| anyhow!("expected <token id>=<bias>, got {}", s))?;
+    let token = token
+        .trim()
+        .parse::<usize>()
+        .map_err(|_| anyhow!("expected a token id, got {}", token))?;
+    let bias = bias
+        .trim()
+        .parse::<f32>()
+        .ok()
+        .filter(|bias| (-100.0..=100.0).contains(bias))
+        .ok_or_else(|| anyhow!("expected a bias from -100 to 100, got {}", bias))?;
+
+    Ok((token, bias))
+}
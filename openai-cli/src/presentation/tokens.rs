@@ -0,0 +1,88 @@
+use anyhow::{anyhow, Error};
+use openai_api::{model, tokenizer::Encoding};
+use std::{fs, io, io::Read, path, str::FromStr};
+use structopt::StructOpt;
+
+#[derive(StructOpt)]
+pub struct Opt {
+    #[structopt(subcommand)]
+    pub subcommand: Subcommand,
+}
+
+#[derive(StructOpt)]
+pub enum Subcommand {
+    Count(Count),
+}
+
+#[derive(StructOpt)]
+pub struct Count {
+    // A file to count, or `-` for stdin.
+    #[structopt(default_value = "-")]
+    pub input: path::PathBuf,
+
+    // Any chat, completion, embedding or edit model; picks the encoding it uses.
+    #[structopt(long, short, conflicts_with = "encoding")]
+    pub model: Option<String>,
+
+    #[structopt(long, short, default_value = "cl100k_base")]
+    pub encoding: Encoding,
+
+    // Reads a JSON array of chat messages and counts them as the API's `prompt_tokens`.
+    #[structopt(long)]
+    pub messages: bool,
+}
+
+// Counting is offline, so unlike the other commands this one needs no datasource.
+impl Opt {
+    pub fn run(&self) -> Result<(), Error> {
+        match &self.subcommand {
+            Subcommand::Count(opt) => count(opt),
+        }
+    }
+}
+
+fn count(opt: &Count) -> Result<(), Error> {
+    let encoding = match &opt.model {
+        Some(name) => encoding(name)?,
+        None => opt.encoding,
+    };
+
+    let content = match opt.input.to_str() {
+        Some("-") => {
+            let mut content = String::new();
+            io::stdin().read_to_string(&mut content)?;
+            content
+        }
+        _ => fs::read_to_string(&opt.input)
+            .map_err(|error| anyhow!("{}: {}", opt.input.display(), error))?,
+    };
+
+    let tokens = match opt.messages {
+        true => {
+            let messages: Vec<model::create_chat::Message> = serde_json::from_str(&content)
+                .map_err(|error| anyhow!("{}: {}", opt.input.display(), error))?;
+            encoding.count_messages(&messages)
+        }
+        false => encoding.count(&content),
+    };
+
+    println!("{}", tokens);
+
+    Ok(())
+}
+
+fn encoding(name: &str) -> Result<Encoding, Error> {
+    if let Ok(model) = model::create_chat::Model::from_str(name) {
+        return Ok(Encoding::from(&model));
+    }
+
+    if let Ok(model) = model::create_completion::Model::from_str(name) {
+        return Ok(Encoding::from(&model));
+    }
+
+    if let Ok(model) = model::create_embedding::Model::from_str(name) {
+        return Ok(Encoding::from(&model));
+    }
+
+    Ok(Encoding::from(&model::create_edit::Model::from_str(name)?))
+}
@@ -0,0 +1,233 @@
+use anyhow::{anyhow, Error};
+use std::{collections::BTreeMap, str::FromStr};
+use structopt::StructOpt;
+
+use crate::{config, ledger};
+
+#[derive(StructOpt)]
+pub struct Opt {
+    #[structopt(subcommand)]
+    pub subcommand: Subcommand,
+}
+
+#[derive(StructOpt)]
+pub enum Subcommand {
+    Report(Report),
+}
+
+#[derive(StructOpt)]
+pub struct Report {
+    // How far back to look: `30m`, `24h`, `7d`, `2w`, or a date such as `2023-07-01`.
+    #[structopt(long, parse(try_from_str = parse_since))]
+    pub since: Option<chrono::DateTime<chrono::Utc>>,
+
+    // Groups the totals by `model`, `day` or `command`.
+    #[structopt(long, default_value = "model")]
+    pub by: By,
+
+    // Only counts calls made with this `--tag`.
+    #[structopt(long)]
+    pub tag: Option<String>,
+}
+
+pub enum By {
+    Model,
+    Day,
+    Command,
+}
+
+impl FromStr for By {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "model" => Ok(Self::Model),
+            "day" => Ok(Self::Day),
+            "command" => Ok(Self::Command),
+            _ => Err(anyhow!("expected model, day or command, got {}", s)),
+        }
+    }
+}
+
+#[derive(Default)]
+struct Total {
+    calls: usize,
+    prompt_tokens: usize,
+    completion_tokens: usize,
+    cost: f64,
+    unpriced: bool,
+    estimated: bool,
+}
+
+impl Total {
+    fn add(&mut self, entry: &ledger::Entry, price: Option<&config::Price>) {
+        self.calls += 1;
+        self.prompt_tokens += entry.prompt_tokens;
+        self.completion_tokens += entry.completion_tokens;
+        self.estimated |= entry.estimated;
+
+        match price {
+            Some(price) => self.cost += price.cost(entry.prompt_tokens, entry.completion_tokens),
+            None => self.unpriced |= entry.prompt_tokens + entry.completion_tokens > 0,
+        }
+    }
+
+    fn print(&self, key: &str) {
+        let tokens = self.prompt_tokens + self.completion_tokens;
+
+        println!(
+            "{:<24} {:>6} {:>10} {:>10} {:>10} {:>10}",
+            key,
+            self.calls,
+            self.prompt_tokens,
+            self.completion_tokens,
+            match self.estimated {
+                true => format!("~{}", tokens),
+                false => tokens.to_string(),
+            },
+            match self.unpriced {
+                true => format!("${:.4}*", self.cost),
+                false => format!("${:.4}", self.cost),
+            }
+        );
+    }
+}
+
+// Usage is read from the local ledger, so like `tokens` this needs no datasource.
+impl Opt {
+    pub fn run(&self) -> Result<(), Error> {
+        match &self.subcommand {
+            Subcommand::Report(opt) => report(opt),
+        }
+    }
+}
+
+fn report(opt: &Report) -> Result<(), Error> {
+    let prices = config::prices()?;
+    let mut groups: BTreeMap<String, Total> = BTreeMap::new();
+    let mut total = Total::default();
+
+    let entries = ledger::Ledger::new()?.entries()?;
+    let entries = entries.iter().filter(|entry| {
+        opt.since.is_none_or(|since| entry.timestamp >= since)
+            && opt.tag.as_ref().is_none_or(|tag| entry.tags.contains(tag))
+    });
+
+    for entry in entries {
+        let key = match opt.by {
+            By::Model => entry
+                .model
+                .clone()
+                .unwrap_or_else(|| entry.endpoint.clone()),
+            By::Day => entry
+                .timestamp
+                .with_timezone(&chrono::Local)
+                .format("%Y-%m-%d")
+                .to_string(),
+            By::Command => entry.command.clone(),
+        };
+        let price = entry.model.as_ref().and_then(|model| prices.get(model));
+
+        groups.entry(key).or_default().add(entry, price);
+        total.add(entry, price);
+    }
+
+    println!(
+        "{}",
+        console::Style::new().dim().apply_to(format!(
+            "{:<24} {:>6} {:>10} {:>10} {:>10} {:>10}",
+            match opt.by {
+                By::Model => "model",
+                By::Day => "day",
+                By::Command => "command",
+            },
+            "calls",
+            "prompt",
+            "completion",
+            "total",
+            "cost"
+        ))
+    );
+
+    for (key, group) in &groups {
+        group.print(key);
+    }
+
+    total.print("total");
+
+    if total.estimated {
+        println!("~ includes streamed replies counted locally");
+    }
+
+    if total.unpriced {
+        println!("* includes models missing from the price table");
+    }
+
+    Ok(())
+}
+
+fn parse_since(s: &str) -> Result<chrono::DateTime<chrono::Utc>, Error> {
+    if let Ok(date) = chrono::NaiveDate::parse_from_str(s, "%Y-%m-%d") {
+        return date
+            .and_hms_opt(0, 0, 0)
+            .and_then(|start| start.and_local_timezone(chrono::Local).earliest())
+            .map(|start| start.with_timezone(&chrono::Utc))
+            .ok_or_else(|| anyhow!("invalid date: {}", s));
+    }
+
+    let split = s.len() - s.chars().last().map_or(0, char::len_utf8);
+    let amount: i64 = s[..split]
+        .parse()
+        .map_err(|_| anyhow!("expected a duration such as 7d or a date, got {}", s))?;
+
+    let unit: i64 = match &s[split..] {
+        "m" => 60,
+        "h" => 60 * 60,
+        "d" => 24 * 60 * 60,
+        "w" => 7 * 24 * 60 * 60,
+        _ => {
+            return Err(anyhow!(
+                "expected a duration such as 7d or a date, got {}",
+                s
+            ))
+        }
+    };
+
+    // chrono's constructors panic past their range, so overflow is checked on the way.
+    amount
+        .checked_mul(unit * 1000)
+        .map(chrono::Duration::milliseconds)
+        .and_then(|duration| chrono::Utc::now().checked_sub_signed(duration))
+        .ok_or_else(|| anyhow!("duration out of range: {}", s))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_since_durations() {
+        let ago = |s| {
+            let since = parse_since(s).unwrap();
+            chrono::Utc::now() - since
+        };
+
+        assert_eq!(ago("90m").num_minutes(), 90);
+        assert_eq!(ago("2h").num_hours(), 2);
+        assert_eq!(ago("7d").num_days(), 7);
+        assert_eq!(ago("1w").num_days(), 7);
+    }
+
+    #[test]
+    fn parse_since_rejects_out_of_range_durations() {
+        assert!(parse_since("99999999999999d").is_err());
+        assert!(parse_since("9223372036854775807m").is_err());
+    }
+
+    #[test]
+    fn parse_since_rejects_garbage() {
+        assert!(parse_since("7").is_err());
+        assert!(parse_since("7y").is_err());
+        assert!(parse_since("").is_err());
+    }
+}
@@ -0,0 +1,443 @@
+use anyhow::{anyhow, Error};
+use openai_api::model::{create_chat, sampling};
+use serde::{Deserialize, Serialize};
+use std::{collections::HashMap, fs, path};
+
+use crate::config;
+
+#[derive(Deserialize, Serialize)]
+pub struct Session {
+    pub model: create_chat::Model,
+    pub max_tokens: Option<usize>,
+    pub temperature: Option<f32>,
+
+    #[serde(default)]
+    pub sampling: Sampling,
+
+    pub messages: Vec<create_chat::Message>,
+    pub turns: Vec<Turn>,
+    pub created: chrono::DateTime<chrono::Utc>,
+    pub updated: chrono::DateTime<chrono::Utc>,
+}
+
+// The rest of the request's sampling parameters. Sessions saved before these were kept
+// load with none of them set.
+#[derive(Clone, Default, Deserialize, Serialize)]
+pub struct Sampling {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub top_p: Option<f32>,
+
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub stop: Vec<String>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub presence_penalty: Option<f32>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub frequency_penalty: Option<f32>,
+
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub logit_bias: HashMap<usize, f32>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub n: Option<usize>,
+}
+
+impl Sampling {
+    // Keeps what was given and takes the rest from `saved`.
+    pub fn or(self, saved: Self) -> Self {
+        Self {
+            top_p: self.top_p.or(saved.top_p),
+            stop: match self.stop.is_empty() {
+                true => saved.stop,
+                false => self.stop,
+            },
+            presence_penalty: self.presence_penalty.or(saved.presence_penalty),
+            frequency_penalty: self.frequency_penalty.or(saved.frequency_penalty),
+            logit_bias: match self.logit_bias.is_empty() {
+                true => saved.logit_bias,
+                false => self.logit_bias,
+            },
+            n: self.n.or(saved.n),
+        }
+    }
+
+    // Replaces the request's parameters, so that ones left unset here are cleared. A
+    // saved session edited by hand can hold values the API would refuse.
+    pub fn apply(&self, request: &mut create_chat::Request) -> Result<(), Error> {
+        let top_p = sampling::top_p(self.top_p)?;
+        let stop = sampling::stop(self.stop.clone())?;
+        let presence_penalty = sampling::presence_penalty(self.presence_penalty)?;
+        let frequency_penalty = sampling::frequency_penalty(self.frequency_penalty)?;
+        let logit_bias = sampling::logit_bias(self.logit_bias.clone())?;
+
+        request.top_p = top_p;
+        request.stop = stop;
+        request.presence_penalty = presence_penalty;
+        request.frequency_penalty = frequency_penalty;
+        request.logit_bias = logit_bias;
+        request.n = self.n;
+
+        Ok(())
+    }
+}
+
+// `messages` is the length of the transcript once the turn completed. Streamed replies
+// carry no usage, so theirs is counted locally and marked estimated.
+#[derive(Deserialize, Serialize)]
+pub struct Turn {
+    pub messages: usize,
+    pub usage: Option<create_chat::Usage>,
+
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub estimated: bool,
+}
+
+impl Session {
+    pub fn new(
+        model: create_chat::Model,
+        max_tokens: Option<usize>,
+        temperature: Option<f32>,
+        sampling: Sampling,
+        messages: Vec<create_chat::Message>,
+    ) -> Self {
+        let now = chrono::Utc::now();
+
+        Self {
+            model,
+            max_tokens,
+            temperature,
+            sampling,
+            messages,
+            turns: vec![],
+            created: now,
+            updated: now,
+        }
+    }
+
+    pub fn total_tokens(&self) -> usize {
+        self.turns
+            .iter()
+            .filter_map(|turn| turn.usage.as_ref())
+            .map(|usage| usage.total_tokens)
+            .sum()
+    }
+
+    pub fn estimated(&self) -> bool {
+        self.turns.iter().any(|turn| turn.estimated)
+    }
+}
+
+pub struct Store {
+    directory: path::PathBuf,
+}
+
+impl Store {
+    pub fn new() -> Result<Self, Error> {
+        let directory = dirs::data_dir()
+            .ok_or_else(|| anyhow!("no data directory for this platform"))?
+            .join("openai-cli")
+            .join("sessions");
+
+        Ok(Self { directory })
+    }
+
+    fn path(&self, name: &str) -> Result<path::PathBuf, Error> {
+        config::check_name("session", name)?;
+
+        Ok(self.directory.join(format!("{}.json", name)))
+    }
+
+    pub fn load(&self, name: &str) -> Result<Option<Session>, Error> {
+        let path = self.path(name)?;
+
+        if !path.exists() {
+            return Ok(None);
+        }
+
+        let content = fs::read_to_string(&path)?;
+
+        Ok(Some(serde_json::from_str(&content)?))
+    }
+
+    // Writes through a temporary file so an interrupted save never truncates the transcript.
+    pub fn save(&self, name: &str, session: &Session) -> Result<(), Error> {
+        let path = self.path(name)?;
+        let temporary = path.with_extension("json.tmp");
+
+        fs::create_dir_all(&self.directory)?;
+        fs::write(&temporary, serde_json::to_string_pretty(session)?)?;
+        fs::rename(&temporary, &path)?;
+
+        Ok(())
+    }
+
+    pub fn list(&self) -> Result<Vec<(String, Session)>, Error> {
+        if !self.directory.exists() {
+            return Ok(vec![]);
+        }
+
+        let mut sessions = vec![];
+
+        for entry in fs::read_dir(&self.directory)? {
+            let path = entry?.path();
+
+            if path.extension().is_none_or(|extension| extension != "json") {
+                continue;
+            }
+
+            if let Some(name) = path.file_stem().and_then(|stem| stem.to_str()) {
+                if let Some(session) = self.load(name)? {
+                    sessions.push((name.to_string(), session));
+                }
+            }
+        }
+
+        sessions.sort_by_key(|(_, session)| std::cmp::Reverse(session.updated));
+
+        Ok(sessions)
+    }
+
+    pub fn delete(&self, name: &str) -> Result<(), Error> {
+        let path = self.path(name)?;
+
+        match path.exists() {
+            true => Ok(fs::remove_file(path)?),
+            false => Err(anyhow!("no session named {}", name)),
+        }
+    }
+
+    pub fn rename(&self, from: &str, to: &str) -> Result<(), Error> {
+        let source = self.path(from)?;
+        let destination = self.path(to)?;
+
+        if !source.exists() {
+            return Err(anyhow!("no session named {}", from));
+        }
+
+        if destination.exists() {
+            return Err(anyhow!("a session named {} already exists", to));
+        }
+
+        Ok(fs::rename(source, destination)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A store in a fresh directory, which is only created by the first save.
+    fn store(name: &str) -> Store {
+        let directory = std::env::temp_dir().join(format!(
+            "openai-cli-session-{}-{}",
+            name,
+            std::process::id()
+        ));
+        let _ = fs::remove_dir_all(&directory);
+
+        Store { directory }
+    }
+
+    fn session(content: &str) -> Session {
+        Session::new(
+            create_chat::Model::Gpt4,
+            Some(100),
+            Some(0.5),
+            Sampling {
+                top_p: Some(0.9),
+                stop: vec![String::from("END")],
+                logit_bias: HashMap::from([(50256, -100.0)]),
+                ..Sampling::default()
+            },
+            vec![create_chat::Message {
+                role: create_chat::Role::User,
+                content: Some(String::from(content)),
+                name: None,
+                function_call: None,
+            }],
+        )
+    }
+
+    fn turn(total_tokens: usize, estimated: bool) -> Turn {
+        Turn {
+            messages: 2,
+            usage: Some(create_chat::Usage {
+                prompt_tokens: total_tokens - 1,
+                completion_tokens: 1,
+                total_tokens,
+            }),
+            estimated,
+        }
+    }
+
+    #[test]
+    fn saved_session_loads_back() {
+        let store = store("round-trip");
+        let mut saved = session("Hi");
+        saved.turns.push(turn(12, true));
+
+        store.save("work", &saved).unwrap();
+        let loaded = store.load("work").unwrap().unwrap();
+
+        assert_eq!(loaded.messages, saved.messages);
+        assert_eq!(loaded.max_tokens, Some(100));
+        assert_eq!(loaded.temperature, Some(0.5));
+        assert_eq!(loaded.sampling.top_p, Some(0.9));
+        assert_eq!(loaded.sampling.stop, vec![String::from("END")]);
+        assert_eq!(loaded.sampling.logit_bias, HashMap::from([(50256, -100.0)]));
+        assert_eq!(loaded.total_tokens(), 12);
+        assert!(loaded.estimated());
+        assert_eq!(loaded.created, saved.created);
+        assert!(!store.directory.join("work.json.tmp").exists());
+    }
+
+    #[test]
+    fn missing_session_loads_as_none() {
+        assert!(store("missing").load("nothing").unwrap().is_none());
+    }
+
+    #[test]
+    fn older_sessions_load_with_defaults() {
+        let store = store("older");
+        fs::create_dir_all(&store.directory).unwrap();
+        fs::write(
+            store.directory.join("old.json"),
+            r#"{
+                "model": "gpt-4",
+                "max_tokens": null,
+                "temperature": null,
+                "messages": [],
+                "turns": [{"messages": 2, "usage": null}],
+                "created": "2023-07-01T00:00:00Z",
+                "updated": "2023-07-01T00:00:00Z"
+            }"#,
+        )
+        .unwrap();
+
+        let session = store.load("old").unwrap().unwrap();
+
+        assert!(session.sampling.top_p.is_none());
+        assert!(session.sampling.stop.is_empty());
+        assert_eq!(session.total_tokens(), 0);
+        assert!(!session.estimated());
+    }
+
+    #[test]
+    fn names_cannot_leave_the_directory() {
+        let store = store("names");
+
+        for name in ["", "../escape", ".hidden", "a/b"] {
+            assert!(store.load(name).is_err(), "{}", name);
+            assert!(store.save(name, &session("Hi")).is_err(), "{}", name);
+            assert!(store.delete(name).is_err(), "{}", name);
+        }
+
+        store.save("good", &session("Hi")).unwrap();
+
+        assert!(store.rename("good", "../escape").is_err());
+        assert!(store.load("good").unwrap().is_some());
+    }
+
+    #[test]
+    fn list_is_newest_first_and_skips_other_files() {
+        let store = store("list");
+        let older = session("first");
+        let mut newer = session("second");
+        newer.updated = older.updated + chrono::Duration::seconds(1);
+
+        store.save("older", &older).unwrap();
+        store.save("newer", &newer).unwrap();
+        fs::write(store.directory.join("notes.txt"), "not a session").unwrap();
+        fs::write(store.directory.join("partial.json.tmp"), "{").unwrap();
+
+        let names: Vec<String> = store
+            .list()
+            .unwrap()
+            .into_iter()
+            .map(|(name, _)| name)
+            .collect();
+
+        assert_eq!(names, ["newer", "older"]);
+    }
+
+    #[test]
+    fn list_without_a_directory_is_empty() {
+        assert!(store("list-empty").list().unwrap().is_empty());
+    }
+
+    #[test]
+    fn rename_and_delete() {
+        let store = store("rename");
+        store.save("a", &session("a")).unwrap();
+        store.save("b", &session("b")).unwrap();
+
+        assert!(store.rename("a", "b").is_err());
+        assert!(store.rename("missing", "c").is_err());
+
+        store.rename("a", "c").unwrap();
+
+        assert!(store.load("a").unwrap().is_none());
+        assert!(store.load("c").unwrap().is_some());
+
+        store.delete("c").unwrap();
+
+        assert!(store.load("c").unwrap().is_none());
+        assert!(store.delete("c").is_err());
+    }
+
+    #[test]
+    fn sampling_keeps_given_values_over_saved_ones() {
+        let given = Sampling {
+            top_p: Some(0.5),
+            ..Sampling::default()
+        };
+        let saved = Sampling {
+            top_p: Some(0.9),
+            stop: vec![String::from("END")],
+            n: Some(2),
+            ..Sampling::default()
+        };
+
+        let merged = given.or(saved);
+
+        assert_eq!(merged.top_p, Some(0.5));
+        assert_eq!(merged.stop, vec![String::from("END")]);
+        assert_eq!(merged.n, Some(2));
+    }
+
+    #[test]
+    fn sampling_apply_clears_unset_values() {
+        let mut request = create_chat::Request::new(create_chat::Model::Gpt4, vec![]);
+        request.presence_penalty = Some(1.0);
+        request.stop = Some(vec![String::from("old")]);
+
+        Sampling {
+            top_p: Some(0.5),
+            ..Sampling::default()
+        }
+        .apply(&mut request)
+        .unwrap();
+
+        assert_eq!(request.top_p, Some(0.5));
+        assert_eq!(request.presence_penalty, None);
+        assert_eq!(request.stop, None);
+        assert_eq!(request.logit_bias, None);
+    }
+
+    #[test]
+    fn sampling_apply_rejects_hand_edited_values() {
+        let mut request = create_chat::Request::new(create_chat::Model::Gpt4, vec![]);
+        request.top_p = Some(0.5);
+
+        let result = Sampling {
+            top_p: Some(0.9),
+            logit_bias: HashMap::from([(50256, -200.0)]),
+            ..Sampling::default()
+        }
+        .apply(&mut request);
+
+        assert!(result.is_err());
+        assert_eq!(request.top_p, Some(0.5));
+    }
+}
@@ -0,0 +1,268 @@
+use anyhow::{anyhow, Error};
+use openai_api::model::{create_chat, function};
+use serde::Deserialize;
+use std::{fs, io, path, process, time};
+use tokio::io::AsyncWriteExt;
+
+// Seconds a command may run when its entry sets no `timeout`.
+const DEFAULT_TIMEOUT: u64 = 60;
+
+// A functions file declares each function the model may call next to the command that
+// answers it. JSON is valid YAML, so either format loads.
+#[derive(Deserialize)]
+pub struct Tools {
+    functions: Vec<Tool>,
+}
+
+#[derive(Deserialize)]
+struct Tool {
+    #[serde(flatten)]
+    function: function::Function,
+    command: Vec<String>,
+
+    // Seconds before the command is killed and the model told it timed out.
+    #[serde(default)]
+    timeout: Option<u64>,
+}
+
+impl Tools {
+    pub fn load(path: &path::Path) -> Result<Self, Error> {
+        let content = fs::read_to_string(path)
+            .map_err(|error| anyhow!("functions {}: {}", path.display(), error))?;
+        let mut tools: Self = serde_yaml::from_str(&content)
+            .map_err(|error| anyhow!("functions {}: {}", path.display(), error))?;
+
+        // Relative commands are resolved against the functions file, not the working directory.
+        let directory = path.parent().unwrap_or(path::Path::new("."));
+
+        for tool in tools.functions.iter_mut() {
+            tool.function
+                .validate()
+                .map_err(|error| anyhow!("functions {}: {}", path.display(), error))?;
+
+            let program = tool.command.first_mut().ok_or_else(|| {
+                anyhow!(
+                    "functions {}: {} has no command",
+                    path.display(),
+                    tool.function.name
+                )
+            })?;
+
+            if program.contains('/') && path::Path::new(program).is_relative() {
+                *program = directory.join(&program).to_string_lossy().into_owned();
+            }
+        }
+
+        Ok(tools)
+    }
+
+    pub fn functions(&self) -> Vec<function::Function> {
+        self.functions
+            .iter()
+            .map(|tool| tool.function.clone())
+            .collect()
+    }
+
+    // Runs the mapped command with the JSON arguments on stdin and returns what the model
+    // should see. Failures are reported back to the model rather than ending the chat.
+    pub async fn call(
+        &self,
+        function_call: &create_chat::FunctionCall,
+        auto_approve: bool,
+    ) -> Result<String, Error> {
+        let tool = match self
+            .functions
+            .iter()
+            .find(|tool| tool.function.name == function_call.name())
+        {
+            Some(tool) => tool,
+            None => return Ok(format!("error: unknown function {}", function_call.name())),
+        };
+
+        let arguments =
+            serde_json::to_string(&function_call.arguments().cloned().unwrap_or_default())?;
+
+        if !auto_approve && !confirm(&tool.command, function_call.name(), &arguments)? {
+            return Ok(String::from(
+                "error: the user declined to run this function",
+            ));
+        }
+
+        let program = &tool.command[0];
+        let mut child = match tokio::process::Command::new(program)
+            .args(&tool.command[1..])
+            .env("OPENAI_FUNCTION_NAME", function_call.name())
+            .stdin(process::Stdio::piped())
+            .stdout(process::Stdio::piped())
+            .stderr(process::Stdio::piped())
+            .kill_on_drop(true)
+            .spawn()
+        {
+            Ok(child) => child,
+            Err(error) => return Ok(format!("error: {}: {}", program, error)),
+        };
+
+        // Written alongside reading the output, so a command that fills its stdout pipe
+        // before draining stdin cannot block the two on each other. One that exits without
+        // reading its arguments at all closes the pipe, which is not a failure of the call.
+        let stdin = child.stdin.take();
+        let writer = tokio::spawn(async move {
+            match stdin {
+                Some(mut stdin) => match stdin.write_all(arguments.as_bytes()).await {
+                    Err(error) if error.kind() != io::ErrorKind::BrokenPipe => Err(error),
+                    _ => Ok(()),
+                },
+                None => Ok(()),
+            }
+        });
+
+        let timeout = time::Duration::from_secs(tool.timeout.unwrap_or(DEFAULT_TIMEOUT));
+
+        // Timing out drops the child, which kills it.
+        let output = match tokio::time::timeout(timeout, child.wait_with_output()).await {
+            Ok(Ok(output)) => output,
+            Ok(Err(error)) => return Ok(format!("error: {}: {}", program, error)),
+            Err(_) => {
+                return Ok(format!(
+                    "error: {} timed out after {}s",
+                    program,
+                    timeout.as_secs()
+                ))
+            }
+        };
+
+        if let Ok(Err(error)) = writer.await {
+            return Ok(format!(
+                "error: writing arguments to {}: {}",
+                program, error
+            ));
+        }
+
+        let stdout = String::from_utf8_lossy(&output.stdout);
+
+        match output.status.success() {
+            true => Ok(stdout.into_owned()),
+            false => Ok(format!(
+                "error: {} exited with {}\n{}{}",
+                program,
+                output.status,
+                stdout,
+                String::from_utf8_lossy(&output.stderr)
+            )),
+        }
+    }
+}
+
+fn confirm(command: &[String], name: &str, arguments: &str) -> Result<bool, Error> {
+    eprint!(
+        "{} ",
+        console::Style::new().yellow().apply_to(format!(
+            "Run {} {} with `{}`? [y/N]",
+            name,
+            arguments,
+            command.join(" ")
+        ))
+    );
+
+    let mut answer = String::new();
+    io::stdin().read_line(&mut answer)?;
+
+    Ok(matches!(answer.trim(), "y" | "Y" | "yes"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tools(command: &[&str], timeout: Option<u64>) -> Tools {
+        let tools = serde_json::json!({
+            "functions": [{
+                "name": "run",
+                "description": "Runs the command",
+                "parameters": {"type": "object", "properties": {}},
+                "command": command,
+                "timeout": timeout,
+            }],
+        });
+
+        serde_json::from_value(tools).unwrap()
+    }
+
+    fn call(arguments: &str) -> create_chat::FunctionCall {
+        create_chat::FunctionCall::new(String::from("run"), arguments).unwrap()
+    }
+
+    #[tokio::test]
+    async fn arguments_arrive_on_stdin() {
+        let output = tools(&["cat"], None)
+            .call(&call(r#"{"city": "Paris"}"#), true)
+            .await
+            .unwrap();
+
+        assert_eq!(output, r#"{"city":"Paris"}"#);
+    }
+
+    #[tokio::test]
+    async fn command_may_ignore_its_arguments() {
+        let arguments = serde_json::json!({ "text": "x".repeat(1 << 20) }).to_string();
+
+        let output = tools(&["echo", "done"], None)
+            .call(&call(&arguments), true)
+            .await
+            .unwrap();
+
+        assert_eq!(output, "done\n");
+    }
+
+    #[tokio::test]
+    async fn large_output_and_input_do_not_deadlock() {
+        let arguments = serde_json::json!({ "text": "x".repeat(1 << 20) }).to_string();
+
+        let output = tools(
+            &["sh", "-c", "head -c 1000000 /dev/zero; cat >/dev/null"],
+            Some(10),
+        )
+        .call(&call(&arguments), true)
+        .await
+        .unwrap();
+
+        assert_eq!(output.len(), 1_000_000);
+    }
+
+    #[tokio::test]
+    async fn failures_are_reported_to_the_model() {
+        let missing = tools(&["/nonexistent/tool"], None)
+            .call(&call("{}"), true)
+            .await
+            .unwrap();
+        let failed = tools(&["sh", "-c", "echo oops >&2; exit 3"], None)
+            .call(&call("{}"), true)
+            .await
+            .unwrap();
+        let unknown = tools(&["cat"], None)
+            .call(
+                &create_chat::FunctionCall::new(String::from("other"), "{}").unwrap(),
+                true,
+            )
+            .await
+            .unwrap();
+
+        assert!(missing.starts_with("error: /nonexistent/tool: "));
+        assert!(failed.starts_with("error: sh exited with"));
+        assert!(failed.ends_with("oops\n"));
+        assert_eq!(unknown, "error: unknown function other");
+    }
+
+    #[tokio::test]
+    async fn slow_command_times_out() {
+        let started = time::Instant::now();
+
+        let output = tools(&["sleep", "30"], Some(1))
+            .call(&call("{}"), true)
+            .await
+            .unwrap();
+
+        assert_eq!(output, "error: sleep timed out after 1s");
+        assert!(started.elapsed() < time::Duration::from_secs(10));
+    }
+}
@@ -0,0 +1,203 @@
+use async_trait::async_trait;
+use openai_api::{error, model, ApiResponse, Datasource, EventStream, ResponseMeta};
+use std::sync;
+
+pub struct Verbose {
+    inner: sync::Arc<dyn Datasource + Send + Sync>,
+}
+
+impl Verbose {
+    pub fn new(inner: sync::Arc<dyn Datasource + Send + Sync>) -> Self {
+        Self { inner }
+    }
+}
+
+fn print<T>(response: ApiResponse<T>) -> ApiResponse<T> {
+    let meta = &response.meta;
+    let style = console::Style::new().dim();
+    let mut fields = vec![];
+
+    if let Some(request_id) = &meta.request_id {
+        fields.push(format!("request id: {}", request_id));
+    }
+
+    if let Some(model) = &meta.model {
+        fields.push(format!("model: {}", model));
+    }
+
+    if let Some(organization) = &meta.organization {
+        fields.push(format!("organization: {}", organization));
+    }
+
+    fields.push(format!("latency: {}ms", meta.latency.as_millis()));
+
+    if let Some(processing) = meta.processing {
+        fields.push(format!("processing: {}ms", processing.as_millis()));
+    }
+
+    fields.extend(rate_limit(meta));
+
+    eprintln!("{}", style.apply_to(fields.join(" | ")));
+
+    response
+}
+
+fn rate_limit(meta: &ResponseMeta) -> Vec<String> {
+    let rate_limit = &meta.rate_limit;
+    let mut fields = vec![];
+
+    if let Some(remaining) = rate_limit.remaining_requests {
+        let limit = rate_limit
+            .limit_requests
+            .map(|limit| format!("/{}", limit))
+            .unwrap_or_default();
+        fields.push(format!("requests remaining: {}{}", remaining, limit));
+    }
+
+    if let Some(remaining) = rate_limit.remaining_tokens {
+        let limit = rate_limit
+            .limit_tokens
+            .map(|limit| format!("/{}", limit))
+            .unwrap_or_default();
+        fields.push(format!("tokens remaining: {}{}", remaining, limit));
+    }
+
+    if let Some(reset) = rate_limit.reset_requests {
+        fields.push(format!("requests reset: {:?}", reset));
+    }
+
+    if let Some(reset) = rate_limit.reset_tokens {
+        fields.push(format!("tokens reset: {:?}", reset));
+    }
+
+    fields
+}
+
+#[async_trait]
+impl Datasource for Verbose {
+    async fn list_models(&self) -> Result<model::list_models::Response, error::Error> {
+        Ok(self.list_models_with_meta().await?.body)
+    }
+
+    async fn create_completion(
+        &self,
+        request: &model::create_completion::Request,
+    ) -> Result<model::create_completion::Response, error::Error> {
+        Ok(self.create_completion_with_meta(request).await?.body)
+    }
+
+    async fn create_completion_stream(
+        &self,
+        request: &model::create_completion::Request,
+    ) -> Result<EventStream<model::create_completion::Choice>, error::Error> {
+        Ok(self.create_completion_stream_with_meta(request).await?.body)
+    }
+
+    async fn create_chat(
+        &self,
+        request: &model::create_chat::Request,
+    ) -> Result<model::create_chat::Response, error::Error> {
+        Ok(self.create_chat_with_meta(request).await?.body)
+    }
+
+    async fn create_chat_stream(
+        &self,
+        request: &model::create_chat::Request,
+    ) -> Result<EventStream<model::create_chat::Chunk>, error::Error> {
+        Ok(self.create_chat_stream_with_meta(request).await?.body)
+    }
+
+    async fn create_image(
+        &self,
+        request: &model::create_image::Request,
+    ) -> Result<model::create_image::Response, error::Error> {
+        Ok(self.create_image_with_meta(request).await?.body)
+    }
+
+    async fn create_edit(
+        &self,
+        request: &model::create_edit::Request,
+    ) -> Result<model::create_edit::Response, error::Error> {
+        Ok(self.create_edit_with_meta(request).await?.body)
+    }
+
+    async fn list_files(&self) -> Result<model::list_files::Response, error::Error> {
+        Ok(self.list_files_with_meta().await?.body)
+    }
+
+    async fn create_embedding(
+        &self,
+        request: &model::create_embedding::Request,
+    ) -> Result<model::create_embedding::Response, error::Error> {
+        Ok(self.create_embedding_with_meta(request).await?.body)
+    }
+
+    async fn list_models_with_meta(
+        &self,
+    ) -> Result<ApiResponse<model::list_models::Response>, error::Error> {
+        Ok(print(self.inner.list_models_with_meta().await?))
+    }
+
+    async fn create_completion_with_meta(
+        &self,
+        request: &model::create_completion::Request,
+    ) -> Result<ApiResponse<model::create_completion::Response>, error::Error> {
+        Ok(print(
+            self.inner.create_completion_with_meta(request).await?,
+        ))
+    }
+
+    async fn create_completion_stream_with_meta(
+        &self,
+        request: &model::create_completion::Request,
+    ) -> Result<ApiResponse<EventStream<model::create_completion::Choice>>, error::Error> {
+        Ok(print(
+            self.inner
+                .create_completion_stream_with_meta(request)
+                .await?,
+        ))
+    }
+
+    async fn create_chat_with_meta(
+        &self,
+        request: &model::create_chat::Request,
+    ) -> Result<ApiResponse<model::create_chat::Response>, error::Error> {
+        Ok(print(self.inner.create_chat_with_meta(request).await?))
+    }
+
+    async fn create_chat_stream_with_meta(
+        &self,
+        request: &model::create_chat::Request,
+    ) -> Result<ApiResponse<EventStream<model::create_chat::Chunk>>, error::Error> {
+        Ok(print(
+            self.inner.create_chat_stream_with_meta(request).await?,
+        ))
+    }
+
+    async fn create_image_with_meta(
+        &self,
+        request: &model::create_image::Request,
+    ) -> Result<ApiResponse<model::create_image::Response>, error::Error> {
+        Ok(print(self.inner.create_image_with_meta(request).await?))
+    }
+
+    async fn create_edit_with_meta(
+        &self,
+        request: &model::create_edit::Request,
+    ) -> Result<ApiResponse<model::create_edit::Response>, error::Error> {
+        Ok(print(self.inner.create_edit_with_meta(request).await?))
+    }
+
+    async fn list_files_with_meta(
+        &self,
+    ) -> Result<ApiResponse<model::list_files::Response>, error::Error> {
+        Ok(print(self.inner.list_files_with_meta().await?))
+    }
+
+    async fn create_embedding_with_meta(
+        &self,
+        request: &model::create_embedding::Request,
+    ) -> Result<ApiResponse<model::create_embedding::Response>, error::Error> {
+        Ok(print(self.inner.create_embedding_with_meta(request).await?))
+    }
+}